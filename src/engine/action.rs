@@ -0,0 +1,189 @@
+use std::path::PathBuf;
+
+use winit::{
+    event::{ElementState, KeyEvent, WindowEvent},
+    keyboard::{KeyCode, ModifiersState, PhysicalKey},
+};
+
+use super::{camera::View, gui::InputCapture};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Action {
+    DecreasePointSize,
+    IncreasePointSize,
+    ToggleSizeMode,
+    CycleColorMode,
+    ToggleNormalization,
+    FitView,
+    ResetView,
+    SetView(View),
+    ToggleNavigationMode,
+    CopyShareLink,
+    OpenFile,
+    ToggleFrameStats,
+    ToggleGui,
+    LoadFile(PathBuf),
+    /// Loads a file next to the clouds already shown.
+    AddFile(PathBuf),
+    Clear,
+    Reload,
+}
+
+impl Action {
+    /// Maps a raw window event to an engine action. Returns `None` for events
+    /// that are not bound to an action, or that egui has claimed, so they can
+    /// fall through to the camera. Files dropped with Shift held are added
+    /// rather than replacing what is loaded.
+    pub fn from_event(
+        event: &WindowEvent,
+        capture: InputCapture,
+        modifiers: ModifiersState,
+    ) -> Option<Self> {
+        match event {
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(keycode),
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } => Self::from_key_press(*keycode, capture, modifiers),
+            WindowEvent::DroppedFile(path) if modifiers.shift_key() => {
+                Some(Action::AddFile(path.clone()))
+            }
+            WindowEvent::DroppedFile(path) => Some(Action::LoadFile(path.clone())),
+            _ => None,
+        }
+    }
+
+    /// The action bound to `keycode`, unless egui has the keyboard focus.
+    /// Ctrl+R reloads; R on its own resets the view.
+    fn from_key_press(
+        keycode: KeyCode,
+        capture: InputCapture,
+        modifiers: ModifiersState,
+    ) -> Option<Self> {
+        if capture.keyboard {
+            return None;
+        }
+
+        if keycode == KeyCode::KeyR && modifiers.control_key() {
+            return Some(Action::Reload);
+        }
+        Self::from_key(keycode)
+    }
+
+    fn from_key(keycode: KeyCode) -> Option<Self> {
+        match keycode {
+            KeyCode::KeyJ => Some(Action::DecreasePointSize),
+            KeyCode::KeyK => Some(Action::IncreasePointSize),
+            KeyCode::KeyM => Some(Action::ToggleSizeMode),
+            KeyCode::KeyC => Some(Action::CycleColorMode),
+            KeyCode::KeyN => Some(Action::ToggleNormalization),
+            KeyCode::KeyF => Some(Action::FitView),
+            KeyCode::KeyR => Some(Action::ResetView),
+            KeyCode::KeyG => Some(Action::ToggleNavigationMode),
+            KeyCode::KeyL => Some(Action::CopyShareLink),
+            KeyCode::KeyO => Some(Action::OpenFile),
+            KeyCode::F3 => Some(Action::ToggleFrameStats),
+            KeyCode::KeyH | KeyCode::F1 => Some(Action::ToggleGui),
+            KeyCode::Delete => Some(Action::Clear),
+            KeyCode::F5 => Some(Action::Reload),
+            KeyCode::Digit1 | KeyCode::Numpad1 => Some(Action::SetView(View::Front)),
+            KeyCode::Digit3 | KeyCode::Numpad3 => Some(Action::SetView(View::Side)),
+            KeyCode::Digit7 | KeyCode::Numpad7 | KeyCode::KeyB => Some(Action::SetView(View::Top)),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn j_decreases_the_point_size_unless_egui_wants_the_keyboard() {
+        assert_eq!(
+            Action::from_key_press(
+                KeyCode::KeyJ,
+                InputCapture::default(),
+                ModifiersState::empty()
+            ),
+            Some(Action::DecreasePointSize)
+        );
+
+        let typing = InputCapture {
+            keyboard: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            Action::from_key_press(KeyCode::KeyJ, typing, ModifiersState::empty()),
+            None
+        );
+    }
+
+    #[test]
+    fn ctrl_r_reloads_and_r_resets_the_view() {
+        let capture = InputCapture::default();
+
+        assert_eq!(
+            Action::from_key_press(KeyCode::KeyR, capture, ModifiersState::CONTROL),
+            Some(Action::Reload)
+        );
+        assert_eq!(
+            Action::from_key_press(KeyCode::KeyR, capture, ModifiersState::empty()),
+            Some(Action::ResetView)
+        );
+        assert_eq!(
+            Action::from_key_press(KeyCode::F5, capture, ModifiersState::empty()),
+            Some(Action::Reload)
+        );
+    }
+
+    #[test]
+    fn dropped_files_load_or_add_with_shift() {
+        let path = PathBuf::from("scan.pcd");
+        let event = WindowEvent::DroppedFile(path.clone());
+
+        assert_eq!(
+            Action::from_event(&event, InputCapture::default(), ModifiersState::empty()),
+            Some(Action::LoadFile(path.clone()))
+        );
+        assert_eq!(
+            Action::from_event(&event, InputCapture::default(), ModifiersState::SHIFT),
+            Some(Action::AddFile(path))
+        );
+    }
+
+    #[test]
+    fn unbound_input_falls_through_to_the_camera() {
+        for keycode in [
+            KeyCode::KeyW,
+            KeyCode::KeyA,
+            KeyCode::KeyS,
+            KeyCode::KeyD,
+            KeyCode::KeyQ,
+            KeyCode::KeyE,
+            KeyCode::ArrowUp,
+            KeyCode::Space,
+            KeyCode::KeyZ,
+        ] {
+            assert_eq!(
+                Action::from_key_press(keycode, InputCapture::default(), ModifiersState::empty()),
+                None,
+                "{:?}",
+                keycode
+            );
+        }
+
+        assert_eq!(
+            Action::from_event(
+                &WindowEvent::Focused(true),
+                InputCapture::default(),
+                ModifiersState::empty()
+            ),
+            None
+        );
+    }
+}
@@ -0,0 +1,139 @@
+use pollster::FutureExt;
+use wgpu::{
+    Adapter, AdapterInfo, Backends, Instance, InstanceDescriptor, PowerPreference,
+    RequestAdapterOptions, Surface,
+};
+
+/// Graphics APIs that can be forced instead of letting wgpu try the primary
+/// ones.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Backend {
+    Vulkan,
+    Dx12,
+    Metal,
+    Gl,
+}
+
+impl Backend {
+    pub const ALL: [Backend; 4] = [Backend::Vulkan, Backend::Dx12, Backend::Metal, Backend::Gl];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Backend::Vulkan => "vulkan",
+            Backend::Dx12 => "dx12",
+            Backend::Metal => "metal",
+            Backend::Gl => "gl",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|backend| backend.name() == name)
+    }
+
+    fn backends(self) -> Backends {
+        match self {
+            Backend::Vulkan => Backends::VULKAN,
+            Backend::Dx12 => Backends::DX12,
+            Backend::Metal => Backends::METAL,
+            Backend::Gl => Backends::GL,
+        }
+    }
+}
+
+/// Which GPU to render with.
+#[derive(Clone, Debug, Default)]
+pub struct AdapterOptions {
+    /// Only this API instead of the primary ones.
+    pub backend: Option<Backend>,
+    /// An index into [`list_adapters`], or part of an adapter's name.
+    pub adapter: Option<String>,
+    /// Prefer a discrete GPU to an integrated one.
+    pub high_performance: bool,
+}
+
+impl AdapterOptions {
+    fn backends(&self) -> Backends {
+        self.backend.map_or(Backends::PRIMARY, Backend::backends)
+    }
+
+    pub fn create_instance(&self) -> Instance {
+        Instance::new(InstanceDescriptor {
+            backends: self.backends(),
+            ..Default::default()
+        })
+    }
+
+    /// The adapter asked for by `adapter` if it can present to `surface`,
+    /// otherwise the one wgpu prefers. The message says why an adapter that
+    /// was asked for is not used.
+    pub fn request_adapter(
+        &self,
+        instance: &Instance,
+        surface: &Surface,
+    ) -> (Adapter, Option<String>) {
+        let mut message = None;
+
+        if let Some(wanted) = &self.adapter {
+            match find_adapter(instance.enumerate_adapters(self.backends()), wanted) {
+                Some(adapter) if adapter.is_surface_supported(surface) => return (adapter, None),
+                Some(adapter) => {
+                    message = Some(format!(
+                        "{} cannot draw to this window; using the default adapter",
+                        adapter.get_info().name
+                    ))
+                }
+                None => {
+                    message = Some(format!(
+                        "No adapter matches \"{}\"; using the default one",
+                        wanted
+                    ))
+                }
+            }
+        }
+
+        let power_preference = if self.high_performance {
+            PowerPreference::HighPerformance
+        } else {
+            PowerPreference::default()
+        };
+        let adapter = instance
+            .request_adapter(&RequestAdapterOptions {
+                power_preference,
+                compatible_surface: Some(surface),
+                force_fallback_adapter: false,
+            })
+            .block_on()
+            .expect("no graphics adapter can draw to the window");
+
+        (adapter, message)
+    }
+}
+
+/// By index if `wanted` is a number, otherwise by a case-insensitive part of
+/// the name.
+fn find_adapter(adapters: Vec<Adapter>, wanted: &str) -> Option<Adapter> {
+    if let Ok(index) = wanted.parse::<usize>() {
+        return adapters.into_iter().nth(index);
+    }
+
+    let wanted = wanted.to_lowercase();
+    adapters
+        .into_iter()
+        .find(|adapter| adapter.get_info().name.to_lowercase().contains(&wanted))
+}
+
+/// The adapters of the options' backends, in the order `adapter` indexes
+/// them.
+pub fn list_adapters(options: &AdapterOptions) -> Vec<AdapterInfo> {
+    options
+        .create_instance()
+        .enumerate_adapters(options.backends())
+        .iter()
+        .map(Adapter::get_info)
+        .collect()
+}
+
+/// Name, backend and kind of an adapter, for the log and the stats overlay.
+pub fn describe(info: &AdapterInfo) -> String {
+    format!("{} ({}, {:?})", info.name, info.backend, info.device_type)
+}
@@ -1,17 +1,89 @@
-use cgmath::{Deg, InnerSpace, Matrix4, Point3, Vector3};
+use std::time::Duration;
+
+use cgmath::{
+    Angle, Deg, EuclideanSpace, InnerSpace, Matrix4, Point3, Quaternion, Rad, Rotation, Rotation3,
+    SquareMatrix, Vector3, Vector4,
+};
+use serde::{Deserialize, Serialize};
 use winit::{
     event::{ElementState, KeyEvent, MouseButton, MouseScrollDelta, WindowEvent},
     keyboard::{KeyCode, PhysicalKey},
     window::Window,
 };
 
-#[rustfmt::skip]
-const OPENGL_TO_WGPU_MATRIX: cgmath::Matrix4<f32> = cgmath::Matrix4::new(
-    1.0, 0.0, 0.0, 0.0,
-    0.0, 1.0, 0.0, 0.0,
-    0.0, 0.0, 0.5, 0.5,
-    0.0, 0.0, 0.0, 1.0,
-);
+/// Orbit-mode movement speed, in target distances per second.
+const ORBIT_SPEED: f32 = 0.5;
+
+/// Smallest angle kept between the view direction and the up vector, so the
+/// view matrix stays well defined when looking straight down or up.
+const MIN_POLAR_ANGLE: f32 = 1e-3;
+
+/// How mouse and keyboard input move the camera.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NavigationMode {
+    /// Right-drag orbits the eye around the target.
+    Orbit,
+    /// Right-drag turns the view around the eye; the target rides along a
+    /// fixed distance ahead, which is where orbiting resumes.
+    Fly,
+}
+
+impl NavigationMode {
+    pub fn toggled(self) -> Self {
+        match self {
+            NavigationMode::Orbit => NavigationMode::Fly,
+            NavigationMode::Fly => NavigationMode::Orbit,
+        }
+    }
+}
+
+/// User-tunable response of the camera to mouse input.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Sensitivity {
+    /// Multiplier on the orbit and look rate, where 1 turns a full circle
+    /// over a drag across the viewport width.
+    pub rotate_speed: f32,
+    /// Fraction of the distance to the target covered per wheel line.
+    pub zoom_speed: f32,
+    /// Multiplier on the pan rate, where 1 keeps the point under the cursor
+    /// at the target's depth following the cursor.
+    pub pan_speed: f32,
+    pub invert_x: bool,
+    pub invert_y: bool,
+}
+
+impl Default for Sensitivity {
+    fn default() -> Self {
+        Self {
+            rotate_speed: 1.0,
+            zoom_speed: 0.1,
+            pan_speed: 1.0,
+            invert_x: false,
+            invert_y: false,
+        }
+    }
+}
+
+/// Canonical views, each looking at the current target.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum View {
+    /// Looking along +y.
+    Front,
+    /// Looking along -x.
+    Side,
+    /// Looking down -z.
+    Top,
+}
+
+#[derive(Clone, Copy)]
+struct Pose {
+    eye: Point3<f32>,
+    target: Point3<f32>,
+    up: Vector3<f32>,
+    znear: f32,
+    zfar: f32,
+}
 
 pub struct Camera {
     eye: Point3<f32>,
@@ -21,12 +93,28 @@ pub struct Camera {
     fovy: f32,
     znear: f32,
     zfar: f32,
+    home: Pose,
+    reversed_z: bool,
+    /// Leave out the far plane; only used with reversed-Z, where precision
+    /// holds up all the way.
+    infinite_far: bool,
+    navigation_mode: NavigationMode,
+    fly_speed: f32,
+    sensitivity: Sensitivity,
 
+    cursor_position: Option<(f32, f32)>,
     mouse_right_position: Option<(f32, f32)>,
+    mouse_middle_position: Option<(f32, f32)>,
     is_left_pressed: bool,
     is_right_pressed: bool,
     is_up_pressed: bool,
     is_down_pressed: bool,
+    is_ascend_pressed: bool,
+    is_descend_pressed: bool,
+    /// Ctrl descends too, unless it is held as part of a chord.
+    is_control_pressed: bool,
+    is_control_chord: bool,
+    is_shift_pressed: bool,
 }
 
 impl Camera {
@@ -37,19 +125,41 @@ impl Camera {
         aspect: f32,
         fovy: f32,
     ) -> Self {
+        let znear = 0.01;
+        let zfar = 100.0;
+
         Self {
             eye,
             target,
             up,
             aspect,
             fovy,
-            znear: 0.01,
-            zfar: 100.0,
+            znear,
+            zfar,
+            home: Pose {
+                eye,
+                target,
+                up,
+                znear,
+                zfar,
+            },
+            reversed_z: true,
+            infinite_far: false,
+            navigation_mode: NavigationMode::Orbit,
+            fly_speed: 1.0,
+            sensitivity: Sensitivity::default(),
+            cursor_position: None,
             mouse_right_position: None,
+            mouse_middle_position: None,
             is_left_pressed: false,
             is_right_pressed: false,
             is_up_pressed: false,
             is_down_pressed: false,
+            is_ascend_pressed: false,
+            is_descend_pressed: false,
+            is_control_pressed: false,
+            is_control_chord: false,
+            is_shift_pressed: false,
         }
     }
 
@@ -63,24 +173,60 @@ impl Camera {
                 ElementState::Pressed => self.mouse_right_position = Some((0.0, 0.0)),
                 ElementState::Released => self.mouse_right_position = None,
             },
+            WindowEvent::MouseInput {
+                state,
+                button: MouseButton::Middle,
+                ..
+            } => match state {
+                ElementState::Pressed => self.mouse_middle_position = Some((0.0, 0.0)),
+                ElementState::Released => self.mouse_middle_position = None,
+            },
             WindowEvent::MouseWheel { delta, .. } => match delta {
-                MouseScrollDelta::LineDelta(_x, y) => {
-                    self.camera_zoom(*y);
-                }
+                MouseScrollDelta::LineDelta(_x, y) => match self.navigation_mode {
+                    NavigationMode::Orbit => {
+                        let size = window.inner_size();
+                        self.camera_zoom(*y, size.width as f32, size.height as f32);
+                    }
+                    NavigationMode::Fly => self.fly_speed *= 1.1_f32.powf(*y),
+                },
                 _ => return false,
             },
             WindowEvent::CursorMoved { position, .. } => {
-                if let Some((x, y)) = self.mouse_right_position {
+                self.cursor_position = Some((position.x as f32, position.y as f32));
+                if let Some((x, y)) = self.mouse_middle_position {
                     let logical_position = position.to_logical(window.scale_factor());
-                    if x == 0.0 && y == 0.0 {
-                        self.mouse_right_position = Some((logical_position.x, logical_position.y));
-                    } else {
-                        self.camera_rotate(logical_position.x - x, logical_position.y - y);
+                    self.mouse_middle_position = Some((logical_position.x, logical_position.y));
+                    if x != 0.0 || y != 0.0 {
+                        let viewport = window.inner_size().to_logical(window.scale_factor());
+                        self.camera_pan(
+                            logical_position.x - x,
+                            logical_position.y - y,
+                            viewport.height,
+                        );
+                    }
+                } else if let Some((x, y)) = self.mouse_right_position {
+                    let logical_position = position.to_logical(window.scale_factor());
+                    self.mouse_right_position = Some((logical_position.x, logical_position.y));
+                    if x != 0.0 || y != 0.0 {
+                        let viewport = window.inner_size().to_logical(window.scale_factor());
+                        let (delta_x, delta_y) = (logical_position.x - x, logical_position.y - y);
+                        match self.navigation_mode {
+                            NavigationMode::Orbit => {
+                                self.camera_rotate(delta_x, delta_y, viewport.width)
+                            }
+                            NavigationMode::Fly => {
+                                self.camera_look(delta_x, delta_y, viewport.width)
+                            }
+                        }
                     }
                 } else {
                     return false;
                 }
             }
+            WindowEvent::ModifiersChanged(modifiers) => {
+                self.is_shift_pressed = modifiers.state().shift_key();
+                return false;
+            }
             WindowEvent::KeyboardInput {
                 event:
                     KeyEvent {
@@ -89,16 +235,11 @@ impl Camera {
                         ..
                     },
                 ..
-            } => {
-                let is_pressed = *state == ElementState::Pressed;
-                match keycode {
-                    KeyCode::KeyW | KeyCode::ArrowUp => self.is_up_pressed = is_pressed,
-                    KeyCode::KeyS | KeyCode::ArrowDown => self.is_down_pressed = is_pressed,
-                    KeyCode::KeyA | KeyCode::ArrowLeft => self.is_left_pressed = is_pressed,
-                    KeyCode::KeyD | KeyCode::ArrowRight => self.is_right_pressed = is_pressed,
-                    KeyCode::KeyB => self.set_birdeye(),
-                    _ => return false,
-                }
+            } => return self.process_key(*keycode, *state),
+            // The releases of keys held while switching away never arrive.
+            WindowEvent::Focused(false) => {
+                self.release_keys();
+                return false;
             }
             _ => return false,
         }
@@ -106,53 +247,698 @@ impl Camera {
         true
     }
 
+    /// Tracks the movement keys; `false` for any other key.
+    fn process_key(&mut self, keycode: KeyCode, state: ElementState) -> bool {
+        let is_pressed = state == ElementState::Pressed;
+        match keycode {
+            KeyCode::KeyW | KeyCode::ArrowUp => self.is_up_pressed = is_pressed,
+            KeyCode::KeyS | KeyCode::ArrowDown => self.is_down_pressed = is_pressed,
+            KeyCode::KeyA | KeyCode::ArrowLeft => self.is_left_pressed = is_pressed,
+            KeyCode::KeyD | KeyCode::ArrowRight => self.is_right_pressed = is_pressed,
+            KeyCode::KeyE | KeyCode::Space => self.is_ascend_pressed = is_pressed,
+            KeyCode::KeyQ => self.is_descend_pressed = is_pressed,
+            KeyCode::ControlLeft | KeyCode::ControlRight => {
+                self.is_control_pressed = is_pressed;
+                self.is_control_chord = false;
+            }
+            _ => {
+                if is_pressed {
+                    self.chord_pressed();
+                }
+                return false;
+            }
+        }
+        true
+    }
+
+    /// A key other than Ctrl went down. A held Ctrl is then a modifier of
+    /// that chord, and stops descending until it is pressed again.
+    pub fn chord_pressed(&mut self) {
+        self.is_control_chord = self.is_control_pressed;
+    }
+
+    fn is_descending(&self) -> bool {
+        self.is_descend_pressed || (self.is_control_pressed && !self.is_control_chord)
+    }
+
+    /// Stops movement from keys whose release may not reach the camera,
+    /// because the window lost focus or egui took the keyboard.
+    pub fn release_keys(&mut self) {
+        self.is_up_pressed = false;
+        self.is_down_pressed = false;
+        self.is_left_pressed = false;
+        self.is_right_pressed = false;
+        self.is_ascend_pressed = false;
+        self.is_descend_pressed = false;
+        self.is_control_pressed = false;
+        self.is_control_chord = false;
+    }
+
+    /// Whether held keys will move the camera on the next update.
+    pub fn is_moving(&self) -> bool {
+        let vertical = match self.navigation_mode {
+            NavigationMode::Orbit => false,
+            NavigationMode::Fly => self.is_ascend_pressed || self.is_descending(),
+        };
+        self.is_up_pressed
+            || self.is_down_pressed
+            || self.is_left_pressed
+            || self.is_right_pressed
+            || vertical
+    }
+
+    pub fn reversed_z(&self) -> bool {
+        self.reversed_z
+    }
+
+    /// Must match the depth test of every pipeline drawing with this camera.
+    pub fn set_reversed_z(&mut self, reversed_z: bool) {
+        self.reversed_z = reversed_z;
+    }
+
+    pub fn infinite_far(&self) -> bool {
+        self.infinite_far
+    }
+
+    /// Has no effect without reversed-Z.
+    pub fn set_infinite_far(&mut self, infinite_far: bool) {
+        self.infinite_far = infinite_far;
+    }
+
+    /// Coefficients `[a, b]` with `1 / distance = a * depth + b`, turning a
+    /// value from the depth buffer back into distance along the view axis.
+    pub fn depth_linearization(&self) -> [f32; 2] {
+        let (near, far) = (self.znear, self.zfar);
+        let slope = (far - near) / (near * far);
+
+        match (self.reversed_z, self.infinite_far) {
+            (true, true) => [1.0 / near, 0.0],
+            (true, false) => [slope, 1.0 / far],
+            (false, _) => [-slope, 1.0 / near],
+        }
+    }
+
+    /// Depth buffer value of a point `distance` in front of the eye.
+    pub fn depth_at(&self, distance: f32) -> f32 {
+        let clip = self.projection() * Vector4::new(0.0, 0.0, -distance, 1.0);
+        clip.z / clip.w
+    }
+
+    pub fn sensitivity(&self) -> Sensitivity {
+        self.sensitivity
+    }
+
+    pub fn set_sensitivity(&mut self, sensitivity: Sensitivity) {
+        self.sensitivity = sensitivity;
+    }
+
+    pub fn navigation_mode(&self) -> NavigationMode {
+        self.navigation_mode
+    }
+
+    /// Switching to fly mode starts at the speed orbit movement had at the
+    /// current distance; switching back orbits around the point the target
+    /// has been carried to, a fixed distance in front of the eye.
+    pub fn set_navigation_mode(&mut self, mode: NavigationMode) {
+        if mode == NavigationMode::Fly && self.navigation_mode == NavigationMode::Orbit {
+            self.fly_speed = ORBIT_SPEED * (self.target - self.eye).magnitude();
+        }
+
+        self.navigation_mode = mode;
+    }
+
+    /// Moves eye and target together while the movement keys are held. In
+    /// orbit mode speed is proportional to the distance to the target, so a
+    /// scene crosses the screen in about the same time at any zoom level; in
+    /// fly mode it is the wheel-adjusted fly speed and Q/E move vertically.
+    pub fn update(&mut self, dt: Duration) {
+        const SHIFT_MULTIPLIER: f32 = 4.0;
+
+        let axis = |positive: bool, negative: bool| positive as i32 as f32 - negative as i32 as f32;
+        let forward_amount = axis(self.is_up_pressed, self.is_down_pressed);
+        let right_amount = axis(self.is_right_pressed, self.is_left_pressed);
+        let up_amount = match self.navigation_mode {
+            NavigationMode::Orbit => 0.0,
+            NavigationMode::Fly => axis(self.is_ascend_pressed, self.is_descending()),
+        };
+        if forward_amount == 0.0 && right_amount == 0.0 && up_amount == 0.0 {
+            return;
+        }
+
+        let forward = self.target - self.eye;
+        let forward_norm = forward.normalize();
+        let right = forward_norm.cross(self.up).normalize();
+
+        let mut speed = match self.navigation_mode {
+            NavigationMode::Orbit => ORBIT_SPEED * forward.magnitude(),
+            NavigationMode::Fly => self.fly_speed,
+        };
+        if self.is_shift_pressed {
+            speed *= SHIFT_MULTIPLIER;
+        }
+
+        let direction = (forward_norm * forward_amount
+            + right * right_amount
+            + self.up.normalize() * up_amount)
+            .normalize();
+        let offset = direction * speed * dt.as_secs_f32();
+
+        self.eye += offset;
+        self.target += offset;
+    }
+
     pub fn get_view_proj(&self) -> [[f32; 4]; 4] {
         self.build_view_projection_matrix().into()
     }
 
+    /// Vertical focal scale of the projection, i.e. `proj[1][1]`.
+    pub fn get_proj_scale(&self) -> f32 {
+        1.0 / (Deg(self.fovy) / 2.0).tan()
+    }
+
     pub fn set_aspect(&mut self, aspect: f32) {
         self.aspect = aspect;
     }
 
+    /// Sizes the clip planes to a sphere of `radius` around `center` as seen
+    /// from the current eye. Outside the sphere the near plane hugs its front;
+    /// inside it falls back to a fraction of the distance to the target.
+    pub fn fit_clip_planes(&mut self, center: Point3<f32>, radius: f32) {
+        let distance = (center - self.eye).magnitude();
+        let target_distance = (self.target - self.eye).magnitude();
+
+        self.znear = (distance - radius).max(target_distance * 1e-3);
+        self.zfar = (distance + 2.0 * radius).max(self.znear * 2.0);
+    }
+
+    pub fn eye(&self) -> Point3<f32> {
+        self.eye
+    }
+
+    pub fn target(&self) -> Point3<f32> {
+        self.target
+    }
+
+    pub fn look_at(&mut self, eye: Point3<f32>, target: Point3<f32>) {
+        self.eye = eye;
+        self.target = target;
+    }
+
+    /// Moves the camera and its home pose by `offset`, for when the space it
+    /// looks into has moved.
+    pub fn translate(&mut self, offset: Vector3<f32>) {
+        self.eye += offset;
+        self.target += offset;
+        self.home.eye += offset;
+        self.home.target += offset;
+    }
+
+    /// Remembers the current pose as the one [`Camera::reset`] returns to.
+    pub fn set_home(&mut self) {
+        self.home = Pose {
+            eye: self.eye,
+            target: self.target,
+            up: self.up,
+            znear: self.znear,
+            zfar: self.zfar,
+        };
+    }
+
+    pub fn reset(&mut self) {
+        let home = self.home;
+        self.eye = home.eye;
+        self.target = home.target;
+        self.up = home.up;
+        self.znear = home.znear;
+        self.zfar = home.zfar;
+    }
+
+    /// Snaps the eye to a canonical view of the target, keeping the current
+    /// eye-target distance. The top view is tilted by a hair so the view
+    /// matrix stays defined.
+    pub fn set_view(&mut self, view: View) {
+        let direction = match view {
+            View::Front => -Vector3::unit_y(),
+            View::Side => Vector3::unit_x(),
+            View::Top => {
+                let (sin, cos) = MIN_POLAR_ANGLE.sin_cos();
+                Vector3::unit_z() * cos - Vector3::unit_y() * sin
+            }
+        };
+
+        let distance = (self.eye - self.target).magnitude();
+        self.up = Vector3::unit_z();
+        self.eye = self.target + direction * distance;
+    }
+
+    /// Moves the eye along the current viewing direction so a sphere of
+    /// `radius` around `center` fits both the vertical and horizontal field of
+    /// view with a small margin, and sizes the clip planes to it.
+    pub fn fit_to_bounds(&mut self, center: Point3<f32>, radius: f32, aspect: f32) {
+        const MARGIN: f32 = 1.1;
+        const MIN_RADIUS: f32 = 1e-3;
+
+        let radius = radius.max(MIN_RADIUS);
+
+        let half_fovy = Rad::from(Deg(self.fovy / 2.0));
+        let half_fovx = Rad::atan(half_fovy.tan() * aspect);
+        let half_fov = if half_fovx < half_fovy {
+            half_fovx
+        } else {
+            half_fovy
+        };
+
+        let offset = self.eye - self.target;
+        let direction = if offset.magnitude2() > 0.0 {
+            offset.normalize()
+        } else {
+            Vector3::new(1.0, 1.0, 1.0).normalize()
+        };
+        let distance = radius * MARGIN / half_fov.sin();
+
+        self.aspect = aspect;
+        self.target = center;
+        self.eye = center + direction * distance;
+        self.fit_clip_planes(center, radius);
+    }
+
     fn build_view_projection_matrix(&self) -> Matrix4<f32> {
         let view = Matrix4::look_at_rh(self.eye, self.target, self.up);
-        let proj = cgmath::perspective(Deg(self.fovy), self.aspect, self.znear, self.zfar);
+        self.projection() * view
+    }
+
+    /// Right-handed perspective projection onto wgpu's depth range of zero
+    /// to one. Reversed-Z puts the near plane at one and the far plane (or
+    /// infinity) at zero, which spreads float precision evenly over distance.
+    #[rustfmt::skip]
+    fn projection(&self) -> Matrix4<f32> {
+        let focal = self.get_proj_scale();
+        let (near, far) = (self.znear, self.zfar);
+
+        // Depth is `(scale * z + offset) / -z` for a view-space `z`.
+        let (scale, offset) = match (self.reversed_z, self.infinite_far) {
+            (true, true) => (0.0, near),
+            (true, false) => (near / (far - near), near * far / (far - near)),
+            (false, _) => (far / (near - far), near * far / (near - far)),
+        };
 
-        OPENGL_TO_WGPU_MATRIX * proj * view
+        // Column by column.
+        Matrix4::new(
+            focal / self.aspect, 0.0, 0.0, 0.0,
+            0.0, focal, 0.0, 0.0,
+            0.0, 0.0, scale, -1.0,
+            0.0, 0.0, offset, 0.0,
+        )
     }
 
-    fn camera_rotate(&mut self, delta_x: f32, delta_y: f32) {
+    /// Orbits the eye around the target by the cursor movement since the last
+    /// event. A drag across the full viewport width turns a full circle.
+    fn camera_rotate(&mut self, delta_x: f32, delta_y: f32, viewport_width: f32) {
+        let offset = self.eye - self.target;
+        self.eye = self.target + self.turn(offset, delta_x, delta_y, viewport_width);
+    }
+
+    /// Turns the view direction around the eye, at the same rate as orbiting.
+    fn camera_look(&mut self, delta_x: f32, delta_y: f32, viewport_width: f32) {
+        let offset = self.target - self.eye;
+        self.target = self.eye + self.turn(offset, delta_x, delta_y, viewport_width);
+    }
+
+    /// Yaws `offset` about the up vector and pitches it about the view's right
+    /// axis, keeping it just short of the poles.
+    fn turn(
+        &self,
+        offset: Vector3<f32>,
+        delta_x: f32,
+        delta_y: f32,
+        viewport_width: f32,
+    ) -> Vector3<f32> {
+        let scale = self.sensitivity.rotate_speed * std::f32::consts::TAU / viewport_width;
+        let delta_x = if self.sensitivity.invert_x {
+            -delta_x
+        } else {
+            delta_x
+        };
+        let delta_y = if self.sensitivity.invert_y {
+            -delta_y
+        } else {
+            delta_y
+        };
+
+        let up = self.up.normalize();
+        let right = (-offset).cross(up).normalize();
+
+        let polar = offset.angle(up).0;
+        let new_polar = (polar + delta_y * scale)
+            .clamp(MIN_POLAR_ANGLE, std::f32::consts::PI - MIN_POLAR_ANGLE);
+
+        let yaw = Quaternion::from_axis_angle(up, Rad(-delta_x * scale));
+        let pitch = Quaternion::from_axis_angle(right, Rad(new_polar - polar));
+
+        (yaw * pitch).rotate_vector(offset)
+    }
+
+    /// Translates eye and target together so the point under the cursor at
+    /// the target's depth follows the cursor.
+    fn camera_pan(&mut self, delta_x: f32, delta_y: f32, viewport_height: f32) {
         let forward = self.target - self.eye;
         let forward_norm = forward.normalize();
-        let forward_mag = forward.magnitude();
 
         let right = forward_norm.cross(self.up).normalize();
-        let up = self.up.normalize();
+        let up = right.cross(forward_norm);
 
-        let scale = 0.0001;
+        let scale =
+            self.sensitivity.pan_speed * 2.0 * forward.magnitude() * (Deg(self.fovy) / 2.0).tan()
+                / viewport_height;
 
-        let eye = self.target
-            - (forward + right * delta_x * scale + up * delta_y * scale).normalize() * forward_mag;
+        let offset = (up * delta_y - right * delta_x) * scale;
 
-        if eye.z > 0.0 {
-            self.eye = eye;
+        self.eye += offset;
+        self.target += offset;
+    }
+
+    /// Maps a point in normalized device coordinates (x and y in -1..1, z in
+    /// 0..1) back to world space.
+    fn unproject(&self, ndc: Vector3<f32>) -> Option<Point3<f32>> {
+        let inverse = self.build_view_projection_matrix().invert()?;
+        let world = inverse * Vector4::new(ndc.x, ndc.y, ndc.z, 1.0);
+
+        Some(Point3::from_vec(world.truncate() / world.w))
+    }
+
+    /// World-space direction of the ray from the eye through the cursor, or
+    /// the view direction when the cursor position is unknown.
+    ///
+    /// The ray goes through the cursor on the near plane, which unprojects to
+    /// a finite point under every depth convention; the far plane may be at
+    /// infinity.
+    fn cursor_ray(&self, viewport_width: f32, viewport_height: f32) -> Vector3<f32> {
+        let forward = (self.target - self.eye).normalize();
+
+        let Some((x, y)) = self.cursor_position else {
+            return forward;
+        };
+
+        let ndc_x = 2.0 * x / viewport_width - 1.0;
+        let ndc_y = 1.0 - 2.0 * y / viewport_height;
+        let near_depth = self.depth_at(self.znear);
+
+        match self.unproject(Vector3::new(ndc_x, ndc_y, near_depth)) {
+            Some(near) => {
+                let ray = (near - self.eye).normalize();
+                if ray.x.is_finite() && ray.y.is_finite() && ray.z.is_finite() {
+                    ray
+                } else {
+                    forward
+                }
+            }
+            None => forward,
         }
     }
 
-    fn camera_zoom(&mut self, y: f32) {
+    /// Moves the eye along the ray under the cursor. The target shifts with it
+    /// so the view direction is kept and the distance to the target shrinks
+    /// exactly as a zoom along the view axis would.
+    fn camera_zoom(&mut self, y: f32, viewport_width: f32, viewport_height: f32) {
         let forward = self.target - self.eye;
         let forward_norm = forward.normalize();
 
-        let scale = 0.01;
+        let step = y * self.sensitivity.zoom_speed * forward.magnitude();
+
+        if step < forward.magnitude() {
+            let ray = self.cursor_ray(viewport_width, viewport_height);
+            let ray = ray / ray.dot(forward_norm);
+
+            self.eye += ray * step;
+            self.target += (ray - forward_norm) * step;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{InnerSpace, Matrix4, Point3, Vector3, Zero};
+    use std::time::Duration;
+
+    use wgpu::CompareFunction;
+    use winit::{event::ElementState, keyboard::KeyCode};
 
-        let eye = self.eye + forward_norm * y * scale;
+    use super::{Camera, NavigationMode};
+    use crate::engine::texture::Texture;
 
-        if eye.z > 0.0 {
-            self.eye = eye;
+    fn camera(reversed_z: bool, infinite_far: bool) -> Camera {
+        let mut camera = Camera::new(
+            Point3::new(0.0, -10.0, 0.0),
+            Point3::new(0.0, 0.0, 0.0),
+            Vector3::unit_z(),
+            1.5,
+            45.0,
+        );
+        camera.set_reversed_z(reversed_z);
+        camera.set_infinite_far(infinite_far);
+        camera
+    }
+
+    /// Where `point` lands in normalized device coordinates.
+    fn ndc(camera: &Camera, point: Point3<f32>) -> [f32; 3] {
+        let clip = Matrix4::from(camera.get_view_proj()) * point.to_homogeneous();
+        [clip.x / clip.w, clip.y / clip.w, clip.z / clip.w]
+    }
+
+    /// Points spread over the surface of a sphere, including its extremes
+    /// along every axis.
+    fn sphere_points(center: Point3<f32>, radius: f32) -> Vec<Point3<f32>> {
+        let steps = [-1.0, 0.0, 1.0];
+        steps
+            .iter()
+            .flat_map(|&x| {
+                steps
+                    .iter()
+                    .flat_map(move |&y| steps.map(|z| Vector3::new(x, y, z)))
+            })
+            .filter(|direction| direction.magnitude2() > 0.0)
+            .map(|direction| center + direction.normalize() * radius)
+            .collect()
+    }
+
+    /// Whether a fragment at `depth` is kept over one at `stored`.
+    fn passes(compare: CompareFunction, depth: f32, stored: f32) -> bool {
+        match compare {
+            CompareFunction::Greater => depth > stored,
+            CompareFunction::Less => depth < stored,
+            _ => unreachable!(),
         }
     }
 
-    fn set_birdeye(&mut self) {
-        self.camera_rotate(0.0, -1e8);
+    #[test]
+    fn nearer_points_win_the_depth_test() {
+        for (reversed_z, infinite_far) in [(false, false), (true, false), (true, true)] {
+            let camera = camera(reversed_z, infinite_far);
+            let compare = Texture::depth_compare(reversed_z);
+            let clear = Texture::depth_clear_value(reversed_z);
+            let (near, far) = (camera.depth_at(1.0), camera.depth_at(50.0));
+
+            assert!((0.0..=1.0).contains(&near) && (0.0..=1.0).contains(&far));
+            assert!(
+                passes(compare, near, far) && !passes(compare, far, near),
+                "reversed_z {reversed_z}, infinite_far {infinite_far}: {near} vs {far}"
+            );
+            assert!(passes(compare, far, clear));
+        }
+    }
+
+    #[test]
+    fn clip_planes_hug_the_bounding_sphere() {
+        for (reversed_z, infinite_far) in [(false, false), (true, false), (true, true)] {
+            let mut camera = camera(reversed_z, infinite_far);
+            let center = Point3::new(0.0, 0.0, 0.0);
+            camera.fit_clip_planes(center, 2.0);
+
+            // The eye is 10 away: the sphere spans depths 8 to 12.
+            assert_eq!(camera.znear, 8.0);
+            assert!(camera.zfar >= 12.0);
+
+            for point in sphere_points(center, 2.0 * 0.999) {
+                let [_, _, depth] = ndc(&camera, point);
+                assert!((0.0..=1.0).contains(&depth), "{:?} at {}", point, depth);
+            }
+        }
+    }
+
+    #[test]
+    fn clip_planes_stay_positive_inside_the_sphere() {
+        let mut camera = camera(false, false);
+        // The eye is 1 from the center of a sphere of radius 5.
+        let center = Point3::new(0.0, -9.0, 0.0);
+        camera.fit_clip_planes(center, 5.0);
+
+        assert!(camera.znear > 0.0 && camera.znear < 0.1);
+        assert!(camera.zfar >= 6.0);
+    }
+
+    #[test]
+    fn a_fitted_sphere_is_in_view() {
+        for aspect in [0.5, 1.0, 2.0] {
+            let mut camera = camera(true, false);
+            let center = Point3::new(3.0, 4.0, 5.0);
+            camera.fit_to_bounds(center, 2.0, aspect);
+
+            for point in sphere_points(center, 2.0) {
+                let [x, y, depth] = ndc(&camera, point);
+                assert!(
+                    x.abs() <= 1.0 && y.abs() <= 1.0,
+                    "aspect {}: {:?}",
+                    aspect,
+                    point
+                );
+                assert!((0.0..=1.0).contains(&depth));
+            }
+        }
+    }
+
+    #[test]
+    fn depth_maps_the_clip_planes_to_the_ends_of_the_range() {
+        let standard = camera(false, false);
+        assert!(standard.depth_at(standard.znear).abs() < 1e-6);
+        assert!((standard.depth_at(standard.zfar) - 1.0).abs() < 1e-6);
+
+        let reversed = camera(true, false);
+        assert!((reversed.depth_at(reversed.znear) - 1.0).abs() < 1e-6);
+        assert!(reversed.depth_at(reversed.zfar).abs() < 1e-6);
+
+        let infinite = camera(true, true);
+        assert!((infinite.depth_at(infinite.znear) - 1.0).abs() < 1e-6);
+        assert!(infinite.depth_at(1e30) < 1e-6);
+    }
+
+    #[test]
+    fn reversed_z_resolves_distant_points() {
+        // A hundred points a millimetre apart, 90 m away with the near plane
+        // at 1 cm.
+        let distinct_depths = |camera: &Camera| {
+            let mut depths: Vec<_> = (0..100)
+                .map(|step| camera.depth_at(90.0 + step as f32 * 1e-3).to_bits())
+                .collect();
+            depths.dedup();
+            depths.len()
+        };
+
+        assert_eq!(distinct_depths(&camera(true, false)), 100);
+        assert_eq!(distinct_depths(&camera(true, true)), 100);
+        assert!(distinct_depths(&camera(false, false)) < 10);
+    }
+
+    /// A world-unit point size in pixels, as `pixel_size` in the point
+    /// shader computes it from the uniforms.
+    fn world_size_in_pixels(camera: &Camera, size: f32, clip_w: f32, height: f32) -> f32 {
+        size * camera.get_proj_scale() * height * 0.5 / clip_w
+    }
+
+    #[test]
+    fn world_point_sizes_match_the_projection() {
+        let (width, height) = (1200.0, 800.0);
+        let size = 0.25;
+
+        for distance in [0.5, 3.0, 40.0] {
+            let mut camera = camera(true, false);
+            camera.set_aspect(width / height);
+            camera.look_at(Point3::new(0.0, -distance, 0.0), Point3::new(0.0, 0.0, 0.0));
+
+            let clip_w = (Matrix4::from(camera.get_view_proj()) * Vector3::zero().extend(1.0)).w;
+            let expected = world_size_in_pixels(&camera, size, clip_w, height);
+
+            // A segment of the point's size across the view, both ways.
+            let [_, bottom, _] = ndc(&camera, Point3::new(0.0, 0.0, -size / 2.0));
+            let [_, top, _] = ndc(&camera, Point3::new(0.0, 0.0, size / 2.0));
+            let [left, _, _] = ndc(&camera, Point3::new(-size / 2.0, 0.0, 0.0));
+            let [right, _, _] = ndc(&camera, Point3::new(size / 2.0, 0.0, 0.0));
+
+            assert!((clip_w - distance).abs() < distance * 1e-5);
+            for pixels in [(top - bottom) * height / 2.0, (right - left) * width / 2.0] {
+                assert!(
+                    (pixels - expected).abs() < expected * 1e-3,
+                    "{} pixels, expected {} at {}",
+                    pixels,
+                    expected,
+                    distance
+                );
+            }
+
+            // The shader's sprite radius in world units inverts it.
+            let radius = expected * clip_w / (camera.get_proj_scale() * height);
+            assert!((radius - size / 2.0).abs() < size * 1e-5);
+        }
+    }
+
+    #[test]
+    fn depth_linearization_inverts_depth() {
+        for (reversed_z, infinite_far) in [(false, false), (true, false), (true, true)] {
+            let camera = camera(reversed_z, infinite_far);
+            let [a, b] = camera.depth_linearization();
+
+            for distance in [0.5, 3.0, 40.0] {
+                let inverse = a * camera.depth_at(distance) + b;
+                assert!((1.0 / inverse - distance).abs() < distance * 1e-3);
+            }
+        }
+    }
+
+    #[test]
+    fn cursor_zoom_stays_finite_with_an_infinite_far_plane() {
+        for (reversed_z, infinite_far) in [(false, false), (true, false), (true, true)] {
+            let mut camera = camera(reversed_z, infinite_far);
+            camera.cursor_position = Some((600.0, 150.0));
+
+            let ray = camera.cursor_ray(800.0, 600.0);
+            assert!((ray.magnitude() - 1.0).abs() < 1e-4);
+            // Right of and above the center of the view, which looks along +y.
+            assert!(ray.x > 0.0 && ray.y > 0.0 && ray.z > 0.0);
+
+            camera.camera_zoom(1.0, 800.0, 600.0);
+            let (eye, target) = (camera.eye(), camera.target());
+            assert!([eye.x, eye.y, eye.z, target.x, target.y, target.z]
+                .iter()
+                .all(|v| v.is_finite()));
+        }
+    }
+
+    #[test]
+    fn released_keys_stop_the_camera() {
+        let mut camera = camera(true, false);
+        camera.set_navigation_mode(NavigationMode::Fly);
+
+        for keycode in [KeyCode::KeyW, KeyCode::KeyD, KeyCode::KeyQ] {
+            assert!(camera.process_key(keycode, ElementState::Pressed));
+        }
+        assert!(camera.is_moving());
+        camera.process_key(KeyCode::KeyW, ElementState::Released);
+        assert!(camera.is_moving());
+
+        // Focus lost or egui took the keyboard with the keys still down.
+        camera.release_keys();
+        assert!(!camera.is_moving());
+
+        let eye = camera.eye();
+        camera.update(Duration::from_millis(100));
+        assert_eq!(camera.eye(), eye);
+    }
+
+    #[test]
+    fn control_descends_unless_part_of_a_chord() {
+        let mut camera = camera(true, false);
+        camera.set_navigation_mode(NavigationMode::Fly);
+
+        camera.process_key(KeyCode::ControlLeft, ElementState::Pressed);
+        assert!(camera.is_moving());
+
+        // Ctrl+R: the R never reaches the camera as a movement key.
+        assert!(!camera.process_key(KeyCode::KeyR, ElementState::Pressed));
+        assert!(!camera.is_moving());
+        camera.process_key(KeyCode::ControlLeft, ElementState::Released);
+
+        camera.process_key(KeyCode::ControlLeft, ElementState::Pressed);
+        assert!(camera.is_moving());
+        camera.chord_pressed();
+        assert!(!camera.is_moving());
     }
 }
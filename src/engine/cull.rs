@@ -0,0 +1,374 @@
+use std::{
+    mem,
+    ops::Range,
+    sync::mpsc::{self, Receiver, TryRecvError},
+};
+
+use bytemuck::{Pod, Zeroable};
+use wgpu::{
+    BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry, BindingType, Buffer, BufferAddress, BufferAsyncError, BufferBindingType,
+    BufferDescriptor, BufferUsages, CommandEncoder, ComputePass, ComputePipeline,
+    ComputePipelineDescriptor, Device, MapMode, PipelineCompilationOptions,
+    PipelineLayoutDescriptor, Queue, ShaderModuleDescriptor, ShaderSource, ShaderStages,
+};
+
+/// Must match `WORKGROUP_SIZE` in cull.wgsl.
+const WORKGROUP_SIZE: u32 = 256;
+
+#[repr(C)]
+#[derive(Clone, Copy, Zeroable, Pod)]
+struct Params {
+    model_view_proj: [[f32; 4]; 4],
+    margin: [f32; 2],
+    range_count: u32,
+    total: u32,
+    groups_x: u32,
+    _padding: [u32; 3],
+}
+
+/// The compute pipeline that drops instances outside the view frustum, and
+/// the readback of how many survived for the stats panel.
+pub struct Culler {
+    pipeline: ComputePipeline,
+    layout: BindGroupLayout,
+    readback: Buffer,
+    state: Readback,
+}
+
+/// Where the copy of the surviving instance counts is on its way back to the
+/// CPU. Only one is in flight at a time, so the count shown lags a frame or
+/// two behind.
+enum Readback {
+    Idle,
+    /// Copied into the readback buffer by an encoder not yet known to have
+    /// been submitted.
+    Copied(usize),
+    Mapping(usize, Receiver<Result<(), BufferAsyncError>>),
+}
+
+impl Culler {
+    pub fn new(device: &Device) -> Self {
+        let shader = device.create_shader_module(ShaderModuleDescriptor {
+            label: Some("cull_shader"),
+            source: ShaderSource::Wgsl(include_str!("shaders/cull.wgsl").into()),
+        });
+
+        let storage = |binding, read_only| BindGroupLayoutEntry {
+            binding,
+            visibility: ShaderStages::COMPUTE,
+            ty: BindingType::Buffer {
+                ty: BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+
+        let layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("cull_bind_group_layout"),
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                storage(1, true),
+                storage(2, true),
+                storage(3, false),
+                storage(4, false),
+            ],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("cull_pipeline_layout"),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_compute_pipeline(&ComputePipelineDescriptor {
+            label: Some("cull_pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &shader,
+            entry_point: "cull",
+            compilation_options: PipelineCompilationOptions::default(),
+            cache: None,
+        });
+
+        Self {
+            pipeline,
+            layout,
+            readback: Self::create_readback(0, device),
+            state: Readback::Idle,
+        }
+    }
+
+    fn create_readback(slots: usize, device: &Device) -> Buffer {
+        device.create_buffer(&BufferDescriptor {
+            label: Some("cull_readback_buffer"),
+            size: (slots * mem::size_of::<u32>()) as BufferAddress,
+            usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    pub fn begin_pass<'a>(&'a self, encoder: &'a mut CommandEncoder) -> ComputePass<'a> {
+        let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("cull_pass"),
+            timestamp_writes: None,
+        });
+        pass.set_pipeline(&self.pipeline);
+        pass
+    }
+
+    /// Copies the instance count of each chunk's draw arguments for reading
+    /// back, unless an earlier copy is still on its way.
+    pub fn copy_counts<'a>(
+        &mut self,
+        chunks: impl Iterator<Item = &'a CullChunk>,
+        encoder: &mut CommandEncoder,
+        device: &Device,
+    ) {
+        if !matches!(self.state, Readback::Idle) {
+            return;
+        }
+
+        let chunks: Vec<&CullChunk> = chunks.collect();
+        let size = (chunks.len() * mem::size_of::<u32>()) as BufferAddress;
+        if size > self.readback.size() {
+            self.readback = Self::create_readback(chunks.len(), device);
+        }
+
+        for (slot, chunk) in chunks.iter().enumerate() {
+            // `instance_count` is the second field of the arguments.
+            encoder.copy_buffer_to_buffer(
+                &chunk.args,
+                4,
+                &self.readback,
+                (slot * mem::size_of::<u32>()) as BufferAddress,
+                4,
+            );
+        }
+
+        self.state = Readback::Copied(chunks.len());
+    }
+
+    /// Whether a count is on its way back.
+    pub fn is_pending(&self) -> bool {
+        !matches!(self.state, Readback::Idle)
+    }
+
+    /// Moves the readback along; returns the total count of surviving
+    /// instances once one arrives. Called after the frame with the copy has
+    /// been submitted.
+    pub fn poll_count(&mut self) -> Option<usize> {
+        match mem::replace(&mut self.state, Readback::Idle) {
+            Readback::Idle => None,
+            Readback::Copied(0) => Some(0),
+            Readback::Copied(slots) => {
+                let (sender, receiver) = mpsc::channel();
+                let size = (slots * mem::size_of::<u32>()) as BufferAddress;
+                self.readback
+                    .slice(..size)
+                    .map_async(MapMode::Read, move |result| {
+                        let _ = sender.send(result);
+                    });
+                self.state = Readback::Mapping(slots, receiver);
+                None
+            }
+            Readback::Mapping(slots, receiver) => match receiver.try_recv() {
+                Ok(Ok(())) => {
+                    let size = (slots * mem::size_of::<u32>()) as BufferAddress;
+                    let count = {
+                        let data = self.readback.slice(..size).get_mapped_range();
+                        bytemuck::cast_slice::<u8, u32>(&data)
+                            .iter()
+                            .map(|&count| count as usize)
+                            .sum()
+                    };
+                    self.readback.unmap();
+                    Some(count)
+                }
+                Ok(Err(e)) => {
+                    log::warn!("cannot read back the culled point count: {}", e);
+                    None
+                }
+                Err(TryRecvError::Empty) => {
+                    self.state = Readback::Mapping(slots, receiver);
+                    None
+                }
+                Err(TryRecvError::Disconnected) => None,
+            },
+        }
+    }
+}
+
+/// Culling buffers for one chunk of a cloud's instances.
+pub struct CullChunk {
+    params: Buffer,
+    ranges: Buffer,
+    /// The surviving instances, drawn in place of the chunk's own buffer.
+    culled: Buffer,
+    /// Indirect draw arguments; the pass fills in the instance count.
+    args: Buffer,
+    /// Dropped whenever a buffer it binds is replaced.
+    bind_group: Option<BindGroup>,
+    groups: [u32; 2],
+}
+
+impl CullChunk {
+    pub fn new(device: &Device) -> Self {
+        Self {
+            params: device.create_buffer(&BufferDescriptor {
+                label: Some("cull_params_buffer"),
+                size: mem::size_of::<Params>() as BufferAddress,
+                usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }),
+            ranges: Self::create_ranges(1, device),
+            culled: Self::create_culled(0, device),
+            args: device.create_buffer(&BufferDescriptor {
+                label: Some("cull_args_buffer"),
+                size: 4 * mem::size_of::<u32>() as BufferAddress,
+                usage: BufferUsages::INDIRECT
+                    | BufferUsages::STORAGE
+                    | BufferUsages::COPY_DST
+                    | BufferUsages::COPY_SRC,
+                mapped_at_creation: false,
+            }),
+            bind_group: None,
+            groups: [0, 0],
+        }
+    }
+
+    fn create_ranges(count: usize, device: &Device) -> Buffer {
+        device.create_buffer(&BufferDescriptor {
+            label: Some("cull_ranges_buffer"),
+            size: (count * mem::size_of::<[u32; 4]>()) as BufferAddress,
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    fn create_culled(size: BufferAddress, device: &Device) -> Buffer {
+        device.create_buffer(&BufferDescriptor {
+            label: Some("cull_instance_buffer"),
+            size,
+            usage: BufferUsages::VERTEX | BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        })
+    }
+
+    /// Call when the chunk's instance buffer has been replaced.
+    pub fn invalidate(&mut self) {
+        self.bind_group = None;
+    }
+
+    /// Bytes allocated for the surviving instances.
+    pub fn capacity(&self) -> BufferAddress {
+        self.culled.size()
+    }
+
+    /// Sets up culling of `ranges` of `instances`, given in instances from
+    /// the start of the chunk.
+    #[allow(clippy::too_many_arguments)]
+    pub fn prepare(
+        &mut self,
+        culler: &Culler,
+        instances: &Buffer,
+        ranges: &[Range<u32>],
+        model_view_proj: [[f32; 4]; 4],
+        margin: [f32; 2],
+        device: &Device,
+        queue: &Queue,
+    ) {
+        let mut total = 0;
+        let ranges: Vec<[u32; 4]> = ranges
+            .iter()
+            .map(|range| {
+                let entry = [range.start, range.len() as u32, total, 0];
+                total += range.len() as u32;
+                entry
+            })
+            .collect();
+
+        let range_bytes: &[u8] = bytemuck::cast_slice(&ranges);
+        if range_bytes.len() as BufferAddress > self.ranges.size() {
+            self.ranges = Self::create_ranges(ranges.len() + ranges.len() / 2, device);
+            self.bind_group = None;
+        }
+        if self.culled.size() < instances.size() {
+            self.culled = Self::create_culled(instances.size(), device);
+            self.bind_group = None;
+        }
+
+        // Workgroup counts are limited per dimension, so large chunks spill
+        // into a second one.
+        let max_groups = device.limits().max_compute_workgroups_per_dimension.max(1);
+        let groups = total.div_ceil(WORKGROUP_SIZE);
+        let groups_x = groups.min(max_groups);
+        self.groups = [groups_x, groups.div_ceil(groups_x.max(1))];
+
+        let params = Params {
+            model_view_proj,
+            margin,
+            range_count: ranges.len() as u32,
+            total,
+            groups_x,
+            _padding: [0; 3],
+        };
+        queue.write_buffer(&self.params, 0, bytemuck::bytes_of(&params));
+        queue.write_buffer(&self.ranges, 0, range_bytes);
+        queue.write_buffer(&self.args, 0, bytemuck::cast_slice(&[6u32, 0, 0, 0]));
+
+        if self.bind_group.is_none() {
+            self.bind_group = Some(device.create_bind_group(&BindGroupDescriptor {
+                label: Some("cull_bind_group"),
+                layout: &culler.layout,
+                entries: &[
+                    BindGroupEntry {
+                        binding: 0,
+                        resource: self.params.as_entire_binding(),
+                    },
+                    BindGroupEntry {
+                        binding: 1,
+                        resource: instances.as_entire_binding(),
+                    },
+                    BindGroupEntry {
+                        binding: 2,
+                        resource: self.ranges.as_entire_binding(),
+                    },
+                    BindGroupEntry {
+                        binding: 3,
+                        resource: self.culled.as_entire_binding(),
+                    },
+                    BindGroupEntry {
+                        binding: 4,
+                        resource: self.args.as_entire_binding(),
+                    },
+                ],
+            }));
+        }
+    }
+
+    pub fn dispatch(&self, pass: &mut ComputePass) {
+        let [x, y] = self.groups;
+        if let (Some(bind_group), true) = (&self.bind_group, x > 0) {
+            pass.set_bind_group(0, bind_group, &[]);
+            pass.dispatch_workgroups(x, y, 1);
+        }
+    }
+
+    pub fn culled(&self) -> &Buffer {
+        &self.culled
+    }
+
+    pub fn args(&self) -> &Buffer {
+        &self.args
+    }
+}
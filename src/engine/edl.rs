@@ -0,0 +1,283 @@
+use bytemuck::{Pod, Zeroable};
+use wgpu::{
+    util::{BufferInitDescriptor, DeviceExt},
+    BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry, BindingResource, BindingType, Buffer, BufferBindingType, BufferUsages,
+    ColorTargetState, ColorWrites, CommandEncoder, Device, FragmentState, MultisampleState,
+    Operations, PipelineCompilationOptions, PipelineLayoutDescriptor, PrimitiveState, Queue,
+    RenderPassColorAttachment, RenderPassDescriptor, RenderPipeline, RenderPipelineDescriptor,
+    ShaderModuleDescriptor, ShaderSource, ShaderStages, SurfaceConfiguration, TextureFormat,
+    TextureSampleType, TextureView, TextureViewDimension, VertexState,
+};
+
+use super::{camera::Camera, texture::Texture};
+
+#[repr(C)]
+#[derive(Clone, Copy, Zeroable, Pod)]
+struct Params {
+    depth_to_inverse_distance: [f32; 2],
+    strength: f32,
+    radius: f32,
+    background: f32,
+    _padding: [u32; 3],
+}
+
+/// Eye-dome lighting: the points are drawn into an offscreen target, which a
+/// full-screen pass then copies to the surface, darkening pixels that lie
+/// behind their neighbors.
+pub struct EyeDome {
+    enabled: bool,
+    strength: f32,
+    radius: f32,
+    format: TextureFormat,
+    /// That of the depth texture read and of the target drawn to.
+    sample_count: u32,
+    pipeline: RenderPipeline,
+    layout: BindGroupLayout,
+    params: Buffer,
+    target: Texture,
+    /// Binds `target` and the depth texture; rebuilt along with them.
+    bind_group: BindGroup,
+}
+
+impl EyeDome {
+    pub const DEFAULT_STRENGTH: f32 = 1.0;
+    pub const DEFAULT_RADIUS: f32 = 1.4;
+
+    pub fn new(
+        device: &Device,
+        config: &SurfaceConfiguration,
+        depth_texture: &Texture,
+        sample_count: u32,
+    ) -> Self {
+        let (pipeline, layout) = Self::create_pipeline(device, config.format, sample_count);
+
+        let params = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("edl_params_buffer"),
+            contents: bytemuck::bytes_of(&Params::zeroed()),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        });
+
+        let target = Texture::create_color_target(device, config, 1, "edl_target");
+        let bind_group = Self::create_bind_group(device, &layout, &params, &target, depth_texture);
+
+        Self {
+            enabled: true,
+            strength: Self::DEFAULT_STRENGTH,
+            radius: Self::DEFAULT_RADIUS,
+            format: config.format,
+            sample_count,
+            pipeline,
+            layout,
+            params,
+            target,
+            bind_group,
+        }
+    }
+
+    fn create_pipeline(
+        device: &Device,
+        format: TextureFormat,
+        sample_count: u32,
+    ) -> (RenderPipeline, BindGroupLayout) {
+        let multisampled = sample_count > 1;
+
+        // `textureLoad` takes a sample index where it would take a mip level,
+        // so only the type of the depth texture differs.
+        let mut source = include_str!("shaders/edl.wgsl").to_string();
+        if multisampled {
+            source = source.replace("texture_depth_2d", "texture_depth_multisampled_2d");
+        }
+        let shader = device.create_shader_module(ShaderModuleDescriptor {
+            label: Some("edl_shader"),
+            source: ShaderSource::Wgsl(source.into()),
+        });
+
+        let texture = |binding, sample_type, multisampled| BindGroupLayoutEntry {
+            binding,
+            visibility: ShaderStages::FRAGMENT,
+            ty: BindingType::Texture {
+                sample_type,
+                view_dimension: TextureViewDimension::D2,
+                multisampled,
+            },
+            count: None,
+        };
+
+        let layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("edl_bind_group_layout"),
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                texture(1, TextureSampleType::Float { filterable: false }, false),
+                texture(2, TextureSampleType::Depth, multisampled),
+            ],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("edl_pipeline_layout"),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some("edl_pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: VertexState {
+                module: &shader,
+                compilation_options: PipelineCompilationOptions::default(),
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            primitive: PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: MultisampleState {
+                count: sample_count,
+                ..Default::default()
+            },
+            fragment: Some(FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                compilation_options: PipelineCompilationOptions::default(),
+                targets: &[Some(ColorTargetState {
+                    format,
+                    blend: None,
+                    write_mask: ColorWrites::ALL,
+                })],
+            }),
+            multiview: None,
+            cache: None,
+        });
+
+        (pipeline, layout)
+    }
+
+    fn create_bind_group(
+        device: &Device,
+        layout: &BindGroupLayout,
+        params: &Buffer,
+        target: &Texture,
+        depth_texture: &Texture,
+    ) -> BindGroup {
+        device.create_bind_group(&BindGroupDescriptor {
+            label: Some("edl_bind_group"),
+            layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: params.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::TextureView(target.view()),
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: BindingResource::TextureView(depth_texture.view()),
+                },
+            ],
+        })
+    }
+
+    /// Call after the surface and depth texture have been recreated, with
+    /// the sample count of the new depth texture.
+    pub fn resize(
+        &mut self,
+        device: &Device,
+        config: &SurfaceConfiguration,
+        depth_texture: &Texture,
+        sample_count: u32,
+    ) {
+        if sample_count != self.sample_count || config.format != self.format {
+            (self.pipeline, self.layout) =
+                Self::create_pipeline(device, config.format, sample_count);
+            self.sample_count = sample_count;
+            self.format = config.format;
+        }
+        self.target = Texture::create_color_target(device, config, 1, "edl_target");
+        self.bind_group = Self::create_bind_group(
+            device,
+            &self.layout,
+            &self.params,
+            &self.target,
+            depth_texture,
+        );
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    pub fn strength(&self) -> f32 {
+        self.strength
+    }
+
+    pub fn set_strength(&mut self, strength: f32) {
+        self.strength = strength.max(0.0);
+    }
+
+    /// Distance to the neighbors compared against, in pixels.
+    pub fn radius(&self) -> f32 {
+        self.radius
+    }
+
+    pub fn set_radius(&mut self, radius: f32) {
+        self.radius = radius.max(1.0);
+    }
+
+    /// Where the points are drawn, or resolved to when multisampled, while
+    /// the effect is on.
+    pub fn target(&self) -> &TextureView {
+        self.target.view()
+    }
+
+    /// Shades the target onto `view`, which must be the size of the surface
+    /// and have the sample count the effect was last sized for.
+    pub fn draw(
+        &self,
+        encoder: &mut CommandEncoder,
+        view: &TextureView,
+        camera: &Camera,
+        queue: &Queue,
+    ) {
+        let params = Params {
+            depth_to_inverse_distance: camera.depth_linearization(),
+            strength: self.strength,
+            radius: self.radius,
+            background: Texture::depth_clear_value(camera.reversed_z()),
+            _padding: [0; 3],
+        };
+        queue.write_buffer(&self.params, 0, bytemuck::bytes_of(&params));
+
+        let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            label: Some("edl_render_pass"),
+            color_attachments: &[Some(RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
@@ -0,0 +1,112 @@
+use std::path::{Path, PathBuf};
+
+/// Path to hand to the OS when opening `path`.
+///
+/// On Windows, paths longer than `MAX_PATH` only open with the `\\?\`
+/// extended-length prefix. That prefix also switches off `.`/`..` and `/`
+/// handling, so the path is made absolute and normalized first; it is not
+/// canonicalized, which would resolve links and fail for missing files.
+/// Elsewhere, and for short paths, `path` is returned unchanged.
+pub fn os_path(path: &Path) -> PathBuf {
+    #[cfg(windows)]
+    {
+        use std::os::windows::ffi::OsStrExt;
+
+        // Directory paths are limited to MAX_PATH minus room for an 8.3 name.
+        const MAX_DIR_PATH: usize = 248;
+
+        if path.as_os_str().encode_wide().count() >= MAX_DIR_PATH {
+            if let Ok(absolute) = std::path::absolute(path) {
+                return PathBuf::from(extended_length(&absolute.as_os_str().to_string_lossy()));
+            }
+        }
+    }
+
+    path.to_path_buf()
+}
+
+/// `absolute` with the extended-length prefix, in its UNC form for network
+/// shares, unless it already has one.
+#[cfg(any(windows, test))]
+fn extended_length(absolute: &str) -> String {
+    if absolute.starts_with(r"\\?\") {
+        absolute.to_string()
+    } else if let Some(share) = absolute.strip_prefix(r"\\") {
+        format!(r"\\?\UNC\{}", share)
+    } else {
+        format!(r"\\?\{}", absolute)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::{
+        loader::Progress,
+        pointcloud::{LoadOptions, PointCloud},
+    };
+
+    /// A relative path past the 260 characters of `MAX_PATH`.
+    fn long_path() -> PathBuf {
+        let mut path = PathBuf::from("scans");
+        for index in 0..20 {
+            path.push(format!("survey-{:02}-section", index));
+        }
+        path.push("..");
+        path.push("cloud.ply");
+        path
+    }
+
+    #[test]
+    fn prefixes_drive_and_share_paths() {
+        assert_eq!(
+            extended_length(r"C:\data\scan.pcd"),
+            r"\\?\C:\data\scan.pcd"
+        );
+        assert_eq!(
+            extended_length(r"\\server\share\scan.pcd"),
+            r"\\?\UNC\server\share\scan.pcd"
+        );
+        assert_eq!(
+            extended_length(r"\\?\C:\data\scan.pcd"),
+            r"\\?\C:\data\scan.pcd"
+        );
+    }
+
+    #[test]
+    fn leaves_short_paths_alone() {
+        let path = Path::new("scans/cloud.pcd");
+        assert_eq!(os_path(path), path);
+    }
+
+    #[test]
+    #[cfg(not(windows))]
+    fn leaves_long_paths_alone_off_windows() {
+        assert_eq!(os_path(&long_path()), long_path());
+    }
+
+    #[test]
+    #[cfg(windows)]
+    fn makes_long_paths_absolute_and_normalized() {
+        let os_path = os_path(&long_path());
+        let text = os_path.to_string_lossy();
+
+        assert!(text.starts_with(r"\\?\"), "{}", text);
+        assert!(!text.contains(r"\..\") && !text.contains('/'), "{}", text);
+        assert!(text.ends_with(r"survey-18-section\cloud.ply"), "{}", text);
+    }
+
+    #[test]
+    fn load_errors_name_the_path() {
+        let path = long_path();
+
+        let error = PointCloud::read(&path, LoadOptions::default(), &Progress::default())
+            .err()
+            .unwrap();
+        assert!(
+            error.to_string().contains(&path.display().to_string()),
+            "{}",
+            error
+        );
+    }
+}
@@ -1,18 +1,281 @@
-use std::sync::Arc;
+use std::{collections::HashMap, ops::RangeInclusive, path::PathBuf, sync::Arc, time::Instant};
 
-use egui::{Align2, Button, Context, Rounding, Shadow, Visuals};
+use egui::{
+    epaint::ImageDelta, Align2, Checkbox, CollapsingHeader, ComboBox, Context, DragValue, Event,
+    Grid, Pos2, RichText, Rounding, Sense, Shadow, Shape, Slider, Stroke, TextureId, Ui, Vec2,
+    ViewportId, Visuals,
+};
 use egui_wgpu::{Renderer, ScreenDescriptor};
 use egui_winit::State;
 use wgpu::{
     CommandEncoder, Device, LoadOp, Operations, Queue, RenderPassColorAttachment,
     RenderPassDescriptor, StoreOp, TextureFormat, TextureView,
 };
-use winit::{event::WindowEvent, window::Window};
+use winit::{
+    event::{ElementState, WindowEvent},
+    window::Window,
+};
+
+use super::{
+    camera::Sensitivity,
+    notification::{Level, Notification},
+    number::NumberFormat,
+    pointcloud::{Bounds, ColorMode, PointShape, SizeMode, Transform},
+    PresentMode,
+};
+
+pub struct FrameStats {
+    pub fps: f32,
+    pub average_ms: f32,
+    /// Time spent on each recent frame, oldest first.
+    pub recent_ms: Vec<f32>,
+    pub adapter: String,
+    /// GPU memory held by egui's textures.
+    pub texture_bytes: usize,
+    /// Size of the last acquired surface texture.
+    pub frame_size: [u32; 2],
+    /// The window's size, when the surface had not caught up with it.
+    pub window_size: Option<[u32; 2]>,
+    /// Milliseconds spent in each startup stage and in total, once the
+    /// first frame has been presented.
+    pub startup_ms: Vec<(&'static str, f32)>,
+}
+
+/// Read-only facts about the loaded cloud for the "Info" section.
+pub struct CloudInfo {
+    pub point_count: usize,
+    /// Points drawn in the last frame after level-of-detail selection.
+    pub drawn_count: usize,
+    /// Of those, the points inside the view, while GPU culling is on.
+    pub visible_count: Option<usize>,
+    /// Valid points in the files, when any was downsampled.
+    pub downsampled_from: Option<usize>,
+    /// Points dropped while loading for non-finite coordinates.
+    pub skipped: usize,
+    pub bounds: Bounds,
+    /// Bytes of point data on the GPU.
+    pub gpu_bytes: u64,
+    /// Bytes allocated for it, including room to grow.
+    pub gpu_capacity: u64,
+}
+
+/// A row of the "Clouds" section; the panel edits `visible`, `color` and
+/// `transform`.
+pub struct CloudEntry {
+    pub name: String,
+    pub point_count: usize,
+    pub visible: bool,
+    /// sRGB.
+    pub color: [u8; 3],
+    pub transform: Transform,
+    /// Translation per dragged pixel, in file units.
+    pub translation_speed: f64,
+}
+
+/// A loaded cloud that looks Y-up, with the choice to turn it upright.
+pub struct UpAxisPrompt {
+    pub name: String,
+    /// Lower case, when the file has one.
+    pub extension: Option<String>,
+}
+
+/// The user's answer to an [`UpAxisPrompt`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UpAxisAnswer {
+    Rotate,
+    Keep,
+    /// Rotate, and every file with the same extension from now on.
+    AlwaysRotate,
+}
+
+/// A file being read in the background.
+pub struct Loading {
+    pub name: String,
+    /// Share of the files read so far, once a size is known.
+    pub fraction: Option<f32>,
+}
+
+/// What the panel shows and edits. The engine fills it from its current state
+/// before drawing and applies it afterwards, so a new control needs a field
+/// here and a line on each side rather than another draw parameter.
+pub struct GuiState {
+    pub file_name: Option<String>,
+    pub info: Option<CloudInfo>,
+    pub loading: Option<Loading>,
+    /// Set by the panel when the user abandons the load in progress.
+    pub cancel_load: bool,
+    pub point_size: f32,
+    pub point_size_range: RangeInclusive<f32>,
+    pub default_point_size: f32,
+    /// Points drawn per frame while the camera moves.
+    pub point_budget: usize,
+    pub size_mode: SizeMode,
+    pub point_shape: PointShape,
+    pub color_mode: ColorMode,
+    pub sensitivity: Sensitivity,
+    pub reversed_z: bool,
+    pub infinite_far: bool,
+    pub gpu_culling: bool,
+    pub continuous_redraw: bool,
+    /// Decimal separator of the numbers the panel shows.
+    pub number_format: NumberFormat,
+    pub msaa_samples: u32,
+    /// Sample counts the adapter can render with, ascending.
+    pub supported_samples: Vec<u32>,
+    pub present_mode: PresentMode,
+    pub supported_present_modes: Vec<PresentMode>,
+    /// Set by the panel when the user asks for the open dialog.
+    pub open_file: bool,
+    /// Like `open_file`, for a file to show next to the loaded ones.
+    pub add_file: bool,
+    /// Set by the panel when the user unloads everything.
+    pub clear: bool,
+    /// Set by the panel when the user asks to re-read the loaded files.
+    pub reload: bool,
+    /// Reload files automatically when they change on disk.
+    pub watch_files: bool,
+    pub apply_viewpoint: bool,
+    /// Average new files down to one point per voxel of `voxel_size`.
+    pub downsample: bool,
+    pub voxel_size: f64,
+    /// Set by the panel when the user asks to reload without downsampling.
+    pub full_resolution: bool,
+    pub clouds: Vec<CloudEntry>,
+    /// Set by the panel to the cloud the user removed.
+    pub remove_cloud: Option<usize>,
+    /// Set by the panel to the cloud the user wants read again.
+    pub reload_cloud: Option<usize>,
+    /// Set by the panel to the cloud whose matrix the user wants copied.
+    pub copy_matrix: Option<usize>,
+    pub up_axis_prompt: Option<UpAxisPrompt>,
+    /// Set by the panel once the user answered `up_axis_prompt`.
+    pub up_axis_answer: Option<UpAxisAnswer>,
+    /// Most recent first.
+    pub recent_files: Vec<PathBuf>,
+    /// Set by the panel to the recent file the user picked.
+    pub open_recent: Option<PathBuf>,
+    pub notifications: Vec<Notification>,
+    pub show_frame_stats: bool,
+    pub frame_stats: FrameStats,
+    /// sRGB.
+    pub background_color: [u8; 3],
+    /// Shade points by depth differences with their neighbors.
+    pub eye_dome: bool,
+    pub eye_dome_strength: f32,
+    /// In pixels.
+    pub eye_dome_radius: f32,
+}
+
+/// Which kinds of input egui wants to keep for itself after seeing an event.
+#[derive(Clone, Copy, Default)]
+pub struct InputCapture {
+    pub pointer: bool,
+    pub keyboard: bool,
+    /// egui wants a new frame to show its response to the event.
+    pub repaint: bool,
+}
+
+impl InputCapture {
+    pub fn blocks(&self, event: &WindowEvent) -> bool {
+        match event {
+            // Releases still reach the camera, which may be holding the key.
+            WindowEvent::KeyboardInput { event, .. } => {
+                self.keyboard && event.state == ElementState::Pressed
+            }
+            WindowEvent::Ime(_) => self.keyboard,
+            WindowEvent::MouseInput { .. }
+            | WindowEvent::MouseWheel { .. }
+            | WindowEvent::CursorMoved { .. }
+            | WindowEvent::Touch(_) => self.pointer,
+            _ => false,
+        }
+    }
+}
+
+/// Bookkeeping of the GPU texture memory egui asks us to allocate, so growth
+/// across DPI switches can be spotted instead of silently creeping.
+#[derive(Default)]
+struct TextureMemory {
+    bytes: HashMap<TextureId, usize>,
+    pixels_per_point: Option<f32>,
+    total_at_scale_change: usize,
+    growth_streak: u32,
+}
+
+impl TextureMemory {
+    /// Consecutive scale changes with growing totals before we call it a leak.
+    const GROWTH_STREAK_LIMIT: u32 = 4;
+
+    fn set(&mut self, id: TextureId, delta: &ImageDelta) {
+        // Partial updates write into an existing allocation.
+        if delta.pos.is_some() {
+            return;
+        }
+
+        let [width, height] = delta.image.size();
+        let bytes = width * height * delta.image.bytes_per_pixel();
+
+        if let Some(previous) = self.bytes.insert(id, bytes) {
+            log::debug!(
+                "egui texture {:?} reallocated without free ({} -> {} bytes)",
+                id,
+                previous,
+                bytes
+            );
+        }
+    }
+
+    fn free(&mut self, id: &TextureId) {
+        if self.bytes.remove(id).is_none() {
+            log::warn!("egui freed untracked texture {:?}", id);
+        }
+    }
+
+    fn total(&self) -> usize {
+        self.bytes.values().sum()
+    }
+
+    fn end_frame(&mut self, pixels_per_point: f32) {
+        let previous = self.pixels_per_point.replace(pixels_per_point);
+        let total = self.total();
+
+        match previous {
+            None => self.total_at_scale_change = total,
+            Some(previous) if previous != pixels_per_point => {
+                if total > self.total_at_scale_change {
+                    self.growth_streak += 1;
+                } else {
+                    self.growth_streak = 0;
+                }
+                self.total_at_scale_change = total;
+
+                log::debug!(
+                    "egui texture memory at {} pixels per point: {} bytes in {} textures",
+                    pixels_per_point,
+                    total,
+                    self.bytes.len()
+                );
+
+                if self.growth_streak >= Self::GROWTH_STREAK_LIMIT {
+                    log::warn!(
+                        "egui texture memory grew across {} consecutive scale changes ({} bytes)",
+                        self.growth_streak,
+                        total
+                    );
+                }
+            }
+            Some(_) => {}
+        }
+    }
+}
 
 pub struct EguiRender {
     context: Context,
     state: State,
     renderer: Renderer,
+    texture_memory: TextureMemory,
+    /// When egui last asked to be drawn again, e.g. to animate a hover.
+    repaint_at: Option<Instant>,
 }
 
 impl EguiRender {
@@ -50,14 +313,53 @@ impl EguiRender {
             context,
             state,
             renderer,
+            texture_memory: TextureMemory::default(),
+            repaint_at: None,
         }
     }
 
-    pub fn input(&mut self, window: &Window, event: &WindowEvent) -> bool {
+    pub fn input(&mut self, window: &Window, event: &WindowEvent) -> InputCapture {
         let response = self.state.on_window_event(window, event);
-        response.consumed
+
+        InputCapture {
+            pointer: response.consumed,
+            keyboard: self.context.wants_keyboard_input(),
+            repaint: response.repaint,
+        }
     }
 
+    /// Recreates the renderer for targets with `msaa_samples` samples. Panel
+    /// layout and open sections carry over; textures are uploaded afresh.
+    pub fn set_msaa_samples(
+        &mut self,
+        device: &Device,
+        output_color_format: TextureFormat,
+        msaa_samples: u32,
+        window: Arc<Window>,
+    ) {
+        let memory = self.context.memory(|memory| memory.clone());
+        *self = Self::new(device, output_color_format, None, msaa_samples, window);
+        self.context.memory_mut(|new| *new = memory);
+    }
+
+    /// When the panel has to be drawn again to keep animating, if at all.
+    pub fn repaint_at(&self) -> Option<Instant> {
+        self.repaint_at
+    }
+
+    /// Bytes of the textures egui has allocated and not yet freed.
+    pub fn texture_bytes(&self) -> usize {
+        self.texture_memory.total()
+    }
+
+    /// Puts `text` on the clipboard right away, so it also works while the
+    /// panel is hidden and no frame is drawn.
+    pub fn copy_text(&mut self, text: String) {
+        self.state.set_clipboard_text(text);
+    }
+
+    /// Draws the panel and returns any text pasted while no egui widget had
+    /// keyboard focus.
     pub fn draw(
         &mut self,
         device: &Device,
@@ -66,15 +368,33 @@ impl EguiRender {
         window: &Window,
         window_surface_view: &TextureView,
         screen_descriptor: ScreenDescriptor,
-    ) {
+        state: &mut GuiState,
+    ) -> Option<String> {
         let raw_input = self.state.take_egui_input(&window);
+        let pasted = raw_input.events.iter().find_map(|event| match event {
+            Event::Paste(text) => Some(text.clone()),
+            _ => None,
+        });
         let full_output = self.context.run(raw_input, |ui| {
-            layout(ui);
+            layout(ui, state);
+            toasts(ui, &state.notifications);
+            if let Some(prompt) = &state.up_axis_prompt {
+                state.up_axis_answer = up_axis_prompt(ui, prompt);
+            }
+            if state.show_frame_stats {
+                frame_overlay(ui, &state.frame_stats);
+            }
         });
+        let pasted = pasted.filter(|_| !self.context.wants_keyboard_input());
 
         self.state
             .handle_platform_output(&window, full_output.platform_output);
 
+        self.repaint_at = full_output
+            .viewport_output
+            .get(&ViewportId::ROOT)
+            .and_then(|output| Instant::now().checked_add(output.repaint_delay));
+
         let tris = self
             .context
             .tessellate(full_output.shapes, full_output.pixels_per_point);
@@ -82,6 +402,7 @@ impl EguiRender {
         for (id, image_delta) in &full_output.textures_delta.set {
             self.renderer
                 .update_texture(&device, &queue, *id, &image_delta);
+            self.texture_memory.set(*id, image_delta);
         }
 
         self.renderer
@@ -109,11 +430,38 @@ impl EguiRender {
 
         for id in &full_output.textures_delta.free {
             self.renderer.free_texture(id);
+            self.texture_memory.free(id);
         }
+
+        self.texture_memory.end_frame(full_output.pixels_per_point);
+
+        pasted
+    }
+}
+
+/// Number widgets that write with the panel's decimal separator and read
+/// either.
+trait Localized {
+    fn localized(self, format: NumberFormat) -> Self;
+}
+
+impl Localized for DragValue<'_> {
+    fn localized(self, format: NumberFormat) -> Self {
+        self.custom_formatter(move |value, decimals| format.format_in_range(value, decimals))
+            .custom_parser(NumberFormat::parse)
+    }
+}
+
+impl Localized for Slider<'_> {
+    fn localized(self, format: NumberFormat) -> Self {
+        self.custom_formatter(move |value, decimals| format.format_in_range(value, decimals))
+            .custom_parser(NumberFormat::parse)
     }
 }
 
-fn layout(ui: &Context) {
+fn layout(ui: &Context, state: &mut GuiState) {
+    let format = state.number_format;
+
     egui::Window::new("pcvisualizer")
         .default_open(true)
         .max_width(640.0)
@@ -122,11 +470,628 @@ fn layout(ui: &Context) {
         .resizable(true)
         .anchor(Align2::LEFT_TOP, [0.0, 0.0])
         .show(&ui, |ui| {
-            if ui.add(Button::new("Click me")).clicked() {
-                println!("PRESSED")
+            ui.horizontal(|ui| {
+                if ui.button("Open…").clicked() {
+                    state.open_file = true;
+                }
+                if ui.button("Add…").clicked() {
+                    state.add_file = true;
+                }
+                if ui
+                    .add_enabled(!state.clouds.is_empty(), egui::Button::new("Clear"))
+                    .on_hover_text("Unload all clouds (Delete)")
+                    .clicked()
+                {
+                    state.clear = true;
+                }
+                ui.label(state.file_name.as_deref().unwrap_or("No file loaded"));
+            });
+
+            if let Some(loading) = &state.loading {
+                ui.horizontal(|ui| {
+                    ui.spinner();
+                    let text = format!("Loading {}", loading.name);
+                    match loading.fraction {
+                        Some(fraction) => {
+                            ui.add(
+                                egui::ProgressBar::new(fraction)
+                                    .desired_width(180.0)
+                                    .text(text),
+                            );
+                        }
+                        None => {
+                            ui.label(format!("{}…", text));
+                        }
+                    }
+                    if ui.small_button("Cancel").clicked() {
+                        state.cancel_load = true;
+                    }
+                });
             }
 
-            ui.label("Slider");
+            ui.horizontal(|ui| {
+                if ui
+                    .add_enabled(!state.clouds.is_empty(), egui::Button::new("Reload"))
+                    .on_hover_text("Read the loaded files again (F5, Ctrl+R)")
+                    .clicked()
+                {
+                    state.reload = true;
+                }
+                ui.checkbox(&mut state.watch_files, "Watch for changes");
+            });
+            ui.checkbox(&mut state.apply_viewpoint, "Apply PCD viewpoint")
+                .on_hover_text("Place points by the sensor pose in the file header");
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut state.downsample, "Downsample to voxels of")
+                    .on_hover_text(
+                        "Keep one averaged point per voxel in files loaded from now on; \
+                         Reload applies it to the loaded ones",
+                    );
+                let speed = state.voxel_size * 0.01;
+                ui.add_enabled(
+                    state.downsample,
+                    DragValue::new(&mut state.voxel_size)
+                        .speed(speed)
+                        .range(1e-6..=f64::MAX)
+                        .localized(format),
+                );
+            });
+
+            ui.collapsing("Recent files", |ui| {
+                if state.recent_files.is_empty() {
+                    ui.label("No recent files");
+                }
+                for path in &state.recent_files {
+                    let name = path.file_name().unwrap_or(path.as_os_str());
+                    if ui
+                        .button(name.to_string_lossy())
+                        .on_hover_text(path.display().to_string())
+                        .clicked()
+                    {
+                        state.open_recent = Some(path.clone());
+                    }
+                }
+            });
+
+            ui.collapsing("Clouds", |ui| {
+                if state.clouds.is_empty() {
+                    ui.label("No cloud loaded");
+                }
+                for (index, cloud) in state.clouds.iter_mut().enumerate() {
+                    ui.horizontal(|ui| {
+                        ui.checkbox(&mut cloud.visible, "");
+                        ui.color_edit_button_srgb(&mut cloud.color);
+                        ui.label(&cloud.name)
+                            .on_hover_text(format!("{} points", cloud.point_count));
+                        if ui.small_button("Reload").clicked() {
+                            state.reload_cloud = Some(index);
+                        }
+                        if ui.small_button("Remove").clicked() {
+                            state.remove_cloud = Some(index);
+                        }
+                    });
+                    CollapsingHeader::new("Transform")
+                        .id_salt(("cloud_transform", index))
+                        .show(ui, |ui| {
+                            if cloud_transform(ui, index, cloud, format) {
+                                state.copy_matrix = Some(index);
+                            }
+                        });
+                }
+            });
+
+            ui.collapsing("Info", |ui| match &state.info {
+                Some(info) => {
+                    cloud_info(ui, info, format);
+                    if info.downsampled_from.is_some()
+                        && ui.button("Reload at full resolution").clicked()
+                    {
+                        state.full_resolution = true;
+                    }
+                }
+                None => {
+                    ui.label("No cloud loaded");
+                }
+            });
+
+            ui.collapsing("Points", |ui| {
+                ui.horizontal(|ui| {
+                    let suffix = match state.size_mode {
+                        SizeMode::Pixel => " px",
+                        SizeMode::World => "",
+                    };
+                    ui.add(
+                        Slider::new(&mut state.point_size, state.point_size_range.clone())
+                            .logarithmic(state.size_mode == SizeMode::World)
+                            .suffix(suffix)
+                            .text("Point size")
+                            .localized(format),
+                    );
+                    if ui.button("Reset").clicked() {
+                        state.point_size = state.default_point_size;
+                    }
+                });
+                let mut budget = state.point_budget as f32 / 1e6;
+                let budget_slider = ui
+                    .add(
+                        Slider::new(&mut budget, 0.1..=100.0)
+                            .logarithmic(true)
+                            .suffix(" M")
+                            .text("Point budget")
+                            .localized(format),
+                    )
+                    .on_hover_text(
+                        "Most points drawn while the camera moves; the view fills in once it stops",
+                    );
+                if budget_slider.changed() {
+                    state.point_budget = (budget * 1e6) as usize;
+                }
+                ui.horizontal(|ui| {
+                    ui.radio_value(&mut state.size_mode, SizeMode::Pixel, "Pixels");
+                    ui.radio_value(&mut state.size_mode, SizeMode::World, "World units");
+                });
+                ui.horizontal(|ui| {
+                    ui.radio_value(&mut state.point_shape, PointShape::Circle, "Circles");
+                    ui.radio_value(&mut state.point_shape, PointShape::Square, "Squares");
+                });
+                ComboBox::from_label("Color")
+                    .selected_text(state.color_mode.name())
+                    .show_ui(ui, |ui| {
+                        for mode in [ColorMode::Flat, ColorMode::Intensity, ColorMode::Height] {
+                            ui.selectable_value(&mut state.color_mode, mode, mode.name());
+                        }
+                    });
+            });
+
+            ui.collapsing("Rendering", |ui| {
+                ui.checkbox(&mut state.reversed_z, "Reversed-Z depth")
+                    .on_hover_text("Better depth precision far from the camera");
+                ui.add_enabled(
+                    state.reversed_z,
+                    Checkbox::new(&mut state.infinite_far, "Infinite far plane"),
+                )
+                .on_hover_text("Never clip distant points; needs reversed-Z");
+                ui.checkbox(&mut state.gpu_culling, "GPU frustum culling")
+                    .on_hover_text("Skip points outside the view in a compute pass before drawing");
+                ui.horizontal(|ui| {
+                    ui.label("Anti-aliasing");
+                    for &samples in &state.supported_samples {
+                        let name = match samples {
+                            1 => "Off".to_string(),
+                            samples => format!("{}x", samples),
+                        };
+                        ui.radio_value(&mut state.msaa_samples, samples, name);
+                    }
+                });
+                ComboBox::from_label("Present mode")
+                    .selected_text(state.present_mode.name())
+                    .show_ui(ui, |ui| {
+                        for &mode in &state.supported_present_modes {
+                            ui.selectable_value(&mut state.present_mode, mode, mode.name());
+                        }
+                    })
+                    .response
+                    .on_hover_text("Vsync caps the frame rate; immediate is uncapped but may tear");
+                ui.checkbox(&mut state.eye_dome, "Eye-dome lighting")
+                    .on_hover_text("Darken points behind their neighbors to bring out shape");
+                ui.add_enabled_ui(state.eye_dome, |ui| {
+                    ui.add(
+                        Slider::new(&mut state.eye_dome_strength, 0.1..=10.0)
+                            .logarithmic(true)
+                            .text("Strength")
+                            .localized(format),
+                    );
+                    ui.add(
+                        Slider::new(&mut state.eye_dome_radius, 1.0..=4.0)
+                            .suffix(" px")
+                            .text("Radius")
+                            .localized(format),
+                    );
+                });
+                ui.checkbox(&mut state.show_frame_stats, "Frame times (F3)");
+                ui.checkbox(&mut state.continuous_redraw, "Redraw continuously")
+                    .on_hover_text("For benchmarking; otherwise frames are drawn only on change");
+                ComboBox::from_label("Decimal separator")
+                    .selected_text(state.number_format.name())
+                    .show_ui(ui, |ui| {
+                        for format in NumberFormat::ALL {
+                            ui.selectable_value(&mut state.number_format, format, format.name());
+                        }
+                    })
+                    .response
+                    .on_hover_text(
+                        "For numbers shown in the panel; either is accepted when typing",
+                    );
+
+                const PRESETS: [(&str, [u8; 3]); 3] = [
+                    ("Black", [0, 0, 0]),
+                    ("Dark gray", [48, 48, 48]),
+                    ("White", [255, 255, 255]),
+                ];
+
+                ui.horizontal(|ui| {
+                    ui.color_edit_button_srgb(&mut state.background_color);
+                    ui.label("Background");
+                    for (name, color) in PRESETS {
+                        if ui.small_button(name).clicked() {
+                            state.background_color = color;
+                        }
+                    }
+                });
+            });
+
+            ui.collapsing("Mouse", |ui| {
+                let sensitivity = &mut state.sensitivity;
+                ui.add(
+                    Slider::new(&mut sensitivity.rotate_speed, 0.1..=5.0)
+                        .logarithmic(true)
+                        .text("Rotate speed")
+                        .localized(format),
+                );
+                ui.add(
+                    Slider::new(&mut sensitivity.zoom_speed, 0.01..=0.5)
+                        .logarithmic(true)
+                        .text("Zoom speed")
+                        .localized(format),
+                );
+                ui.add(
+                    Slider::new(&mut sensitivity.pan_speed, 0.1..=5.0)
+                        .logarithmic(true)
+                        .text("Pan speed")
+                        .localized(format),
+                );
+                ui.checkbox(&mut sensitivity.invert_x, "Invert horizontal rotation");
+                ui.checkbox(&mut sensitivity.invert_y, "Invert vertical rotation");
+            });
+        });
+}
+
+/// Returns whether the user asked for the matrix to be copied.
+fn cloud_transform(
+    ui: &mut Ui,
+    index: usize,
+    cloud: &mut CloudEntry,
+    format: NumberFormat,
+) -> bool {
+    let transform = &mut cloud.transform;
+
+    Grid::new(("cloud_transform_grid", index))
+        .num_columns(4)
+        .show(ui, |ui| {
+            ui.label("Translation");
+            for value in &mut transform.translation {
+                ui.add(
+                    DragValue::new(value)
+                        .speed(cloud.translation_speed)
+                        .localized(format),
+                );
+            }
+            ui.end_row();
+
+            ui.label("Roll, pitch, yaw");
+            for value in &mut transform.rotation {
+                ui.add(
+                    DragValue::new(value)
+                        .speed(0.1)
+                        .suffix("°")
+                        .localized(format),
+                );
+            }
             ui.end_row();
         });
+
+    ui.horizontal(|ui| {
+        if ui.button("Reset").clicked() {
+            *transform = Transform::default();
+        }
+        ui.button("Copy matrix")
+            .on_hover_text("Row-major 4×4, in file coordinates")
+            .clicked()
+    })
+    .inner
+}
+
+fn cloud_info(ui: &mut Ui, info: &CloudInfo, format: NumberFormat) {
+    let [min_x, min_y, min_z] = info.bounds.min;
+    let [max_x, max_y, max_z] = info.bounds.max;
+    let vector = |values: [f64; 3], separator: &str| {
+        values.map(|value| format.format(value, 3)).join(separator)
+    };
+
+    Grid::new("cloud_info").num_columns(2).show(ui, |ui| {
+        ui.label("Points");
+        ui.label(info.point_count.to_string());
+        ui.end_row();
+
+        if let Some(full) = info.downsampled_from {
+            ui.label("Full resolution");
+            ui.label(full.to_string());
+            ui.end_row();
+        }
+
+        ui.label("Drawn");
+        ui.label(info.drawn_count.to_string());
+        ui.end_row();
+
+        if let Some(visible) = info.visible_count {
+            ui.label("In view");
+            ui.label(visible.to_string());
+            ui.end_row();
+        }
+
+        if info.skipped > 0 {
+            ui.label("Skipped");
+            ui.label(format!("{} (invalid coordinates)", info.skipped));
+            ui.end_row();
+        }
+
+        // A comma separator would run into the decimal commas.
+        let list = match format.decimal_separator() {
+            ',' => "; ",
+            _ => ", ",
+        };
+
+        ui.label("Min");
+        ui.label(vector(info.bounds.min, list));
+        ui.end_row();
+
+        ui.label("Max");
+        ui.label(vector(info.bounds.max, list));
+        ui.end_row();
+
+        ui.label("Size");
+        ui.label(vector([max_x - min_x, max_y - min_y, max_z - min_z], " × "));
+        ui.end_row();
+
+        if let Some(shape) = match info.bounds.flat_axes() {
+            0 => None,
+            1 => Some("planar"),
+            2 => Some("line"),
+            _ => Some("single point"),
+        } {
+            ui.label("Shape");
+            ui.label(shape);
+            ui.end_row();
+        }
+
+        ui.label("GPU memory");
+        ui.label(format!(
+            "{} of {} MB",
+            format.format(info.gpu_bytes as f64 / (1024.0 * 1024.0), 1),
+            format.format(info.gpu_capacity as f64 / (1024.0 * 1024.0), 1)
+        ))
+        .on_hover_text("Used by points, of the memory allocated for them");
+        ui.end_row();
+    });
+}
+
+/// FPS, average frame time and a sparkline of recent frame times in the
+/// top-right corner.
+fn frame_overlay(ui: &Context, stats: &FrameStats) {
+    const PLOT_SIZE: Vec2 = Vec2::new(160.0, 40.0);
+
+    egui::Area::new(egui::Id::new("frame_stats"))
+        .anchor(Align2::RIGHT_TOP, [-8.0, 8.0])
+        .interactable(false)
+        .show(ui, |ui| {
+            egui::Frame::popup(ui.style()).show(ui, |ui| {
+                ui.label(format!("{:.0} fps  {:.2} ms", stats.fps, stats.average_ms));
+                ui.label(RichText::new(&stats.adapter).small().weak());
+                ui.label(
+                    RichText::new(format!(
+                        "egui textures {:.1} MB",
+                        stats.texture_bytes as f64 / (1024.0 * 1024.0)
+                    ))
+                    .small()
+                    .weak(),
+                );
+                let [width, height] = stats.frame_size;
+                ui.label(
+                    RichText::new(format!("frame {}×{}", width, height))
+                        .small()
+                        .weak(),
+                );
+                if let Some([width, height]) = stats.window_size {
+                    ui.label(
+                        RichText::new(format!("window {}×{}", width, height))
+                            .small()
+                            .color(ui.visuals().warn_fg_color),
+                    )
+                    .on_hover_text("The surface has not caught up with the window yet");
+                }
+
+                let (rect, _) = ui.allocate_exact_size(PLOT_SIZE, Sense::hover());
+                let max_ms = stats.recent_ms.iter().copied().fold(1.0, f32::max);
+                let step = rect.width() / (stats.recent_ms.len().max(2) - 1) as f32;
+                let points = stats
+                    .recent_ms
+                    .iter()
+                    .enumerate()
+                    .map(|(i, ms)| {
+                        Pos2::new(
+                            rect.left() + i as f32 * step,
+                            rect.bottom() - ms / max_ms * rect.height(),
+                        )
+                    })
+                    .collect();
+
+                let painter = ui.painter();
+                painter.rect_stroke(rect, 0.0, ui.visuals().widgets.noninteractive.bg_stroke);
+                painter.add(Shape::line(
+                    points,
+                    Stroke::new(1.0, ui.visuals().text_color()),
+                ));
+
+                if !stats.startup_ms.is_empty() {
+                    ui.label(RichText::new("Startup").small());
+                    egui::Grid::new("startup_report").show(ui, |ui| {
+                        for (stage, ms) in &stats.startup_ms {
+                            ui.label(RichText::new(*stage).small().weak());
+                            ui.label(RichText::new(format!("{:.1} ms", ms)).small().weak());
+                            ui.end_row();
+                        }
+                    });
+                }
+            });
+        });
+}
+
+/// Stacks notifications in the bottom-right corner, fading each out over its
+/// last second.
+/// Asks whether to turn a Y-up cloud upright, above the toasts and without
+/// blocking the view.
+fn up_axis_prompt(ui: &Context, prompt: &UpAxisPrompt) -> Option<UpAxisAnswer> {
+    egui::Area::new(egui::Id::new("up_axis_prompt"))
+        .anchor(Align2::CENTER_BOTTOM, [0.0, -8.0])
+        .show(ui, |ui| {
+            egui::Frame::popup(ui.style())
+                .show(ui, |ui| {
+                    ui.label(format!("{} looks Y-up. Rotate it?", prompt.name));
+                    ui.horizontal(|ui| {
+                        let mut answer = None;
+                        if ui.button("Yes").clicked() {
+                            answer = Some(UpAxisAnswer::Rotate);
+                        }
+                        if ui.button("No").clicked() {
+                            answer = Some(UpAxisAnswer::Keep);
+                        }
+                        if let Some(extension) = &prompt.extension {
+                            if ui
+                                .button(format!("Always for .{}", extension))
+                                .on_hover_text("Rotate Y-up files of this type without asking")
+                                .clicked()
+                            {
+                                answer = Some(UpAxisAnswer::AlwaysRotate);
+                            }
+                        }
+                        answer
+                    })
+                    .inner
+                })
+                .inner
+        })
+        .inner
+}
+
+fn toasts(ui: &Context, notifications: &[Notification]) {
+    if notifications.is_empty() {
+        return;
+    }
+
+    let now = Instant::now();
+
+    egui::Area::new(egui::Id::new("notifications"))
+        .anchor(Align2::RIGHT_BOTTOM, [-8.0, -8.0])
+        .interactable(false)
+        .show(ui, |ui| {
+            for notification in notifications {
+                ui.scope(|ui| {
+                    ui.set_opacity(notification.remaining(now).as_secs_f32().min(1.0));
+                    egui::Frame::popup(ui.style()).show(ui, |ui| {
+                        ui.set_max_width(480.0);
+                        let color = match notification.level {
+                            Level::Info => ui.visuals().text_color(),
+                            Level::Error => ui.visuals().error_fg_color,
+                        };
+                        ui.colored_label(color, &notification.message);
+                    });
+                });
+            }
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use egui::{epaint::ColorImage, RawInput, TextureOptions};
+
+    use super::*;
+
+    /// Runs one egui frame at `pixels_per_point` and books its texture
+    /// changes the way [`EguiRender::draw`] does.
+    fn frame(context: &Context, memory: &mut TextureMemory, pixels_per_point: f32) {
+        let mut input = RawInput::default();
+        input
+            .viewports
+            .entry(ViewportId::ROOT)
+            .or_default()
+            .native_pixels_per_point = Some(pixels_per_point);
+
+        let output = context.run(input, |context| {
+            egui::Window::new("Stats").show(context, |ui| {
+                ui.heading("Point cloud");
+                ui.label("1 234 567 points");
+                ui.add(Slider::new(&mut 1.5, 0.5..=20.0).text("Point size"));
+            });
+        });
+
+        for (id, delta) in &output.textures_delta.set {
+            memory.set(*id, delta);
+        }
+        for id in &output.textures_delta.free {
+            memory.free(id);
+        }
+        memory.end_frame(output.pixels_per_point);
+    }
+
+    #[test]
+    fn texture_memory_settles_across_repeated_scale_changes() {
+        let context = Context::default();
+        let mut memory = TextureMemory::default();
+        let scales = [1.0, 1.5, 2.0, 2.5];
+
+        // Let the font atlas settle at each scale once.
+        for scale in scales {
+            frame(&context, &mut memory, scale);
+            frame(&context, &mut memory, scale);
+        }
+        let settled: Vec<_> = scales
+            .iter()
+            .map(|&scale| {
+                frame(&context, &mut memory, scale);
+                frame(&context, &mut memory, scale);
+                memory.total()
+            })
+            .collect();
+
+        for _ in 0..20 {
+            for (&scale, &total) in scales.iter().zip(&settled) {
+                frame(&context, &mut memory, scale);
+                frame(&context, &mut memory, scale);
+                assert!(
+                    memory.total() <= total,
+                    "{} > {} bytes",
+                    memory.total(),
+                    total
+                );
+            }
+        }
+
+        assert!(memory.total() > 0);
+        assert!(memory.growth_streak < TextureMemory::GROWTH_STREAK_LIMIT);
+    }
+
+    #[test]
+    fn texture_memory_notices_growth_across_scale_changes() {
+        let mut memory = TextureMemory::default();
+        let image = |size| {
+            ImageDelta::full(
+                ColorImage::new([size, size], Default::default()),
+                TextureOptions::LINEAR,
+            )
+        };
+
+        memory.end_frame(1.0);
+        for step in 1..=TextureMemory::GROWTH_STREAK_LIMIT as u64 {
+            // Never freed, as a leaking texture would be.
+            memory.set(TextureId::User(step), &image(64));
+            memory.end_frame(1.0 + step as f32 * 0.25);
+        }
+
+        assert_eq!(memory.total(), 4 * 64 * 64 * 4);
+        assert_eq!(memory.growth_streak, TextureMemory::GROWTH_STREAK_LIMIT);
+
+        memory.free(&TextureId::User(1));
+        memory.end_frame(1.0);
+        assert_eq!(memory.growth_streak, 0);
+    }
 }
@@ -0,0 +1,274 @@
+use std::{
+    io::{Read, Seek, SeekFrom},
+    path::Path,
+};
+
+use anyhow::{anyhow, bail, Result};
+
+use super::{
+    loader::{self, Progress},
+    pointcloud::Point,
+};
+
+/// The parts of a LAS public header block we need.
+struct Header {
+    point_offset: u32,
+    point_format: u8,
+    record_length: usize,
+    point_count: u64,
+    scale: [f64; 3],
+    offset: [f64; 3],
+}
+
+impl Header {
+    /// Size of the header as of LAS 1.0; later versions only append to it.
+    const MIN_SIZE: usize = 227;
+    /// Size of the LAS 1.4 header, which adds a 64-bit point count.
+    const SIZE_1_4: usize = 375;
+
+    fn parse(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() < Self::MIN_SIZE || &bytes[..4] != b"LASF" {
+            bail!("not a LAS file");
+        }
+
+        let u16_at = |at: usize| u16::from_le_bytes([bytes[at], bytes[at + 1]]);
+        let u32_at = |at: usize| u32::from_le_bytes(array(&bytes[at..]));
+        let f64_at = |at: usize| f64::from_le_bytes(array(&bytes[at..]));
+
+        let version = (bytes[24], bytes[25]);
+        let header_size = u16_at(94) as usize;
+
+        let raw_format = bytes[104];
+        // LAZ sets one of the top bits to mark compressed point data.
+        if raw_format & 0xC0 != 0 {
+            bail!("compressed LAZ point data is not supported");
+        }
+
+        let point_count = if version >= (1, 4) && header_size >= Self::SIZE_1_4 {
+            u64::from_le_bytes(array(&bytes[247..]))
+        } else {
+            u32_at(107) as u64
+        };
+
+        Ok(Self {
+            point_offset: u32_at(96),
+            point_format: raw_format,
+            record_length: u16_at(105) as usize,
+            point_count,
+            scale: [f64_at(131), f64_at(139), f64_at(147)],
+            offset: [f64_at(155), f64_at(163), f64_at(171)],
+        })
+    }
+
+    /// Byte offset of the red channel within a record, for the formats that
+    /// carry color.
+    fn rgb_offset(&self) -> Option<usize> {
+        match self.point_format {
+            2 => Some(20),
+            3 | 5 => Some(28),
+            7 | 8 | 10 => Some(30),
+            _ => None,
+        }
+    }
+
+    /// The class of `record`. The formats before 6 share its byte with three
+    /// flags.
+    fn classification(&self, record: &[u8]) -> u8 {
+        match self.point_format {
+            0..=5 => record[15] & 0x1F,
+            _ => record[16],
+        }
+    }
+
+    /// Smallest record the point format allows.
+    fn min_record_length(&self) -> Result<usize> {
+        Ok(match self.point_format {
+            0 => 20,
+            1 => 28,
+            2 => 26,
+            3 => 34,
+            4 => 57,
+            5 => 63,
+            6 => 30,
+            7 => 36,
+            8 => 38,
+            9 => 59,
+            10 => 67,
+            format => bail!("unknown point data format {}", format),
+        })
+    }
+}
+
+fn array<const N: usize>(bytes: &[u8]) -> [u8; N] {
+    let mut array = [0; N];
+    array.copy_from_slice(&bytes[..N]);
+    array
+}
+
+/// Reads the points of an uncompressed LAS file, applying the header's scale
+/// and offset so coordinates come out in the file's georeferenced units.
+pub fn read(path: &Path, progress: &Progress) -> Result<Vec<Point>> {
+    let mut reader = loader::open(path, progress)?;
+
+    let mut header_bytes = vec![0; Header::SIZE_1_4];
+    let header_len = read_up_to(&mut reader, &mut header_bytes)?;
+    let header = Header::parse(&header_bytes[..header_len])?;
+
+    if header.record_length < header.min_record_length()? {
+        bail!(
+            "point records of {} bytes are too short for format {}",
+            header.record_length,
+            header.point_format
+        );
+    }
+
+    // A corrupt count must not reserve memory for more records than the
+    // file can hold; reading stops at its end either way.
+    let file_len = reader.seek(SeekFrom::End(0))?;
+    let room = file_len.saturating_sub(header.point_offset as u64) / header.record_length as u64;
+    reader.seek(SeekFrom::Start(header.point_offset as u64))?;
+
+    let rgb_offset = header.rgb_offset();
+    let count = usize::try_from(header.point_count).map_err(|_| anyhow!("too many points"))?;
+    let capacity = count.min(usize::try_from(room).unwrap_or(usize::MAX));
+    let mut record = vec![0; header.record_length];
+    let mut points = Vec::with_capacity(capacity);
+    let mut colors = Vec::with_capacity(if rgb_offset.is_some() { capacity } else { 0 });
+
+    for _ in 0..count {
+        reader.read_exact(&mut record)?;
+
+        let [x, y, z] = [0, 1, 2].map(|axis| {
+            let raw = i32::from_le_bytes(array(&record[axis * 4..]));
+            raw as f64 * header.scale[axis] + header.offset[axis]
+        });
+
+        points.push(Point {
+            x,
+            y,
+            z,
+            intensity: u16::from_le_bytes([record[12], record[13]]) as f32,
+            rgb: None,
+            classification: Some(header.classification(&record)),
+        });
+
+        if let Some(at) = rgb_offset {
+            colors.push([0, 1, 2].map(|channel| {
+                let at = at + channel * 2;
+                u16::from_le_bytes([record[at], record[at + 1]])
+            }));
+        }
+    }
+
+    // The specification asks for 16-bit colors, but plenty of writers store
+    // 8-bit values as they are.
+    let shift = if colors.iter().flatten().any(|&channel| channel > 255) {
+        8
+    } else {
+        0
+    };
+    for (point, [r, g, b]) in points.iter_mut().zip(colors) {
+        let [r, g, b] = [r, g, b].map(|channel| (channel >> shift) as u32);
+        point.rgb = Some((r << 16) | (g << 8) | b);
+    }
+
+    Ok(points)
+}
+
+/// Fills as much of `buffer` as the file holds.
+fn read_up_to(reader: &mut impl Read, buffer: &mut [u8]) -> Result<usize> {
+    let mut filled = 0;
+
+    while filled < buffer.len() {
+        match reader.read(&mut buffer[filled..])? {
+            0 => break,
+            read => filled += read,
+        }
+    }
+
+    Ok(filled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A LAS 1.2 file of format 0 or 6 records at `positions`, in
+    /// centimetres, with the raw classification byte of each, whose header
+    /// claims `point_count` points.
+    fn las(format: u8, records: &[([i32; 3], u8)], point_count: u32) -> Vec<u8> {
+        let (record_length, class_at) = match format {
+            0 => (20, 15),
+            6 => (30, 16),
+            _ => unimplemented!(),
+        };
+
+        let mut bytes = vec![0; Header::MIN_SIZE];
+        bytes[..4].copy_from_slice(b"LASF");
+        bytes[24..26].copy_from_slice(&[1, 2]);
+        bytes[94..96].copy_from_slice(&(Header::MIN_SIZE as u16).to_le_bytes());
+        bytes[96..100].copy_from_slice(&(Header::MIN_SIZE as u32).to_le_bytes());
+        bytes[104] = format;
+        bytes[105..107].copy_from_slice(&(record_length as u16).to_le_bytes());
+        bytes[107..111].copy_from_slice(&point_count.to_le_bytes());
+        for axis in 0..3 {
+            let at = 131 + axis * 8;
+            bytes[at..at + 8].copy_from_slice(&0.01f64.to_le_bytes());
+        }
+
+        for (position, class) in records {
+            let mut record = vec![0; record_length];
+            for (axis, value) in position.iter().enumerate() {
+                record[axis * 4..axis * 4 + 4].copy_from_slice(&value.to_le_bytes());
+            }
+            record[12..14].copy_from_slice(&500u16.to_le_bytes());
+            record[class_at] = *class;
+            bytes.extend(record);
+        }
+
+        bytes
+    }
+
+    #[test]
+    fn reads_scaled_records() {
+        // Ground, and a withheld building whose flag shares the class byte.
+        let records = [([100, -250, 12], 2), ([0, 0, 0], 0x80 | 6)];
+        let path = loader::fixture("scan.las", &las(0, &records, 2));
+
+        let points = read(&path, &Progress::default()).unwrap();
+
+        assert_eq!(points.len(), 2);
+        assert_eq!([points[0].x, points[0].y, points[0].z], [1.0, -2.5, 0.12]);
+        assert_eq!(points[0].intensity, 500.0);
+        assert_eq!(points[0].rgb, None);
+        assert_eq!(
+            points
+                .iter()
+                .map(|point| point.classification)
+                .collect::<Vec<_>>(),
+            [Some(2), Some(6)]
+        );
+
+        // Format 6 and later give the class a byte of its own.
+        let records = [([100, -250, 12], 2), ([0, 0, 0], 0x80 | 6)];
+        let path = loader::fixture("scan14.las", &las(6, &records, 2));
+
+        let points = read(&path, &Progress::default()).unwrap();
+
+        assert_eq!(points[0].intensity, 500.0);
+        assert_eq!(
+            points
+                .iter()
+                .map(|point| point.classification)
+                .collect::<Vec<_>>(),
+            [Some(2), Some(0x86)]
+        );
+    }
+
+    #[test]
+    fn a_corrupt_point_count_fails_without_reserving_for_it() {
+        let path = loader::fixture("corrupt.las", &las(0, &[([100, 200, 300], 0)], u32::MAX));
+
+        assert!(read(&path, &Progress::default()).is_err());
+    }
+}
@@ -0,0 +1,391 @@
+use std::{
+    fs::File,
+    io::{self, BufReader, Read, Seek, SeekFrom},
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        mpsc::{self, Receiver, TryRecvError},
+        Arc,
+    },
+    thread,
+};
+
+use anyhow::{anyhow, Result};
+
+use super::{fs, pointcloud::CloudData};
+
+/// How far a reader has got through its file, shared with the thread that
+/// shows it. Also carries the request to stop early.
+#[derive(Debug, Default)]
+pub struct Progress {
+    read: AtomicU64,
+    total: AtomicU64,
+    cancelled: AtomicBool,
+}
+
+impl Progress {
+    /// Fraction of the file read so far, or `None` when its size is unknown.
+    pub fn fraction(&self) -> Option<f32> {
+        let total = self.total.load(Ordering::Relaxed);
+        if total == 0 {
+            return None;
+        }
+
+        let read = self.read.load(Ordering::Relaxed);
+        Some((read as f64 / total as f64).min(1.0) as f32)
+    }
+
+    /// Makes every further read of the file fail, so the reader gives up at
+    /// its next read.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+/// Counts the bytes read through it into a [`Progress`].
+pub struct ProgressReader<'a, R> {
+    inner: R,
+    progress: &'a Progress,
+}
+
+impl<R: Read> Read for ProgressReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // Not `Interrupted`, which `read_exact` and friends quietly retry.
+        if self.progress.is_cancelled() {
+            return Err(io::Error::other("loading cancelled"));
+        }
+
+        let read = self.inner.read(buf)?;
+        self.progress.read.fetch_add(read as u64, Ordering::Relaxed);
+        Ok(read)
+    }
+}
+
+impl<R: Seek> Seek for ProgressReader<'_, R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = self.inner.seek(pos)?;
+        self.progress.read.store(position, Ordering::Relaxed);
+        Ok(position)
+    }
+}
+
+/// Opens `path` for reading with its progress reported to `progress`.
+pub fn open<'a>(
+    path: &Path,
+    progress: &'a Progress,
+) -> io::Result<BufReader<ProgressReader<'a, File>>> {
+    let file = File::open(fs::os_path(path))?;
+
+    let total = file.metadata().map_or(0, |metadata| metadata.len());
+    progress.total.store(total, Ordering::Relaxed);
+    progress.read.store(0, Ordering::Relaxed);
+
+    Ok(BufReader::new(ProgressReader {
+        inner: file,
+        progress,
+    }))
+}
+
+/// Writes `contents` to a file called `name`, in a directory of its own under
+/// the system temp directory, for tests of readers that take a path. The
+/// directory is removed when the returned guard is dropped.
+#[cfg(test)]
+pub fn fixture(name: &str, contents: &[u8]) -> Fixture {
+    static NEXT: AtomicU64 = AtomicU64::new(0);
+
+    let dir = std::env::temp_dir().join(format!(
+        "pcvisualizer-{}-{}",
+        std::process::id(),
+        NEXT.fetch_add(1, Ordering::Relaxed)
+    ));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let path = dir.join(name);
+    std::fs::write(&path, contents).unwrap();
+    Fixture { path }
+}
+
+/// The path of a file written by [`fixture`].
+#[cfg(test)]
+pub struct Fixture {
+    path: PathBuf,
+}
+
+#[cfg(test)]
+impl std::ops::Deref for Fixture {
+    type Target = PathBuf;
+
+    fn deref(&self) -> &PathBuf {
+        &self.path
+    }
+}
+
+#[cfg(test)]
+impl Drop for Fixture {
+    fn drop(&mut self) {
+        if let Some(dir) = self.path.parent() {
+            let _ = std::fs::remove_dir_all(dir);
+        }
+    }
+}
+
+/// What a finished [`LoadJob`] does with the clouds already shown.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LoadKind {
+    /// Replaces them.
+    Open,
+    /// Joins them.
+    Add,
+    /// Replaces the data of the cloud read from the same file, keeping its
+    /// color, visibility and transform.
+    Reload,
+}
+
+/// Reads `paths` on a few threads at once, reporting each file's progress
+/// to the [`Progress`] at the same index. The results are in the order of
+/// `paths`; a reader that panics fails only its own file.
+pub fn read_all(
+    paths: &[PathBuf],
+    progress: &[Progress],
+    read: &(impl Fn(&PathBuf, &Progress) -> Result<CloudData> + Sync),
+) -> Vec<Result<CloudData>> {
+    let workers = thread::available_parallelism()
+        .map_or(1, |count| count.get())
+        .min(paths.len());
+    let next = AtomicUsize::new(0);
+
+    let mut results: Vec<(usize, Result<CloudData>)> = thread::scope(|scope| {
+        let workers: Vec<_> = (0..workers)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut read_files = Vec::new();
+                    loop {
+                        let index = next.fetch_add(1, Ordering::Relaxed);
+                        let (Some(path), Some(progress)) = (paths.get(index), progress.get(index))
+                        else {
+                            break read_files;
+                        };
+                        let result = panic::catch_unwind(AssertUnwindSafe(|| read(path, progress)))
+                            .unwrap_or_else(|_| {
+                                Err(anyhow!("{}: reader panicked", path.display()))
+                            });
+                        read_files.push((index, result));
+                    }
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().expect("reads catch their own panics"))
+            .collect()
+    });
+
+    results.sort_by_key(|(index, _)| *index);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+/// Point clouds being read on worker threads. The GPU upload is left to the
+/// caller, which owns the device, so a job can be started before there is
+/// one.
+///
+/// Dropping the job cancels it, so replacing one job with another supersedes
+/// the earlier load.
+pub struct LoadJob {
+    paths: Vec<PathBuf>,
+    kind: LoadKind,
+    /// One per path.
+    progress: Arc<[Progress]>,
+    receiver: Receiver<Vec<Result<CloudData>>>,
+}
+
+impl LoadJob {
+    pub fn spawn(
+        paths: Vec<PathBuf>,
+        kind: LoadKind,
+        read: impl Fn(&PathBuf, &Progress) -> Result<CloudData> + Send + Sync + 'static,
+    ) -> Self {
+        let progress: Arc<[Progress]> = paths.iter().map(|_| Progress::default()).collect();
+        let (sender, receiver) = mpsc::channel();
+
+        let worker_paths = paths.clone();
+        let worker_progress = progress.clone();
+        thread::spawn(move || {
+            // The receiver is gone when the job was cancelled; nobody is left
+            // to tell.
+            let _ = sender.send(read_all(&worker_paths, &worker_progress, &read));
+        });
+
+        Self {
+            paths,
+            kind,
+            progress,
+            receiver,
+        }
+    }
+
+    /// The first file of the job, the only one for a reload.
+    pub fn path(&self) -> &Path {
+        &self.paths[0]
+    }
+
+    pub fn paths(&self) -> &[PathBuf] {
+        &self.paths
+    }
+
+    pub fn kind(&self) -> LoadKind {
+        self.kind
+    }
+
+    /// Fraction of the job read so far, each file counting the same, or
+    /// `None` while no file's size is known.
+    pub fn fraction(&self) -> Option<f32> {
+        let fractions: Vec<_> = self.progress.iter().map(Progress::fraction).collect();
+        if fractions.iter().all(Option::is_none) {
+            return None;
+        }
+
+        let read: f32 = fractions
+            .iter()
+            .map(|fraction| fraction.unwrap_or(0.0))
+            .sum();
+        Some(read / fractions.len() as f32)
+    }
+
+    /// The worker's results, in the order of the paths, once it has
+    /// finished, without blocking.
+    pub fn try_finish(&self) -> Option<Vec<Result<CloudData>>> {
+        match self.receiver.try_recv() {
+            Ok(results) => Some(results),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => {
+                Some(vec![Err(anyhow!("the loader thread stopped"))])
+            }
+        }
+    }
+}
+
+impl Drop for LoadJob {
+    fn drop(&mut self) {
+        for progress in self.progress.iter() {
+            progress.cancel();
+        }
+    }
+}
+
+/// How a run of queued reloads went, told once the queue is empty rather
+/// than once per file.
+#[derive(Debug, Default)]
+pub struct ReloadSummary {
+    reloaded: Vec<PathBuf>,
+    failed: usize,
+}
+
+impl ReloadSummary {
+    pub fn record(&mut self, path: &Path, reloaded: bool) {
+        if reloaded {
+            self.reloaded.push(path.to_path_buf());
+        } else {
+            self.failed += 1;
+        }
+    }
+
+    /// What to tell the user, starting the next run afresh. `None` when no
+    /// file was reloaded; each failure has already said why.
+    pub fn take_message(&mut self) -> Option<String> {
+        let summary = std::mem::take(self);
+        match (summary.reloaded.as_slice(), summary.failed) {
+            ([], _) => None,
+            ([path], 0) => Some(format!("Reloaded {}", path.display())),
+            (reloaded, 0) => Some(format!("Reloaded {} files", reloaded.len())),
+            (reloaded, failed) => Some(format!(
+                "Reloaded {} of {} files",
+                reloaded.len(),
+                reloaded.len() + failed
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::pointcloud::{LoadOptions, PointCloud};
+
+    #[test]
+    fn reload_summary_counts_each_run_once() {
+        let mut summary = ReloadSummary::default();
+        assert_eq!(summary.take_message(), None);
+
+        summary.record(Path::new("a.pcd"), true);
+        assert_eq!(summary.take_message().as_deref(), Some("Reloaded a.pcd"));
+        assert_eq!(summary.take_message(), None);
+
+        summary.record(Path::new("a.pcd"), true);
+        summary.record(Path::new("b.pcd"), true);
+        assert_eq!(summary.take_message().as_deref(), Some("Reloaded 2 files"));
+
+        summary.record(Path::new("a.pcd"), true);
+        summary.record(Path::new("b.pcd"), false);
+        summary.record(Path::new("c.pcd"), true);
+        assert_eq!(
+            summary.take_message().as_deref(),
+            Some("Reloaded 2 of 3 files")
+        );
+
+        summary.record(Path::new("b.pcd"), false);
+        assert_eq!(summary.take_message(), None);
+    }
+
+    #[test]
+    fn fixtures_clean_up_after_themselves() {
+        let fixture = fixture("scan.xyz", b"1 2 3\n");
+        let dir = fixture.parent().unwrap().to_path_buf();
+        assert_eq!(std::fs::read(&*fixture).unwrap(), b"1 2 3\n");
+
+        drop(fixture);
+        assert!(!dir.exists());
+    }
+
+    #[test]
+    fn read_all_keeps_order_and_fails_files_alone() {
+        // More files than most machines have cores, so workers share them.
+        let fixtures: Vec<_> = (1..=12)
+            .map(|count| {
+                let contents = match count {
+                    5 => "1 2 3\n4 five 6\n".to_string(),
+                    _ => "1 2 3\n".repeat(count),
+                };
+                fixture(&format!("{}.xyz", count), contents.as_bytes())
+            })
+            .collect();
+        let paths: Vec<PathBuf> = fixtures
+            .iter()
+            .map(|fixture| fixture.to_path_buf())
+            .collect();
+        let progress: Vec<_> = paths.iter().map(|_| Progress::default()).collect();
+
+        let results = read_all(&paths, &progress, &|path, progress| {
+            PointCloud::read(path, LoadOptions::default(), progress)
+        });
+
+        assert_eq!(results.len(), paths.len());
+        for (index, result) in results.iter().enumerate() {
+            match result {
+                Err(e) => {
+                    assert_eq!(index, 4);
+                    assert!(format!("{:#}", e).contains("line 2"), "{:#}", e);
+                }
+                Ok(data) => {
+                    assert_eq!(data.path(), &paths[index]);
+                    assert_eq!(progress[index].fraction(), Some(1.0));
+                }
+            }
+        }
+        assert!(results[4].is_err());
+    }
+}
@@ -0,0 +1,346 @@
+use std::ops::Range;
+
+use cgmath::{Matrix4, MetricSpace, Point3, Transform};
+
+/// Points a leaf holds before it is split further.
+const LEAF_SIZE: usize = 65_536;
+/// Stops splitting piles of coincident points.
+const MAX_DEPTH: u32 = 10;
+
+/// A leaf of the octree: a contiguous run of instances, shuffled so that any
+/// prefix of it is an even subsample of the whole.
+#[derive(Clone, Debug)]
+pub struct Node {
+    pub range: Range<usize>,
+    /// Bounding sphere of the node's points.
+    pub center: [f32; 3],
+    pub radius: f32,
+}
+
+impl Node {
+    /// A node over `items`, which sit at `range` of the whole buffer.
+    fn new<T>(range: Range<usize>, items: &[T], position: impl Fn(&T) -> [f32; 3]) -> Self {
+        let (center, radius) = bounding_box(items, position).map_or(([0.0; 3], 0.0), sphere);
+        Self {
+            range,
+            center,
+            radius,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.range.len()
+    }
+
+    /// Recomputes the bounding sphere from the node's range of `items`, after
+    /// they have moved to another space.
+    pub fn fit<T>(&mut self, items: &[T], position: impl Fn(&T) -> [f32; 3]) {
+        if let Some(bounds) = bounding_box(&items[self.range.clone()], position) {
+            (self.center, self.radius) = sphere(bounds);
+        }
+    }
+}
+
+/// Sphere around a box given by its corners.
+fn sphere((min, max): ([f32; 3], [f32; 3])) -> ([f32; 3], f32) {
+    let center = [0, 1, 2].map(|axis| (min[axis] + max[axis]) / 2.0);
+    let radius = Point3::from(min).distance(Point3::from(max)) / 2.0;
+    (center, radius)
+}
+
+/// Reorders `items` into octree leaves and returns the leaves in buffer
+/// order. Runs on the loader thread, so the render loop only has to pick how
+/// much of each leaf to draw.
+pub fn build<T>(items: &mut [T], position: impl Fn(&T) -> [f32; 3] + Copy) -> Vec<Node> {
+    let mut nodes = Vec::new();
+    let Some((min, max)) = bounding_box(items, position) else {
+        return nodes;
+    };
+
+    split(items, 0, min, max, 0, position, &mut nodes);
+
+    let mut random = Random(0x9E37_79B9_7F4A_7C15);
+    for node in &nodes {
+        random.shuffle(&mut items[node.range.clone()]);
+    }
+
+    nodes
+}
+
+fn split<T>(
+    items: &mut [T],
+    offset: usize,
+    min: [f32; 3],
+    max: [f32; 3],
+    depth: u32,
+    position: impl Fn(&T) -> [f32; 3] + Copy,
+    nodes: &mut Vec<Node>,
+) {
+    if items.len() <= LEAF_SIZE || depth == MAX_DEPTH {
+        nodes.push(Node::new(offset..offset + items.len(), items, position));
+        return;
+    }
+
+    let mid = [0, 1, 2].map(|axis| (min[axis] + max[axis]) / 2.0);
+
+    // Halve along x, then each half along y, then each quarter along z, so
+    // the eight octants end up contiguous.
+    let mut octants = vec![(0..items.len(), min, max)];
+    for axis in 0..3 {
+        octants = octants
+            .into_iter()
+            .flat_map(|(range, low, high)| {
+                let at = range.start
+                    + partition(&mut items[range.clone()], |item| {
+                        position(item)[axis] < mid[axis]
+                    });
+
+                let mut lower_high = high;
+                lower_high[axis] = mid[axis];
+                let mut upper_low = low;
+                upper_low[axis] = mid[axis];
+
+                [
+                    (range.start..at, low, lower_high),
+                    (at..range.end, upper_low, high),
+                ]
+            })
+            .collect();
+    }
+
+    for (range, low, high) in octants {
+        if !range.is_empty() {
+            let start = range.start;
+            split(
+                &mut items[range],
+                offset + start,
+                low,
+                high,
+                depth + 1,
+                position,
+                nodes,
+            );
+        }
+    }
+}
+
+/// Moves the items matching `predicate` to the front, returning how many
+/// there are.
+fn partition<T>(items: &mut [T], predicate: impl Fn(&T) -> bool) -> usize {
+    let mut split = 0;
+    for index in 0..items.len() {
+        if predicate(&items[index]) {
+            items.swap(split, index);
+            split += 1;
+        }
+    }
+    split
+}
+
+fn bounding_box<T>(items: &[T], position: impl Fn(&T) -> [f32; 3]) -> Option<([f32; 3], [f32; 3])> {
+    items.iter().map(position).fold(None, |bounds, p| {
+        let (min, max) = bounds.unwrap_or((p, p));
+        Some((
+            [0, 1, 2].map(|axis| min[axis].min(p[axis])),
+            [0, 1, 2].map(|axis| max[axis].max(p[axis])),
+        ))
+    })
+}
+
+/// Deterministic xorshift, so a file loads into the same order every time.
+struct Random(u64);
+
+impl Random {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// Fisher–Yates.
+    fn shuffle<T>(&mut self, items: &mut [T]) {
+        for index in (1..items.len()).rev() {
+            let other = (self.next() % (index as u64 + 1)) as usize;
+            items.swap(index, other);
+        }
+    }
+}
+
+/// A node as the camera sees it this frame.
+pub struct View {
+    pub len: usize,
+    /// Squared ratio of the node's radius to its distance, roughly the share
+    /// of the screen it covers.
+    pub coverage: f32,
+}
+
+impl View {
+    pub fn new(node: &Node, model: &Matrix4<f32>, eye: Point3<f32>) -> Self {
+        let center = model.transform_point(Point3::from(node.center));
+        let distance = center.distance(eye).max(f32::EPSILON);
+
+        Self {
+            len: node.len(),
+            coverage: (node.radius / distance).min(1.0).powi(2),
+        }
+    }
+}
+
+/// How many points of each node to draw so the total stays within `budget`.
+/// Nodes covering more of the screen keep a larger share of their points.
+pub fn select(views: &[View], budget: usize) -> Vec<usize> {
+    let total: usize = views.iter().map(|view| view.len).sum();
+    if total <= budget {
+        return views.iter().map(|view| view.len).collect();
+    }
+
+    // Each node keeps `density * coverage` of its points, capped at all of
+    // them; bisect for the density that fills the budget.
+    let share =
+        |view: &View, density: f32| (view.len as f32 * (density * view.coverage).min(1.0)) as usize;
+    let drawn = |density: f32| -> usize { views.iter().map(|view| share(view, density)).sum() };
+
+    let (mut low, mut high) = (0.0f32, 1.0f32);
+    while drawn(high) < budget && high < 1e30 {
+        high *= 2.0;
+    }
+    for _ in 0..32 {
+        let mid = (low + high) / 2.0;
+        if drawn(mid) <= budget {
+            low = mid;
+        } else {
+            high = mid;
+        }
+    }
+
+    views.iter().map(|view| share(view, low)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Items tagged with their index before the build reorders them.
+    fn items(positions: impl Iterator<Item = [f32; 3]>) -> Vec<(usize, [f32; 3])> {
+        positions.enumerate().collect()
+    }
+
+    /// Checks that the leaves tile the buffer in order, that each holds the
+    /// points of its sphere, and that every item is still there once.
+    fn assert_leaves_cover(items: &[(usize, [f32; 3])], nodes: &[Node]) {
+        let mut end = 0;
+        for node in nodes {
+            assert_eq!(node.range.start, end);
+            end = node.range.end;
+
+            assert!(node.center.iter().all(|v| v.is_finite()) && node.radius.is_finite());
+            for (_, position) in &items[node.range.clone()] {
+                let distance = Point3::from(*position).distance(Point3::from(node.center));
+                assert!(distance <= node.radius * (1.0 + 1e-5) + 1e-6);
+            }
+        }
+        assert_eq!(end, items.len());
+
+        let mut ids: Vec<usize> = items.iter().map(|(id, _)| *id).collect();
+        ids.sort_unstable();
+        assert!(ids.into_iter().eq(0..items.len()));
+    }
+
+    #[test]
+    fn leaves_cover_every_instance_once() {
+        let mut random = Random(7);
+        let mut unit = || (random.next() % 1_000_000) as f32 / 1e4;
+        let mut items = items((0..4 * LEAF_SIZE).map(|_| [unit(), unit(), unit()]));
+        let mut again = items.clone();
+
+        let nodes = build(&mut items, |item| item.1);
+
+        assert!(nodes.len() > 1);
+        assert!(nodes.iter().all(|node| node.len() <= LEAF_SIZE));
+        assert_leaves_cover(&items, &nodes);
+
+        // The shuffle is the same on every load.
+        build(&mut again, |item| item.1);
+        assert_eq!(again, items);
+    }
+
+    fn views() -> Vec<View> {
+        [
+            (1000, 1.0),
+            (5000, 0.2),
+            (20_000, 0.01),
+            (300, 0.0),
+            (70_000, 0.5),
+        ]
+        .into_iter()
+        .map(|(len, coverage)| View { len, coverage })
+        .collect()
+    }
+
+    #[test]
+    fn selection_stays_within_the_budget() {
+        let views = views();
+        let total: usize = views.iter().map(|view| view.len).sum();
+
+        for budget in [0, 1, 100, 5_000, 50_000, total - 1] {
+            let selected = select(&views, budget);
+
+            assert!(selected.iter().sum::<usize>() <= budget, "{}", budget);
+            for (count, view) in selected.iter().zip(&views) {
+                assert!(*count <= view.len);
+            }
+        }
+
+        let all: Vec<usize> = views.iter().map(|view| view.len).collect();
+        assert_eq!(select(&views, total), all);
+        assert_eq!(select(&views, usize::MAX), all);
+    }
+
+    #[test]
+    fn selection_grows_with_the_budget() {
+        let views = views();
+        let total: usize = views.iter().map(|view| view.len).sum();
+
+        let mut previous = vec![0; views.len()];
+        for budget in (0..=total).step_by(997) {
+            let selected = select(&views, budget);
+            for (count, before) in selected.iter().zip(&previous) {
+                assert!(
+                    count >= before,
+                    "{}: {:?} < {:?}",
+                    budget,
+                    selected,
+                    previous
+                );
+            }
+            previous = selected;
+        }
+    }
+
+    #[test]
+    fn degenerate_clouds_split_into_finite_leaves() {
+        let count = 2 * LEAF_SIZE + 1;
+        let side = (count as f32).sqrt().ceil() as usize;
+
+        let coplanar = items((0..count).map(|i| [(i % side) as f32, (i / side) as f32, 5.0]));
+        let collinear = items((0..count).map(|i| [0.0, i as f32 * 1e-3, 0.0]));
+        let coincident = items((0..count).map(|_| [1.0, 2.0, 3.0]));
+
+        for (name, mut items) in [("coplanar", coplanar), ("collinear", collinear)] {
+            let nodes = build(&mut items, |item| item.1);
+
+            assert!(nodes.len() > 1, "{}", name);
+            assert!(nodes.iter().all(|node| node.len() <= LEAF_SIZE), "{}", name);
+            assert_leaves_cover(&items, &nodes);
+        }
+
+        // Splitting can never separate the pile, so it stops at the depth
+        // limit with everything in one leaf.
+        let mut items = coincident;
+        let nodes = build(&mut items, |item| item.1);
+        assert_eq!(nodes.len(), 1);
+        assert_eq!((nodes[0].center, nodes[0].radius), ([1.0, 2.0, 3.0], 0.0));
+        assert_leaves_cover(&items, &nodes);
+    }
+}
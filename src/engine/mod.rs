@@ -1,29 +1,70 @@
+mod action;
+mod adapter;
 mod camera;
+mod cull;
+mod edl;
+mod fs;
 mod geometry;
 mod gui;
+mod las;
+mod loader;
+mod lod;
+mod notification;
+mod number;
+mod ply;
 mod pointcloud;
+mod settings;
+mod share;
+mod startup;
+mod text;
 mod texture;
+mod timing;
+mod up_axis;
+mod voxel;
 
-use std::{path::PathBuf, sync::Arc};
+use std::{
+    collections::VecDeque,
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{self, Receiver, TryRecvError},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
 
+use action::Action;
+pub use adapter::{describe as describe_adapter, list_adapters, AdapterOptions, Backend};
 use camera::Camera;
+use cgmath::Point3;
+use edl::EyeDome;
 use egui_wgpu::ScreenDescriptor;
-use gui::EguiRender;
-use pointcloud::PointCloud;
+use gui::{
+    CloudEntry, CloudInfo, EguiRender, FrameStats, GuiState, InputCapture, Loading, UpAxisAnswer,
+    UpAxisPrompt,
+};
+use loader::ReloadSummary;
+pub use loader::{LoadJob, LoadKind};
+use notification::Notifications;
+use number::NumberFormat;
+use pointcloud::{Bounds, PointCloud, SizeMode, Transform};
+pub use pointcloud::{CloudData, ColorMode, LoadOptions};
 use pollster::FutureExt;
+pub use settings::{Overrides, Settings};
+use settings::{RecentFiles, WindowSettings};
+pub use share::{parse_camera, register_scheme, ShareLink};
+pub use startup::{Stage, StartupReport};
 use texture::Texture;
+use timing::FrameTimes;
+use up_axis::UpAxis;
 use wgpu::{
-    Backends, Color, CommandEncoderDescriptor, Device, DeviceDescriptor, Features, Instance,
-    InstanceDescriptor, Limits, Operations, PowerPreference, Queue, RenderPassColorAttachment,
-    RenderPassDescriptor, RequestAdapterOptions, Surface, SurfaceConfiguration, SurfaceError,
-    TextureUsages, TextureViewDescriptor,
-};
-use winit::{
-    dpi::PhysicalSize,
-    event::{ElementState, KeyEvent, WindowEvent},
-    keyboard::{KeyCode, PhysicalKey},
-    window::Window,
+    Adapter, Color, CommandEncoderDescriptor, Device, DeviceDescriptor, Features, Limits, Maintain,
+    Operations, Queue, RenderPassColorAttachment, RenderPassDescriptor, Surface,
+    SurfaceConfiguration, SurfaceError, TextureFormat, TextureUsages, TextureViewDescriptor,
 };
+use winit::{dpi::PhysicalSize, event::WindowEvent, keyboard::ModifiersState, window::Window};
 
 pub struct Engine {
     size: PhysicalSize<u32>,
@@ -32,38 +73,105 @@ pub struct Engine {
     device: Device,
     queue: Queue,
     depth_texture: Texture,
+    /// The frame is drawn here and resolved to the surface when
+    /// multisampling.
+    msaa_texture: Option<Texture>,
+    msaa_samples: u32,
+    /// Sample counts the surface and depth formats both allow, ascending.
+    supported_samples: Vec<u32>,
+    /// Name, backend and kind of the GPU in use.
+    adapter_name: String,
+    present_mode: PresentMode,
+    /// Modes the surface offers; vsync always among them.
+    supported_present_modes: Vec<PresentMode>,
+    eye_dome: EyeDome,
     gui: EguiRender,
     window: Arc<Window>,
     camera: Camera,
     pointcloud: PointCloud,
+    last_update: Instant,
+    /// The file being read in the background, and the share link to apply
+    /// once it is shown.
+    loading: Option<(LoadJob, Option<ShareLink>)>,
+    /// Files to reload once `loading` is done, oldest request first.
+    reload_queue: VecDeque<PathBuf>,
+    reload_summary: ReloadSummary,
+    /// The pending dialog, and whether its file is added rather than
+    /// replacing the loaded ones.
+    open_dialog: Option<(Receiver<Option<PathBuf>>, bool)>,
+    modifiers: ModifiersState,
+    watch_files: bool,
+    last_watch_poll: Instant,
+    load_options: LoadOptions,
+    /// Voxel size shown in the panel, kept while downsampling is off.
+    voxel_size: f64,
+    notifications: Notifications,
+    frame_times: FrameTimes,
+    show_frame_stats: bool,
+    /// Draw frames back to back instead of only when something changed, for
+    /// benchmarking.
+    continuous_redraw: bool,
+    /// sRGB, as picked in the panel.
+    background_color: [u8; 3],
+    number_format: NumberFormat,
+    /// Extensions of files turned from Y-up to Z-up without asking.
+    y_up_extensions: Vec<String>,
+    /// A loaded file that looks Y-up, until the user says what to do.
+    up_axis_prompt: Option<PathBuf>,
+    /// When false egui neither draws nor sees input, and keeps its state for
+    /// when it is shown again.
+    show_gui: bool,
+    recent_files: RecentFiles,
+    saved_settings: Settings,
+    /// Given for this run only; kept out of the saved settings until changed.
+    overrides: Overrides,
+    /// Settings that differ from the saved ones, and when they last changed.
+    pending_settings: Option<(Settings, Instant)>,
+    /// How long startup took, once the first frame has been presented.
+    startup_report: Option<StartupReport>,
+    /// Of the frame drawn last.
+    frame_size: Option<FrameSize>,
 }
 
 impl Engine {
-    pub fn new(window: Window) -> Self {
+    /// How long settings have to stay unchanged before they are written.
+    const SAVE_DELAY: Duration = Duration::from_secs(1);
+    const WATCH_INTERVAL: Duration = Duration::from_millis(500);
+
+    /// `settings` should be the ones the window was created from, so the
+    /// saved window size is kept while it is maximized.
+    pub fn new(
+        window: Window,
+        settings: Settings,
+        adapter_options: &AdapterOptions,
+        startup: &mut StartupReport,
+    ) -> Self {
         let window_arc = Arc::new(window);
         let size = window_arc.inner_size();
 
-        let instance = Instance::new(InstanceDescriptor {
-            backends: Backends::PRIMARY,
-            ..Default::default()
-        });
+        let instance = adapter_options.create_instance();
 
         let surface = instance.create_surface(window_arc.clone()).unwrap();
+        startup.stage(Stage::Surface);
 
-        let adapter = instance
-            .request_adapter(&RequestAdapterOptions {
-                power_preference: PowerPreference::default(),
-                compatible_surface: Some(&surface),
-                force_fallback_adapter: false,
-            })
-            .block_on()
-            .unwrap();
+        let mut notifications = Notifications::default();
+        let (adapter, passed_over) = adapter_options.request_adapter(&instance, &surface);
+        if let Some(message) = passed_over {
+            log::warn!("{}", message);
+            notifications.error(message);
+        }
+        let adapter_name = describe_adapter(&adapter.get_info());
+        log::info!("rendering with {}", adapter_name);
+        startup.stage(Stage::Adapter);
 
         let (device, queue) = adapter
             .request_device(
                 &DeviceDescriptor {
                     label: None,
-                    required_features: Features::empty(),
+                    // Lets formats use the sample counts the adapter offers
+                    // beyond the 1 and 4 every adapter has.
+                    required_features: adapter.features()
+                        & Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES,
                     required_limits: Limits::default(),
                     ..Default::default()
                 },
@@ -71,6 +179,7 @@ impl Engine {
             )
             .block_on()
             .unwrap();
+        startup.stage(Stage::Device);
 
         let surface_caps = surface.get_capabilities(&adapter);
 
@@ -86,27 +195,49 @@ impl Engine {
             format: surface_format,
             width: size.width,
             height: size.height,
-            present_mode: wgpu::PresentMode::AutoVsync,
+            present_mode: PresentMode::Vsync.to_wgpu(),
             alpha_mode: surface_caps.alpha_modes[0],
             view_formats: vec![],
             desired_maximum_frame_latency: 2,
         };
 
         surface.configure(&device, &config);
+        startup.stage(Stage::SurfaceConfiguration);
 
-        let camera = Camera::new(
+        let mut camera = Camera::new(
             (0.5, 0.5, 0.5).into(),
             (0.0, 0.0, 0.0).into(),
             (0.0, 0.0, 1.0).into(),
             config.width as f32 / config.height as f32,
             45.0,
         );
+        camera.set_sensitivity(settings.sensitivity);
 
-        let depth_texture = Texture::create_depth_texture(&device, &config, "depth_texture");
+        let supported_samples = supported_sample_counts(&adapter, &device, config.format);
+        // AutoVsync falls back to Fifo, which every surface supports.
+        let supported_present_modes = PresentMode::ALL
+            .into_iter()
+            .filter(|mode| {
+                *mode == PresentMode::Vsync || surface_caps.present_modes.contains(&mode.to_wgpu())
+            })
+            .collect();
+        let depth_texture = Texture::create_depth_texture(&device, &config, 1, "depth_texture");
+        let eye_dome = EyeDome::new(&device, &config, &depth_texture, 1);
 
         let gui = EguiRender::new(&device, config.format, None, 1, window_arc.clone());
+        startup.stage(Stage::Egui);
 
-        let pointcloud = PointCloud::new(&device, &camera, window_arc.clone(), &config);
+        let mut recent_files = settings.recent_files.clone();
+        recent_files.prune_missing();
+
+        let mut pointcloud = PointCloud::new(&device, &camera, window_arc.clone(), &config);
+        if settings.point_size.is_finite() {
+            pointcloud.set_point_size(settings.point_size);
+        }
+        pointcloud.set_point_budget(settings.point_budget);
+        pointcloud.set_shape(settings.point_shape);
+        pointcloud.set_color_mode(settings.color_mode);
+        startup.stage(Stage::PointPipeline);
 
         Self {
             size,
@@ -115,10 +246,42 @@ impl Engine {
             device,
             queue,
             depth_texture,
+            msaa_texture: None,
+            msaa_samples: 1,
+            supported_samples,
+            adapter_name,
+            present_mode: PresentMode::Vsync,
+            supported_present_modes,
+            eye_dome,
             gui,
             window: window_arc,
             camera,
             pointcloud,
+            last_update: Instant::now(),
+            loading: None,
+            reload_queue: VecDeque::new(),
+            reload_summary: ReloadSummary::default(),
+            open_dialog: None,
+            modifiers: ModifiersState::default(),
+            watch_files: false,
+            last_watch_poll: Instant::now(),
+            load_options: LoadOptions::default(),
+            voxel_size: 0.05,
+            notifications,
+            frame_times: FrameTimes::new(),
+            show_frame_stats: false,
+            continuous_redraw: settings.continuous_redraw,
+            background_color: settings.background_color,
+            number_format: settings.number_format,
+            y_up_extensions: settings.y_up_extensions.clone(),
+            up_axis_prompt: None,
+            show_gui: true,
+            recent_files,
+            saved_settings: settings,
+            overrides: Overrides::default(),
+            pending_settings: None,
+            startup_report: None,
+            frame_size: None,
         }
     }
 
@@ -131,44 +294,205 @@ impl Engine {
     }
 
     pub fn input(&mut self, event: &WindowEvent) -> bool {
-        if self.gui.input(&self.window, &event) {
+        let capture = if self.show_gui {
+            self.gui.input(&self.window, event)
+        } else {
+            InputCapture::default()
+        };
+
+        if capture.repaint {
+            self.window.request_redraw();
+        }
+
+        // Keys go to egui from now on, releases included.
+        if capture.keyboard {
+            self.camera.release_keys();
+        }
+
+        if capture.blocks(event) {
             return true;
         }
 
-        if self.camera.process_event(event, &self.window) {
+        if let WindowEvent::ModifiersChanged(modifiers) = event {
+            self.modifiers = modifiers.state();
+        }
+
+        if let Some(action) = Action::from_event(event, capture, self.modifiers) {
+            if matches!(event, WindowEvent::KeyboardInput { .. }) {
+                self.camera.chord_pressed();
+            }
+            self.dispatch(action);
+            self.window.request_redraw();
             return true;
         }
 
-        match event {
-            WindowEvent::KeyboardInput {
-                event:
-                    KeyEvent {
-                        physical_key: PhysicalKey::Code(keycode),
-                        state: ElementState::Pressed,
-                        ..
-                    },
-                ..
-            } => match keycode {
-                KeyCode::KeyJ => self
-                    .pointcloud
-                    .set_point_size(self.pointcloud.point_size() - 0.1),
-                KeyCode::KeyK => self
-                    .pointcloud
-                    .set_point_size(self.pointcloud.point_size() + 0.1),
-                _ => return false,
+        let consumed = self.camera.process_event(event, &self.window);
+        if consumed {
+            self.window.request_redraw();
+        }
+        consumed
+    }
+
+    pub fn dispatch(&mut self, action: Action) {
+        match action {
+            Action::DecreasePointSize => self
+                .pointcloud
+                .set_point_size(self.pointcloud.point_size() - self.pointcloud.point_size_step()),
+            Action::IncreasePointSize => self
+                .pointcloud
+                .set_point_size(self.pointcloud.point_size() + self.pointcloud.point_size_step()),
+            Action::ToggleSizeMode => {
+                self.pointcloud
+                    .set_size_mode(match self.pointcloud.size_mode() {
+                        SizeMode::Pixel => SizeMode::World,
+                        SizeMode::World => SizeMode::Pixel,
+                    })
+            }
+            Action::CycleColorMode => self
+                .pointcloud
+                .set_color_mode(self.pointcloud.color_mode().next()),
+            Action::FitView => self.frame_cloud(),
+            Action::ResetView => self.camera.reset(),
+            Action::SetView(view) => self.camera.set_view(view),
+            Action::ToggleNavigationMode => self
+                .camera
+                .set_navigation_mode(self.camera.navigation_mode().toggled()),
+            Action::ToggleNormalization => {
+                self.pointcloud.set_normalized(
+                    !self.pointcloud.is_normalized(),
+                    &self.device,
+                    &self.queue,
+                );
+                self.frame_cloud();
+                self.camera.set_home();
+            }
+            Action::CopyShareLink => match self.share_link() {
+                Some(link) => {
+                    self.gui.copy_text(link.to_string());
+                    self.notifications
+                        .info("Share link copied to the clipboard");
+                }
+                None => self.notifications.error("Nothing loaded to share"),
             },
-            WindowEvent::DroppedFile(path) => {
-                self.set_pcd(path);
+            Action::OpenFile => self.open_file_dialog(false),
+            Action::ToggleFrameStats => self.show_frame_stats = !self.show_frame_stats,
+            Action::ToggleGui => {
+                self.show_gui = !self.show_gui;
+                self.update_title();
             }
-            _ => return false,
+            Action::LoadFile(path) => self.set_pcd(&path),
+            Action::AddFile(path) => self.add_pcd(&path),
+            Action::Clear => self.clear(),
+            Action::Reload => self.reload(),
         }
-
-        true
     }
 
     pub fn update(&mut self) {
-        self.pointcloud
-            .update(&self.camera, &self.queue, &self.window);
+        // Cap the step so a stall (window drag, breakpoint) does not teleport
+        // the camera on the next frame.
+        const MAX_STEP: Duration = Duration::from_millis(100);
+
+        let now = Instant::now();
+        let dt = (now - self.last_update).min(MAX_STEP);
+        self.last_update = now;
+        self.camera.update(dt);
+        self.notifications.expire();
+
+        // Follow zoom and movement so depth precision is spent on the cloud.
+        if let Some((center, radius)) = self.pointcloud.bounding_sphere() {
+            self.camera.fit_clip_planes(center.into(), radius);
+        }
+
+        // Reconfigure before acquiring so the next surface texture already
+        // matches the window instead of lagging a frame behind a live resize.
+        let window_size = self.window.inner_size();
+        if window_size != self.size {
+            self.resize(window_size);
+        }
+    }
+
+    /// Does the work that does not wait for a frame, and asks for one when
+    /// something on screen is still changing. Returns when to call again at
+    /// the latest, or `None` to wait for the next event.
+    pub fn poll(&mut self) -> Option<Instant> {
+        // How often background work is checked on while it runs.
+        const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+        let now = Instant::now();
+
+        let finished = self.loading.as_ref().and_then(|(job, _)| job.try_finish());
+        if let Some(results) = finished {
+            if let Some((job, link)) = self.loading.take() {
+                self.finish_load(&job, results, link);
+            }
+        }
+        // Reloads wait for whatever the user asked to load.
+        if self.loading.is_none() {
+            if let Some(path) = self.reload_queue.pop_front() {
+                self.start_load(&path, LoadKind::Reload, None);
+            } else if let Some(message) = self.reload_summary.take_message() {
+                self.notifications.info(message);
+            }
+        }
+
+        if let Some((dialog, add)) = &self.open_dialog {
+            match dialog.try_recv() {
+                Ok(path) => {
+                    let add = *add;
+                    self.open_dialog = None;
+                    match path {
+                        Some(path) if add => self.add_pcd(&path),
+                        Some(path) => self.set_pcd(&path),
+                        None => {}
+                    }
+                }
+                Err(TryRecvError::Disconnected) => self.open_dialog = None,
+                Err(TryRecvError::Empty) => {}
+            }
+        }
+
+        self.save_settings_when_idle(now);
+
+        if self.watch_files && now - self.last_watch_poll >= Self::WATCH_INTERVAL {
+            self.last_watch_poll = now;
+            self.reload_changed_files();
+        }
+
+        // A culled point count arriving from the GPU only matters to the
+        // panel.
+        let _ = self.device.poll(Maintain::Poll);
+        if self.pointcloud.poll_readback() && self.show_gui {
+            self.window.request_redraw();
+        }
+
+        let animating = self.continuous_redraw
+            || self.camera.is_moving()
+            || self.pointcloud.is_refining()
+            || (self.show_gui && !self.notifications.active().is_empty());
+        if animating {
+            self.window.request_redraw();
+        }
+
+        let repaint_at = self.gui.repaint_at().filter(|_| self.show_gui);
+        if repaint_at.is_some_and(|at| at <= now) {
+            self.window.request_redraw();
+        }
+
+        let polling = self.loading.is_some()
+            || self.open_dialog.is_some()
+            || self.pointcloud.is_reading_back();
+        [
+            repaint_at.filter(|&at| at > now),
+            polling.then(|| now + POLL_INTERVAL),
+            self.watch_files
+                .then(|| self.last_watch_poll + Self::WATCH_INTERVAL),
+            self.pending_settings
+                .as_ref()
+                .map(|(_, changed)| *changed + Self::SAVE_DELAY),
+        ]
+        .into_iter()
+        .flatten()
+        .min()
     }
 
     pub fn resize(&mut self, new_size: PhysicalSize<u32>) {
@@ -179,13 +503,103 @@ impl Engine {
             self.surface.configure(&self.device, &self.config);
             self.camera
                 .set_aspect(new_size.width as f32 / new_size.height as f32);
-            self.depth_texture =
-                Texture::create_depth_texture(&self.device, &self.config, "depth_texture");
+            self.create_render_targets();
+            self.window.request_redraw();
+        }
+    }
+
+    /// Recreates the surface-sized textures for the current size and sample
+    /// count.
+    fn create_render_targets(&mut self) {
+        let samples = self.msaa_samples;
+        self.depth_texture =
+            Texture::create_depth_texture(&self.device, &self.config, samples, "depth_texture");
+        self.msaa_texture = (samples > 1).then(|| {
+            Texture::create_color_target(&self.device, &self.config, samples, "msaa_texture")
+        });
+        self.eye_dome
+            .resize(&self.device, &self.config, &self.depth_texture, samples);
+    }
+
+    /// Switches how frames are presented, falling back to vsync when the
+    /// surface does not offer `mode`.
+    pub fn set_present_mode(&mut self, mut mode: PresentMode) {
+        if !self.supported_present_modes.contains(&mode) {
+            self.notifications.info(format!(
+                "The {} present mode is not supported here; using vsync",
+                mode.name()
+            ));
+            mode = PresentMode::Vsync;
+        }
+        if mode == self.present_mode {
+            return;
+        }
+
+        self.present_mode = mode;
+        self.config.present_mode = mode.to_wgpu();
+        self.surface.configure(&self.device, &self.config);
+        self.window.request_redraw();
+    }
+
+    /// Multisamples the point and panel passes with `requested` samples, or
+    /// the most below it the adapter supports.
+    pub fn set_msaa_samples(&mut self, requested: u32) {
+        let samples = self
+            .supported_samples
+            .iter()
+            .copied()
+            .filter(|&samples| samples <= requested)
+            .max()
+            .unwrap_or(1);
+        if samples != requested {
+            self.notifications.info(format!(
+                "{}x MSAA is not supported here; using {}x",
+                requested, samples
+            ));
         }
+        if samples == self.msaa_samples {
+            return;
+        }
+
+        self.msaa_samples = samples;
+        self.pointcloud.set_sample_count(samples, &self.device);
+        self.gui.set_msaa_samples(
+            &self.device,
+            self.config.format,
+            samples,
+            self.window.clone(),
+        );
+        self.create_render_targets();
     }
 
     pub fn render(&mut self) -> Result<(), SurfaceError> {
-        let output = self.surface.get_current_texture()?;
+        self.frame_times.begin_frame();
+
+        let output = match self.surface.get_current_texture() {
+            Ok(output) => output,
+            // Recovered by reconfiguring the surface, e.g. after a resize.
+            Err(e @ (SurfaceError::Outdated | SurfaceError::Lost)) => return Err(e),
+            Err(e) => {
+                self.notifications.error(format!("surface error: {}", e));
+                return Err(e);
+            }
+        };
+
+        let frame_size = FrameSize::of(&output.texture, self.window.inner_size());
+        if let Some(window) = frame_size.window {
+            log::debug!(
+                "acquired {}x{} surface texture for a {}x{} window",
+                frame_size.size.width,
+                frame_size.size.height,
+                window.width,
+                window.height,
+            );
+        }
+
+        self.frame_size = Some(frame_size);
+        self.camera.set_aspect(frame_size.aspect);
+        self.pointcloud
+            .update(&self.camera, &self.device, &self.queue, frame_size.size);
 
         let view = output
             .texture
@@ -197,13 +611,31 @@ impl Engine {
                 label: Some("Render Encoder"),
             });
 
+        // Without points the depth texture is not cleared, so there is nothing
+        // for eye-dome lighting to shade.
+        let eye_dome = self.eye_dome.is_enabled()
+            && self
+                .pointcloud
+                .clouds()
+                .iter()
+                .any(|cloud| cloud.is_visible());
+        // Everything is drawn to `frame`, which is resolved to the surface at
+        // the end when multisampling. Without it, points for eye-dome
+        // lighting go straight to its target instead.
+        let frame = self.msaa_texture.as_ref().map_or(&view, Texture::view);
+        let target = if eye_dome && self.msaa_texture.is_none() {
+            self.eye_dome.target()
+        } else {
+            frame
+        };
+
         let _ = encoder.begin_render_pass(&RenderPassDescriptor {
             label: Some("init_render_pass"),
             color_attachments: &[Some(RenderPassColorAttachment {
-                view: &view,
+                view: target,
                 resolve_target: None,
                 ops: Operations {
-                    load: wgpu::LoadOp::Clear(Color::BLACK),
+                    load: wgpu::LoadOp::Clear(self.clear_color()),
                     store: wgpu::StoreOp::Store,
                 },
             })],
@@ -213,33 +645,978 @@ impl Engine {
         });
 
         self.pointcloud
-            .draw(&mut encoder, &view, &self.depth_texture);
+            .draw(&mut encoder, target, &self.depth_texture, &self.device);
+
+        if eye_dome {
+            if self.msaa_texture.is_some() {
+                Texture::resolve(&mut encoder, frame, self.eye_dome.target());
+            }
+            self.eye_dome
+                .draw(&mut encoder, frame, &self.camera, &self.queue);
+        }
 
         let screen_descriptor = ScreenDescriptor {
-            size_in_pixels: [self.config.width, self.config.height],
+            size_in_pixels: [frame_size.size.width, frame_size.size.height],
             pixels_per_point: self.window.scale_factor() as f32,
         };
 
-        self.gui.draw(
-            &self.device,
-            &self.queue,
-            &mut encoder,
-            &self.window,
-            &view,
-            screen_descriptor,
-        );
+        let mut pasted = None;
+        let mut gui_state = None;
+        if self.show_gui {
+            let mut state = self.gui_state();
+            pasted = self.gui.draw(
+                &self.device,
+                &self.queue,
+                &mut encoder,
+                &self.window,
+                frame,
+                screen_descriptor,
+                &mut state,
+            );
+            gui_state = Some(state);
+        }
+
+        if self.msaa_texture.is_some() {
+            Texture::resolve(&mut encoder, frame, &view);
+        }
+
+        // Applied once the frame's targets are no longer needed, as it may
+        // recreate them.
+        if let Some(state) = gui_state {
+            self.apply_gui_state(state);
+        }
 
         self.queue.submit(std::iter::once(encoder.finish()));
         output.present();
+        self.frame_times.end_frame();
+
+        if let Some(text) = pasted.filter(|text| ShareLink::is_link(text)) {
+            self.open_share_link(&text);
+        }
 
         Ok(())
     }
 
-    pub fn set_pcd(&mut self, path: &PathBuf) {
-        match self.pointcloud.load_pcd(path, &self.device) {
-            Err(e) => eprintln!("{:?}", e),
-            _ => {}
+    /// Starts reading `path` in the background; the loaded clouds are
+    /// replaced once it has been read.
+    pub fn set_pcd(&mut self, path: &Path) {
+        self.start_load(path, LoadKind::Open, None);
+    }
+
+    /// Options for the files loaded from now on.
+    pub fn set_load_options(&mut self, options: LoadOptions) {
+        if let Some(voxel_size) = options.voxel_size {
+            self.voxel_size = voxel_size;
+        }
+        self.load_options = options;
+    }
+
+    /// Starts reading `paths` on worker threads, without an engine, so files
+    /// given at startup are parsed while the device is being created. Hand
+    /// the job to [`Engine::adopt_load`].
+    pub fn spawn_load(paths: Vec<PathBuf>, kind: LoadKind, options: LoadOptions) -> LoadJob {
+        LoadJob::spawn(paths, kind, move |path, progress| {
+            PointCloud::read(path, options, progress)
+        })
+    }
+
+    /// Reads `path` on a worker thread, superseding any load still running.
+    /// `poll` uploads the result when it arrives.
+    fn start_load(&mut self, path: &Path, kind: LoadKind, link: Option<ShareLink>) {
+        let job = Self::spawn_load(vec![path.to_path_buf()], kind, self.load_options);
+        self.adopt_load(job, link);
+    }
+
+    /// Makes `job` the load in progress, superseding any still running.
+    /// `poll` uploads its clouds with progress shown, and restores the view
+    /// of `link` once they are.
+    pub fn adopt_load(&mut self, job: LoadJob, link: Option<ShareLink>) {
+        match job.kind() {
+            // The clouds the queued reloads are for are about to go.
+            LoadKind::Open => self.reload_queue.clear(),
+            // A reload that is cut short goes back to the front of the queue.
+            LoadKind::Add => {
+                if let Some((job, _)) = &self.loading {
+                    if job.kind() == LoadKind::Reload {
+                        self.reload_queue.push_front(job.path().to_path_buf());
+                    }
+                }
+            }
+            LoadKind::Reload => {}
+        }
+
+        // Dropping the previous job cancels its read.
+        self.loading = Some((job, link));
+        self.window.request_redraw();
+    }
+
+    fn finish_load(
+        &mut self,
+        job: &LoadJob,
+        results: Vec<Result<CloudData>>,
+        link: Option<ShareLink>,
+    ) {
+        if job.kind() == LoadKind::Reload {
+            if let Some(result) = results.into_iter().next() {
+                self.finish_reload(job.path(), result);
+            }
+            return;
+        }
+
+        // A file that fails is reported and the rest still load; when none
+        // could be read, the clouds shown stay.
+        let mut read = Vec::new();
+        for result in results {
+            match result {
+                Ok(data) => read.push(data),
+                Err(e) => self.notifications.error(format!("{:#}", e)),
+            }
+        }
+        if read.is_empty() {
+            self.window.request_redraw();
+            return;
+        }
+
+        let paths: Vec<PathBuf> = read.iter().map(|data| data.path().clone()).collect();
+        let y_up: Vec<PathBuf> = read
+            .iter()
+            .filter(|data| data.up_axis() == Some(UpAxis::Y))
+            .map(|data| data.path().clone())
+            .collect();
+        let shown = match job.kind() {
+            LoadKind::Open => self
+                .pointcloud
+                .set_data_many(read, &self.device, &self.queue),
+            _ => self
+                .pointcloud
+                .add_data_many(read, &self.device, &self.queue),
+        };
+        let loaded = shown.iter().any(Option::is_some);
+        self.on_pcd_loaded(paths.into_iter().zip(shown).collect());
+        for path in y_up {
+            self.offer_z_up(path);
+        }
+
+        if let (true, Some(link)) = (loaded, link) {
+            self.apply_share_link(&link);
+        }
+    }
+
+    /// Unloads everything, leaving the camera where it is so loading the same
+    /// area again does not jump.
+    pub fn clear(&mut self) {
+        self.reload_queue.clear();
+        self.up_axis_prompt = None;
+        self.pointcloud.clear();
+        self.update_title();
+    }
+
+    /// Re-reads every loaded file in the background, keeping the camera, the
+    /// point size and each cloud's color, visibility and transform.
+    pub fn reload(&mut self) {
+        let paths: Vec<PathBuf> = self
+            .pointcloud
+            .clouds()
+            .iter()
+            .map(|cloud| cloud.path().clone())
+            .collect();
+        for path in paths {
+            self.queue_reload(path);
+        }
+    }
+
+    /// Queues a reload of `path` unless one is already queued or running.
+    fn queue_reload(&mut self, path: PathBuf) {
+        let running = self
+            .loading
+            .as_ref()
+            .is_some_and(|(job, _)| job.kind() == LoadKind::Reload && job.path() == path);
+        if !running && !self.reload_queue.contains(&path) {
+            self.reload_queue.push_back(path);
+            self.window.request_redraw();
+        }
+    }
+
+    /// Swaps the new data into every cloud read from `path`. A file that
+    /// fails to load, say because it is half written, keeps showing its
+    /// previous data.
+    fn finish_reload(&mut self, path: &Path, result: Result<CloudData>) {
+        let indices: Vec<usize> = (0..self.pointcloud.clouds().len())
+            .filter(|&index| self.pointcloud.clouds()[index].path() == path)
+            .collect();
+        let Some((&last, others)) = indices.split_last() else {
+            // Unloaded while it was being read.
+            return;
+        };
+        let origin = self.pointcloud.origin();
+
+        let result = result.and_then(|data| {
+            for &index in others {
+                self.pointcloud
+                    .reload_data(index, data.clone(), &self.device, &self.queue);
+            }
+            self.pointcloud
+                .reload_data(last, data, &self.device, &self.queue)
+                .ok_or_else(|| anyhow!("{}: no points", path.display()))
+        });
+
+        self.follow_origin(origin);
+        self.reload_summary.record(path, result.is_ok());
+        if let Err(e) = result {
+            for &index in &indices {
+                self.pointcloud
+                    .set_cloud_modified(index, PointCloud::modified(path));
+            }
+            self.notifications
+                .error(format!("{:#}; keeping the previous data", e));
+        }
+        self.window.request_redraw();
+    }
+
+    /// Reloads the clouds whose file changed on disk, once the change has
+    /// settled for a moment so a writer that is still busy is not caught
+    /// halfway.
+    fn reload_changed_files(&mut self) {
+        const SETTLE: Duration = Duration::from_millis(300);
+
+        for index in 0..self.pointcloud.clouds().len() {
+            let cloud = &self.pointcloud.clouds()[index];
+            let modified = PointCloud::modified(cloud.path());
+            let settled = modified
+                .and_then(|modified| modified.elapsed().ok())
+                .is_some_and(|age| age >= SETTLE);
+
+            if modified != cloud.modified() && settled {
+                self.queue_reload(cloud.path().clone());
+            }
+        }
+    }
+
+    /// Like [`Engine::set_pcd`], keeping the clouds already loaded.
+    pub fn add_pcd(&mut self, path: &Path) {
+        self.start_load(path, LoadKind::Add, None);
+    }
+
+    fn gui_state(&self) -> GuiState {
+        GuiState {
+            file_name: self
+                .pointcloud
+                .path()
+                .and_then(|path| path.file_name())
+                .map(|name| name.to_string_lossy().into_owned()),
+            info: self.pointcloud.bounds().map(|bounds| CloudInfo {
+                point_count: self.pointcloud.point_count(),
+                drawn_count: self.pointcloud.drawn_point_count(),
+                visible_count: self.pointcloud.visible_point_count(),
+                downsampled_from: self
+                    .pointcloud
+                    .clouds()
+                    .iter()
+                    .any(|cloud| cloud.downsampled_from().is_some())
+                    .then(|| {
+                        self.pointcloud
+                            .clouds()
+                            .iter()
+                            .map(|cloud| cloud.downsampled_from().unwrap_or(cloud.point_count()))
+                            .sum()
+                    }),
+                skipped: self
+                    .pointcloud
+                    .clouds()
+                    .iter()
+                    .map(|cloud| cloud.skipped())
+                    .sum(),
+                bounds,
+                gpu_bytes: self.pointcloud.gpu_bytes(),
+                gpu_capacity: self.pointcloud.gpu_capacity(),
+            }),
+            loading: self.loading.as_ref().map(|(job, _)| Loading {
+                name: match job.paths() {
+                    [path] => path
+                        .file_name()
+                        .unwrap_or(path.as_os_str())
+                        .to_string_lossy()
+                        .into_owned(),
+                    paths => format!("{} files", paths.len()),
+                },
+                fraction: job.fraction(),
+            }),
+            cancel_load: false,
+            point_size: self.pointcloud.point_size(),
+            point_size_range: self.pointcloud.point_size_range(),
+            default_point_size: self.pointcloud.default_point_size(),
+            point_budget: self.pointcloud.point_budget(),
+            size_mode: self.pointcloud.size_mode(),
+            point_shape: self.pointcloud.shape(),
+            color_mode: self.pointcloud.color_mode(),
+            sensitivity: self.camera.sensitivity(),
+            reversed_z: self.camera.reversed_z(),
+            infinite_far: self.camera.infinite_far(),
+            gpu_culling: self.pointcloud.is_culling(),
+            continuous_redraw: self.continuous_redraw,
+            number_format: self.number_format,
+            msaa_samples: self.msaa_samples,
+            supported_samples: self.supported_samples.clone(),
+            present_mode: self.present_mode,
+            supported_present_modes: self.supported_present_modes.clone(),
+            open_file: false,
+            add_file: false,
+            clear: false,
+            reload: false,
+            watch_files: self.watch_files,
+            apply_viewpoint: self.load_options.apply_viewpoint,
+            downsample: self.load_options.voxel_size.is_some(),
+            voxel_size: self.voxel_size,
+            full_resolution: false,
+            clouds: self
+                .pointcloud
+                .clouds()
+                .iter()
+                .map(|cloud| CloudEntry {
+                    name: cloud
+                        .path()
+                        .file_name()
+                        .unwrap_or(cloud.path().as_os_str())
+                        .to_string_lossy()
+                        .into_owned(),
+                    point_count: cloud.point_count(),
+                    visible: cloud.is_visible(),
+                    color: cloud.color(),
+                    transform: cloud.transform(),
+                    translation_speed: cloud.bounds().radius() * 1e-3,
+                })
+                .collect(),
+            remove_cloud: None,
+            reload_cloud: None,
+            copy_matrix: None,
+            up_axis_prompt: self
+                .up_axis_prompt
+                .as_ref()
+                .filter(|path| self.cloud_index(path).is_some())
+                .map(|path| UpAxisPrompt {
+                    name: path
+                        .file_name()
+                        .unwrap_or(path.as_os_str())
+                        .to_string_lossy()
+                        .into_owned(),
+                    extension: PointCloud::extension(path),
+                }),
+            up_axis_answer: None,
+            recent_files: self.recent_files.paths().to_vec(),
+            open_recent: None,
+            notifications: self.notifications.active().to_vec(),
+            show_frame_stats: self.show_frame_stats,
+            background_color: self.background_color,
+            eye_dome: self.eye_dome.is_enabled(),
+            eye_dome_strength: self.eye_dome.strength(),
+            eye_dome_radius: self.eye_dome.radius(),
+            frame_stats: FrameStats {
+                fps: self.frame_times.fps(),
+                average_ms: self.frame_times.average().as_secs_f32() * 1e3,
+                adapter: self.adapter_name.clone(),
+                texture_bytes: self.gui.texture_bytes(),
+                frame_size: self
+                    .frame_size
+                    .map_or([0, 0], |frame| [frame.size.width, frame.size.height]),
+                window_size: self
+                    .frame_size
+                    .and_then(|frame| frame.window)
+                    .map(|window| [window.width, window.height]),
+                recent_ms: self
+                    .frame_times
+                    .durations()
+                    .map(|duration| duration.as_secs_f32() * 1e3)
+                    .collect(),
+                startup_ms: self
+                    .startup_report
+                    .iter()
+                    .flat_map(|report| {
+                        report
+                            .stages()
+                            .iter()
+                            .map(|(stage, duration)| (stage.name(), *duration))
+                            .chain([("total", report.total())])
+                    })
+                    .map(|(name, duration)| (name, duration.as_secs_f32() * 1e3))
+                    .collect(),
+            },
+        }
+    }
+
+    fn apply_gui_state(&mut self, state: GuiState) {
+        // The size belongs to the mode it was shown for, so set it before a
+        // mode switch from the same frame takes effect.
+        self.pointcloud.set_point_size(state.point_size);
+        self.pointcloud.set_point_budget(state.point_budget);
+        self.pointcloud.set_size_mode(state.size_mode);
+        self.pointcloud.set_shape(state.point_shape);
+        self.pointcloud.set_color_mode(state.color_mode);
+        self.camera.set_sensitivity(state.sensitivity);
+        self.camera.set_reversed_z(state.reversed_z);
+        self.camera.set_infinite_far(state.infinite_far);
+        self.pointcloud.set_culling(state.gpu_culling);
+        self.continuous_redraw = state.continuous_redraw;
+        self.number_format = state.number_format;
+        if state.msaa_samples != self.msaa_samples {
+            self.set_msaa_samples(state.msaa_samples);
+        }
+        if state.present_mode != self.present_mode {
+            self.set_present_mode(state.present_mode);
+        }
+        self.show_frame_stats = state.show_frame_stats;
+        self.background_color = state.background_color;
+        self.eye_dome.set_enabled(state.eye_dome);
+        self.eye_dome.set_strength(state.eye_dome_strength);
+        self.eye_dome.set_radius(state.eye_dome_radius);
+        for (index, cloud) in state.clouds.iter().enumerate() {
+            self.pointcloud.set_cloud_visible(index, cloud.visible);
+            self.pointcloud.set_cloud_color(index, cloud.color);
+            self.pointcloud.set_cloud_transform(index, cloud.transform);
+        }
+        if let Some(cloud) = state
+            .copy_matrix
+            .and_then(|index| self.pointcloud.clouds().get(index))
+        {
+            let matrix = cloud.matrix();
+            let values: Vec<String> = (0..4)
+                .flat_map(|row| (0..4).map(move |column| matrix[column][row].to_string()))
+                .collect();
+            self.gui.copy_text(format!("[{}]", values.join(", ")));
+            self.notifications
+                .info("Transform matrix copied to the clipboard");
+        }
+        if let Some(answer) = state.up_axis_answer {
+            self.answer_up_axis_prompt(answer);
+        }
+        self.watch_files = state.watch_files;
+        if state.cancel_load {
+            self.loading = None;
+            self.reload_queue.clear();
+        }
+        self.voxel_size = state.voxel_size;
+        let options = LoadOptions {
+            apply_viewpoint: state.apply_viewpoint,
+            voxel_size: (state.downsample && !state.full_resolution).then_some(state.voxel_size),
+        };
+        let reload = state.reload
+            || state.full_resolution
+            || options.apply_viewpoint != self.load_options.apply_viewpoint;
+        self.load_options = options;
+        if reload {
+            self.reload();
+        } else if let Some(cloud) = state
+            .reload_cloud
+            .and_then(|index| self.pointcloud.clouds().get(index))
+        {
+            self.queue_reload(cloud.path().clone());
+        }
+        if state.clear {
+            self.clear();
+        } else if let Some(index) = state.remove_cloud {
+            self.pointcloud
+                .remove_cloud(index, &self.device, &self.queue);
+            self.update_title();
+        }
+        if state.open_file || state.add_file {
+            self.open_file_dialog(state.add_file);
+        }
+        if let Some(path) = state.open_recent {
+            self.set_pcd(&path);
+            if !path.exists() {
+                self.recent_files.remove(&path);
+            }
+        }
+        self.pointcloud
+            .set_reversed_z(state.reversed_z, &self.device);
+    }
+
+    /// The background as a clear value. An sRGB surface encodes on write, so
+    /// it is given linear values to end up showing the picked color.
+    fn clear_color(&self) -> Color {
+        let [r, g, b] = self.background_color;
+
+        let [r, g, b, a] = if self.config.format.is_srgb() {
+            egui::Rgba::from(egui::Color32::from_rgb(r, g, b)).to_array()
+        } else {
+            [r, g, b, 255].map(|channel| channel as f32 / 255.0)
+        };
+
+        Color {
+            r: r as f64,
+            g: g as f64,
+            b: b as f64,
+            a: a as f64,
+        }
+    }
+
+    /// Shows a native open dialog without blocking rendering. The dialog is
+    /// created here, on the event loop thread as macOS requires, and awaited
+    /// on a helper thread; `update` picks up the choice.
+    fn open_file_dialog(&mut self, add: bool) {
+        if self.open_dialog.is_some() {
+            return;
+        }
+
+        let title = if add {
+            "Add point cloud"
+        } else {
+            "Open point cloud"
+        };
+
+        let dialog = rfd::AsyncFileDialog::new()
+            .set_title(title)
+            .add_filter("Point clouds", &["pcd", "ply", "las", "xyz", "txt", "csv"])
+            .set_parent(&*self.window)
+            .pick_file();
+
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let path = dialog.block_on().map(|file| file.path().to_path_buf());
+            let _ = sender.send(path);
+        });
+
+        self.open_dialog = Some((receiver, add));
+    }
+
+    /// Link to the current file as seen from the current camera.
+    pub fn share_link(&self) -> Option<ShareLink> {
+        Some(ShareLink {
+            file: self.pointcloud.path()?.clone(),
+            camera: Some((
+                self.shown_position(self.camera.eye()),
+                self.shown_position(self.camera.target()),
+            )),
+            normalized: Some(self.pointcloud.is_normalized()),
+            color_mode: Some(self.pointcloud.color_mode()),
+            infinite_far: Some(self.camera.infinite_far()),
+            ignored: Vec::new(),
+        })
+    }
+
+    /// Restores the view settings of `link` onto the loaded cloud.
+    pub fn apply_share_link(&mut self, link: &ShareLink) {
+        if let Some(normalized) = link.normalized {
+            if normalized != self.pointcloud.is_normalized() {
+                self.pointcloud
+                    .set_normalized(normalized, &self.device, &self.queue);
+                self.frame_cloud();
+            }
+        }
+
+        if let Some(color_mode) = link.color_mode {
+            self.pointcloud.set_color_mode(color_mode);
+        }
+
+        if let Some((eye, target)) = link.camera {
+            self.look_at(eye, target);
+        }
+
+        if let Some(infinite_far) = link.infinite_far {
+            self.camera.set_infinite_far(infinite_far);
+        }
+
+        for problem in &link.ignored {
+            self.notifications.info(format!("Ignored a {}", problem));
+        }
+    }
+
+    /// Points the camera from `eye` at `target`, in the coordinates a share
+    /// link would use.
+    pub fn look_at(&mut self, eye: [f32; 3], target: [f32; 3]) {
+        let (eye, target) = (self.rendered_position(eye), self.rendered_position(target));
+        self.camera.look_at(eye, target);
+    }
+
+    /// `point` of the rendered space as the user sees it: in file units when
+    /// not normalized, rather than relative to the recentered origin.
+    fn shown_position(&self, point: Point3<f32>) -> [f32; 3] {
+        let origin = self.pointcloud.origin();
+        [0, 1, 2].map(|axis| (point[axis] as f64 + origin[axis]) as f32)
+    }
+
+    /// Inverse of [`Engine::shown_position`].
+    fn rendered_position(&self, position: [f32; 3]) -> Point3<f32> {
+        let origin = self.pointcloud.origin();
+        Point3::from([0, 1, 2].map(|axis| (position[axis] as f64 - origin[axis]) as f32))
+    }
+
+    /// Keeps the camera on the same file coordinates after the rendered
+    /// space has been recentered away from `origin`.
+    fn follow_origin(&mut self, origin: [f64; 3]) {
+        let moved = self.pointcloud.origin();
+        let offset = [0, 1, 2].map(|axis| (origin[axis] - moved[axis]) as f32);
+        self.camera.translate(offset.into());
+    }
+
+    fn open_share_link(&mut self, text: &str) {
+        match ShareLink::parse(text) {
+            Ok(link) => self.open_share_link_file(link),
+            Err(e) => self.notifications.error(format!("{:#}", e)),
+        }
+    }
+
+    /// Loads the file of `link` in the background and restores its view once
+    /// the file is shown.
+    pub fn open_share_link_file(&mut self, link: ShareLink) {
+        let file = link.file.clone();
+        self.start_load(&file, LoadKind::Open, Some(link));
+    }
+
+    fn cloud_index(&self, path: &Path) -> Option<usize> {
+        self.pointcloud
+            .clouds()
+            .iter()
+            .position(|cloud| cloud.path() == path)
+    }
+
+    /// Turns the cloud read from `path`, which looks Y-up, to the viewer's
+    /// Z-up, or asks first unless files of its type are always turned.
+    fn offer_z_up(&mut self, path: PathBuf) {
+        let always = PointCloud::extension(&path)
+            .is_some_and(|extension| self.y_up_extensions.contains(&extension));
+        if always {
+            self.rotate_to_z_up(&path);
+            self.notifications.info(format!(
+                "Rotated {} from Y-up; reset its transform to undo",
+                path.display()
+            ));
+        } else {
+            self.up_axis_prompt = Some(path);
+        }
+    }
+
+    fn answer_up_axis_prompt(&mut self, answer: UpAxisAnswer) {
+        let Some(path) = self.up_axis_prompt.take() else {
+            return;
+        };
+        if answer == UpAxisAnswer::Keep {
+            return;
+        }
+
+        self.rotate_to_z_up(&path);
+        if let (UpAxisAnswer::AlwaysRotate, Some(extension)) =
+            (answer, PointCloud::extension(&path))
+        {
+            if !self.y_up_extensions.contains(&extension) {
+                self.y_up_extensions.push(extension);
+            }
+        }
+    }
+
+    /// Rolls the cloud read from `path` a quarter turn about x, so its y axis
+    /// points up. It is a plain transform, which Reset in the panel undoes.
+    fn rotate_to_z_up(&mut self, path: &Path) {
+        let Some(index) = self.cloud_index(path) else {
+            return;
+        };
+        let transform = Transform {
+            rotation: [90.0, 0.0, 0.0],
+            ..self.pointcloud.clouds()[index].transform()
+        };
+        self.pointcloud.set_cloud_transform(index, transform);
+        self.window.request_redraw();
+    }
+
+    /// Keeps the finished startup report for the frame stats overlay.
+    pub fn set_startup_report(&mut self, report: StartupReport) {
+        self.startup_report = Some(report);
+    }
+
+    /// Surfaces an error the application shell ran into.
+    pub fn notify_error(&mut self, message: impl Into<String>) {
+        self.notifications.error(message);
+    }
+
+    /// Reports on the files a load read, with the bounds of each cloud shown
+    /// or `None` for a file without points, and frames the clouds once.
+    fn on_pcd_loaded(&mut self, shown: Vec<(PathBuf, Option<Bounds>)>) {
+        let single = shown.len() == 1;
+        for (path, _) in shown.iter().filter(|(_, bounds)| bounds.is_none()) {
+            self.notifications.error(if single {
+                "Nothing to show: the file has no points with valid coordinates".to_string()
+            } else {
+                format!(
+                    "Nothing to show from {}: no points with valid coordinates",
+                    path.display()
+                )
+            });
+        }
+
+        let added = shown.iter().filter(|(_, bounds)| bounds.is_some()).count();
+        let clouds = self.pointcloud.clouds();
+        let new = &clouds[clouds.len() - added..];
+        if let Some(first) = new.first() {
+            // One entry per load, so opening many files at once does not
+            // push everything else out of the list.
+            let first = first.path().clone();
+            let skipped: usize = new.iter().map(|cloud| cloud.skipped()).sum();
+
+            self.recent_files.push(&first);
+            if skipped > 0 {
+                self.notifications.info(format!(
+                    "Skipped {} points with invalid coordinates",
+                    skipped
+                ));
+            }
+            self.update_title();
+            self.frame_cloud();
+            self.camera.set_home();
+        }
+        self.window.request_redraw();
+    }
+
+    /// Writes the current settings if they differ from the saved ones.
+    pub fn save_settings(&mut self) {
+        let settings = self.settings();
+        self.pending_settings = None;
+
+        if settings == self.saved_settings {
+            return;
         }
+
+        if let Err(e) = settings.save() {
+            log::warn!("cannot save settings: {:#}", e);
+        }
+        self.saved_settings = settings;
+    }
+
+    /// Saves once the settings have stopped changing for a moment, so a slider
+    /// drag or a window resize does not write the file every frame.
+    fn save_settings_when_idle(&mut self, now: Instant) {
+        let settings = self.settings();
+
+        match &self.pending_settings {
+            _ if settings == self.saved_settings => self.pending_settings = None,
+            Some((pending, changed)) if *pending == settings => {
+                if now - *changed >= Self::SAVE_DELAY {
+                    self.save_settings();
+                }
+            }
+            _ => self.pending_settings = Some((settings, now)),
+        }
+    }
+
+    /// Shows the `overrides` without making them the saved settings.
+    pub fn override_settings(&mut self, overrides: Overrides) {
+        if let Some(size) = overrides.point_size {
+            self.pointcloud.set_point_size(size);
+        }
+        if let Some(mode) = overrides.color_mode {
+            self.pointcloud.set_color_mode(mode);
+        }
+        if let Some(color) = overrides.background_color {
+            self.background_color = color;
+        }
+        // Clamping may have changed the size; compare against what is shown.
+        self.overrides = Overrides {
+            point_size: overrides
+                .point_size
+                .map(|_| self.pointcloud.pixel_point_size()),
+            ..overrides
+        };
         self.window.request_redraw();
     }
+
+    fn settings(&self) -> Settings {
+        let maximized = self.window.is_maximized();
+
+        // The inner size of a maximized window is the screen's; keep the one
+        // to restore to.
+        let (width, height) = if maximized {
+            (
+                self.saved_settings.window.width,
+                self.saved_settings.window.height,
+            )
+        } else {
+            let size = self.size.to_logical::<u32>(self.window.scale_factor());
+            (size.width, size.height)
+        };
+
+        let mut settings = Settings {
+            point_size: self.pointcloud.pixel_point_size(),
+            point_budget: self.pointcloud.point_budget(),
+            point_shape: self.pointcloud.shape(),
+            color_mode: self.pointcloud.color_mode(),
+            continuous_redraw: self.continuous_redraw,
+            background_color: self.background_color,
+            number_format: self.number_format,
+            y_up_extensions: self.y_up_extensions.clone(),
+            sensitivity: self.camera.sensitivity(),
+            window: WindowSettings {
+                width,
+                height,
+                maximized,
+            },
+            recent_files: self.recent_files.clone(),
+        };
+        self.overrides.restore(&mut settings, &self.saved_settings);
+        settings
+    }
+
+    /// Names the loaded file, and says how to bring the panel back while it is
+    /// hidden since toasts are not drawn then either.
+    fn update_title(&self) {
+        let mut title = String::from("pcvisualizer");
+
+        if let Some(name) = self.pointcloud.path().and_then(|path| path.file_name()) {
+            title += &format!(" - {}", name.to_string_lossy());
+        }
+
+        let others = self.pointcloud.clouds().len().saturating_sub(1);
+        if others > 0 {
+            title += &format!(" (+{})", others);
+        }
+
+        if !self.show_gui {
+            title += " (press H to show the panel)";
+        }
+
+        self.window.set_title(&title);
+    }
+
+    /// Fits the camera to the cloud's bounding sphere, keeping the current
+    /// viewing direction.
+    fn frame_cloud(&mut self) {
+        let Some((center, radius)) = self.pointcloud.bounding_sphere() else {
+            return;
+        };
+
+        let aspect = self.size.width as f32 / self.size.height as f32;
+        self.camera.fit_to_bounds(center.into(), radius, aspect);
+    }
+}
+
+/// How finished frames are handed to the display.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PresentMode {
+    /// Waits for vertical blank; supported everywhere.
+    Vsync,
+    /// Shows the newest frame at vertical blank without blocking rendering.
+    Mailbox,
+    /// Shows frames as soon as they are done, uncapped and possibly torn.
+    Immediate,
+}
+
+impl PresentMode {
+    pub const ALL: [PresentMode; 3] = [
+        PresentMode::Vsync,
+        PresentMode::Mailbox,
+        PresentMode::Immediate,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            PresentMode::Vsync => "vsync",
+            PresentMode::Mailbox => "mailbox",
+            PresentMode::Immediate => "immediate",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|mode| mode.name() == name)
+    }
+
+    fn to_wgpu(self) -> wgpu::PresentMode {
+        match self {
+            PresentMode::Vsync => wgpu::PresentMode::AutoVsync,
+            PresentMode::Mailbox => wgpu::PresentMode::Mailbox,
+            PresentMode::Immediate => wgpu::PresentMode::Immediate,
+        }
+    }
+}
+
+/// Sample counts out of 1, 2 and 4 that both `format` and the depth format
+/// can be rendered with.
+fn supported_sample_counts(adapter: &Adapter, device: &Device, format: TextureFormat) -> Vec<u32> {
+    let adapter_specific = device
+        .features()
+        .contains(Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES);
+    let supports = |format: TextureFormat, samples: u32| {
+        let features = if adapter_specific {
+            adapter.get_texture_format_features(format)
+        } else {
+            format.guaranteed_format_features(device.features())
+        };
+        features.flags.sample_count_supported(samples)
+    };
+
+    [1, 2, 4]
+        .into_iter()
+        .filter(|&samples| {
+            samples == 1 || (supports(format, samples) && supports(Texture::DEPTH_FORMAT, samples))
+        })
+        .collect()
+}
+
+/// Resolution-dependent values of one frame. Every one of them is derived
+/// from the texture actually acquired for the frame rather than the cached
+/// window size, which can be ahead of the surface during a live resize.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct FrameSize {
+    size: PhysicalSize<u32>,
+    /// Width over height, or 1 for a texture without area.
+    aspect: f32,
+    /// The window's size, when the surface has not caught up with it.
+    window: Option<PhysicalSize<u32>>,
+}
+
+impl FrameSize {
+    fn new(acquired: PhysicalSize<u32>, window: PhysicalSize<u32>) -> Self {
+        let aspect = if acquired.width > 0 && acquired.height > 0 {
+            acquired.width as f32 / acquired.height as f32
+        } else {
+            1.0
+        };
+
+        Self {
+            size: acquired,
+            aspect,
+            window: (window != acquired).then_some(window),
+        }
+    }
+
+    fn of(texture: &wgpu::Texture, window: PhysicalSize<u32>) -> Self {
+        Self::new(PhysicalSize::new(texture.width(), texture.height()), window)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frame_size_follows_the_acquired_texture() {
+        let size = PhysicalSize::new(1600, 900);
+        let frame = FrameSize::new(size, size);
+
+        assert_eq!(frame.size, size);
+        assert_eq!(frame.aspect, 16.0 / 9.0);
+        assert_eq!(frame.window, None);
+    }
+
+    #[test]
+    fn frame_size_ignores_a_window_the_surface_lags_behind() {
+        let acquired = PhysicalSize::new(800, 600);
+
+        // Growing, shrinking, and turned from landscape to portrait.
+        for window in [
+            PhysicalSize::new(1024, 768),
+            PhysicalSize::new(640, 600),
+            PhysicalSize::new(600, 800),
+        ] {
+            let frame = FrameSize::new(acquired, window);
+
+            assert_eq!(frame.size, acquired);
+            assert_eq!(frame.aspect, 800.0 / 600.0);
+            assert_eq!(frame.window, Some(window));
+        }
+    }
+
+    #[test]
+    fn frame_size_keeps_a_finite_aspect_without_area() {
+        for acquired in [PhysicalSize::new(0, 0), PhysicalSize::new(800, 0)] {
+            let frame = FrameSize::new(acquired, PhysicalSize::new(800, 600));
+            assert_eq!(frame.aspect, 1.0);
+        }
+    }
 }
@@ -0,0 +1,63 @@
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Level {
+    Info,
+    Error,
+}
+
+#[derive(Clone, Debug)]
+pub struct Notification {
+    pub level: Level,
+    pub message: String,
+    pub time: Instant,
+}
+
+impl Notification {
+    const LIFETIME: Duration = Duration::from_secs(6);
+
+    /// Time left before the notification expires, for fading it out.
+    pub fn remaining(&self, now: Instant) -> Duration {
+        Self::LIFETIME.saturating_sub(now.saturating_duration_since(self.time))
+    }
+}
+
+/// Messages for the user that would otherwise go to a console nobody sees
+/// (release builds on Windows have none). Each one is also logged.
+#[derive(Default)]
+pub struct Notifications {
+    items: Vec<Notification>,
+}
+
+impl Notifications {
+    pub fn info(&mut self, message: impl Into<String>) {
+        let message = message.into();
+        log::info!("{}", message);
+        self.push(Level::Info, message);
+    }
+
+    pub fn error(&mut self, message: impl Into<String>) {
+        let message = message.into();
+        log::error!("{}", message);
+        self.push(Level::Error, message);
+    }
+
+    /// Notifications that have not expired yet, oldest first.
+    pub fn active(&self) -> &[Notification] {
+        &self.items
+    }
+
+    pub fn expire(&mut self) {
+        let now = Instant::now();
+        self.items
+            .retain(|notification| notification.remaining(now) > Duration::ZERO);
+    }
+
+    fn push(&mut self, level: Level, message: String) {
+        self.items.push(Notification {
+            level,
+            message,
+            time: Instant::now(),
+        });
+    }
+}
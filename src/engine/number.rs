@@ -0,0 +1,186 @@
+use std::{ops::RangeInclusive, sync::OnceLock};
+
+use serde::{Deserialize, Serialize};
+
+/// Which decimal separator the panel writes numbers with. Input takes either,
+/// so "1,5" and "1.5" both mean one and a half whatever is chosen.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NumberFormat {
+    /// The separator of the system locale.
+    #[default]
+    System,
+    Point,
+    Comma,
+}
+
+impl NumberFormat {
+    pub const ALL: [NumberFormat; 3] = [
+        NumberFormat::System,
+        NumberFormat::Point,
+        NumberFormat::Comma,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            NumberFormat::System => "System",
+            NumberFormat::Point => "1.5",
+            NumberFormat::Comma => "1,5",
+        }
+    }
+
+    pub fn decimal_separator(self) -> char {
+        match self {
+            NumberFormat::System => system_separator(),
+            NumberFormat::Point => '.',
+            NumberFormat::Comma => ',',
+        }
+    }
+
+    /// `value` with `decimals` digits after the separator.
+    pub fn format(self, value: f64, decimals: usize) -> String {
+        self.localize(format!("{:.*}", decimals, value))
+    }
+
+    /// `value` the way egui writes it for a widget showing `decimals`, with
+    /// this separator.
+    pub fn format_in_range(self, value: f64, decimals: RangeInclusive<usize>) -> String {
+        self.localize(egui::emath::format_with_decimals_in_range(value, decimals))
+    }
+
+    fn localize(self, text: String) -> String {
+        match self.decimal_separator() {
+            '.' => text,
+            separator => text.replace('.', &separator.to_string()),
+        }
+    }
+
+    /// Reads a number written with either separator. Text with both is
+    /// refused rather than guessing which one groups thousands.
+    pub fn parse(text: &str) -> Option<f64> {
+        let text = text.trim();
+        if text.contains('.') && text.contains(',') {
+            return None;
+        }
+        text.replace(',', ".").parse().ok()
+    }
+}
+
+/// The decimal separator of the locale the user runs under, looked up once.
+fn system_separator() -> char {
+    static SEPARATOR: OnceLock<char> = OnceLock::new();
+
+    *SEPARATOR.get_or_init(|| system_locale().map_or('.', |name| separator_for_locale(&name)))
+}
+
+/// The numeric locale from the environment, as POSIX systems set it, and
+/// otherwise from the user's Windows settings.
+fn system_locale() -> Option<String> {
+    let from_env = ["LC_ALL", "LC_NUMERIC", "LANG"]
+        .into_iter()
+        .filter_map(|name| std::env::var(name).ok())
+        .find(|value| !value.is_empty());
+    if from_env.is_some() {
+        return from_env;
+    }
+
+    #[cfg(windows)]
+    {
+        const LOCALE_NAME_MAX_LENGTH: usize = 85;
+
+        #[link(name = "kernel32")]
+        extern "system" {
+            fn GetUserDefaultLocaleName(name: *mut u16, length: i32) -> i32;
+        }
+
+        let mut name = [0u16; LOCALE_NAME_MAX_LENGTH];
+        // Returns the length including the terminating nul, or 0 on failure.
+        let length = unsafe { GetUserDefaultLocaleName(name.as_mut_ptr(), name.len() as i32) };
+        if length > 1 {
+            return Some(String::from_utf16_lossy(&name[..length as usize - 1]));
+        }
+    }
+
+    None
+}
+
+/// The decimal separator of a locale name such as `de_DE.UTF-8` or `fr-CA`,
+/// going by its language. Unknown languages, `C` and `POSIX` use a point.
+fn separator_for_locale(name: &str) -> char {
+    // Languages whose standard numbers use a decimal comma.
+    const COMMA: [&str; 33] = [
+        "bg", "ca", "cs", "da", "de", "el", "es", "et", "eu", "fi", "fr", "gl", "hr", "hu", "id",
+        "is", "it", "lt", "lv", "nb", "nl", "nn", "no", "pl", "pt", "ro", "ru", "sk", "sl", "sv",
+        "tr", "uk", "vi",
+    ];
+    // Regions where those languages write a point instead.
+    const POINT_REGIONS: [&str; 3] = ["CH", "LI", "MX"];
+
+    let mut parts = name.split(['_', '-', '.', '@']);
+    let language = parts.next().unwrap_or("").to_ascii_lowercase();
+    let region = parts.next().unwrap_or("").to_ascii_uppercase();
+
+    if COMMA.contains(&language.as_str()) && !POINT_REGIONS.contains(&region.as_str()) {
+        ','
+    } else {
+        '.'
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_either_separator() {
+        assert_eq!(NumberFormat::parse("1.5"), Some(1.5));
+        assert_eq!(NumberFormat::parse(" 1,5 "), Some(1.5));
+        assert_eq!(NumberFormat::parse("-0,25"), Some(-0.25));
+        assert_eq!(NumberFormat::parse("12"), Some(12.0));
+        assert_eq!(NumberFormat::parse("1,000.5"), None);
+        assert_eq!(NumberFormat::parse("1.000,5"), None);
+        assert_eq!(NumberFormat::parse("1,5,0"), None);
+        assert_eq!(NumberFormat::parse(""), None);
+    }
+
+    #[test]
+    fn formats_round_trip_through_parse() {
+        for format in [NumberFormat::Point, NumberFormat::Comma] {
+            for value in [0.0, 1.5, -2.125, 1234.5, 1e-4] {
+                let text = format.format(value, 4);
+                assert_eq!(
+                    NumberFormat::parse(&text),
+                    Some((value * 1e4).round() / 1e4)
+                );
+
+                let text = format.format_in_range(value, 0..=6);
+                assert_eq!(NumberFormat::parse(&text), Some(value), "{}", text);
+            }
+        }
+
+        assert_eq!(NumberFormat::Point.format(1.25, 3), "1.250");
+        assert_eq!(NumberFormat::Comma.format(1.25, 3), "1,250");
+        assert_eq!(NumberFormat::Comma.format(-3.0, 0), "-3");
+    }
+
+    #[test]
+    fn locale_names_pick_their_separator() {
+        for (name, separator) in [
+            ("de_DE.UTF-8", ','),
+            ("fr-FR", ','),
+            ("pt_BR", ','),
+            ("ru_RU.UTF-8@euro", ','),
+            ("tr_TR", ','),
+            ("de_CH.UTF-8", '.'),
+            ("es-MX", '.'),
+            ("en_US.UTF-8", '.'),
+            ("en-GB", '.'),
+            ("ja_JP", '.'),
+            ("C", '.'),
+            ("POSIX", '.'),
+            ("", '.'),
+        ] {
+            assert_eq!(separator_for_locale(name), separator, "{}", name);
+        }
+    }
+}
@@ -0,0 +1,514 @@
+use std::{io::BufRead, path::Path};
+
+use anyhow::{anyhow, bail, Result};
+
+use super::{
+    loader::{self, Progress},
+    pointcloud::Point,
+};
+
+#[derive(Clone, Copy, PartialEq)]
+enum Format {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+#[derive(Clone, Copy)]
+enum Scalar {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl Scalar {
+    fn parse(name: &str) -> Result<Self> {
+        Ok(match name {
+            "char" | "int8" => Scalar::I8,
+            "uchar" | "uint8" => Scalar::U8,
+            "short" | "int16" => Scalar::I16,
+            "ushort" | "uint16" => Scalar::U16,
+            "int" | "int32" => Scalar::I32,
+            "uint" | "uint32" => Scalar::U32,
+            "float" | "float32" => Scalar::F32,
+            "double" | "float64" => Scalar::F64,
+            _ => bail!("unknown property type '{}'", name),
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Scalar::I8 | Scalar::U8 => 1,
+            Scalar::I16 | Scalar::U16 => 2,
+            Scalar::I32 | Scalar::U32 | Scalar::F32 => 4,
+            Scalar::F64 => 8,
+        }
+    }
+
+    fn is_float(self) -> bool {
+        matches!(self, Scalar::F32 | Scalar::F64)
+    }
+}
+
+enum Property {
+    Scalar { name: String, ty: Scalar },
+    List { count: Scalar, item: Scalar },
+}
+
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+/// Reads values one at a time in whichever encoding the file uses.
+struct Values<R> {
+    reader: R,
+    format: Format,
+    /// The current element's line, for ASCII files.
+    line: Vec<f64>,
+    next: usize,
+}
+
+impl<R: BufRead> Values<R> {
+    /// ASCII files hold one element per line.
+    fn begin_element(&mut self) -> Result<()> {
+        if self.format != Format::Ascii {
+            return Ok(());
+        }
+
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if self.reader.read_line(&mut line)? == 0 {
+                bail!("unexpected end of file");
+            }
+            if !line.trim().is_empty() {
+                break;
+            }
+        }
+
+        self.line = line
+            .split_ascii_whitespace()
+            .map(|token| {
+                token
+                    .parse::<f64>()
+                    .map_err(|_| anyhow!("invalid value '{}'", token))
+            })
+            .collect::<Result<_>>()?;
+        self.next = 0;
+
+        Ok(())
+    }
+
+    fn value(&mut self, ty: Scalar) -> Result<f64> {
+        if self.format == Format::Ascii {
+            let value = self
+                .line
+                .get(self.next)
+                .copied()
+                .ok_or_else(|| anyhow!("too few values on a line"))?;
+            self.next += 1;
+            return Ok(value);
+        }
+
+        // Brought to little endian in place, zero padded to eight bytes.
+        let mut b = [0u8; 8];
+        self.reader.read_exact(&mut b[..ty.size()])?;
+        if self.format == Format::BinaryBigEndian {
+            b[..ty.size()].reverse();
+        }
+
+        Ok(match ty {
+            Scalar::I8 => b[0] as i8 as f64,
+            Scalar::U8 => b[0] as f64,
+            Scalar::I16 => i16::from_le_bytes([b[0], b[1]]) as f64,
+            Scalar::U16 => u16::from_le_bytes([b[0], b[1]]) as f64,
+            Scalar::I32 => i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+            Scalar::U32 => u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+            Scalar::F32 => f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+            Scalar::F64 => f64::from_le_bytes(b),
+        })
+    }
+
+    /// Reads one element into `scalars`, one value per scalar property;
+    /// lists are skipped.
+    fn element(&mut self, element: &Element, scalars: &mut Vec<f64>) -> Result<()> {
+        self.begin_element()?;
+        scalars.clear();
+
+        for property in &element.properties {
+            match property {
+                Property::Scalar { ty, .. } => scalars.push(self.value(*ty)?),
+                Property::List { count, item } => {
+                    let count = self.value(*count)?;
+                    for _ in 0..count as usize {
+                        self.value(*item)?;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Most vertices reserved up front. The header's count is not trusted with
+/// more, so a corrupt one cannot exhaust memory before the read fails.
+const MAX_RESERVED_VERTICES: usize = 1 << 20;
+
+/// Reads the vertices of a PLY file as points, with the header's comments.
+/// Only the `vertex` element is used; faces and any other elements are
+/// skipped.
+pub fn read(path: &Path, progress: &Progress) -> Result<(Vec<Point>, Vec<String>)> {
+    let mut reader = loader::open(path, progress)?;
+    let (format, elements, comments) = read_header(&mut reader)?;
+
+    let mut values = Values {
+        reader,
+        format,
+        line: Vec::new(),
+        next: 0,
+    };
+    let mut scalars = Vec::new();
+
+    for element in &elements {
+        if element.name != "vertex" {
+            for _ in 0..element.count {
+                values.element(element, &mut scalars)?;
+            }
+            continue;
+        }
+
+        let schema = VertexSchema::new(element)?;
+        let mut points = Vec::with_capacity(element.count.min(MAX_RESERVED_VERTICES));
+        for _ in 0..element.count {
+            values.element(element, &mut scalars)?;
+            points.push(schema.point(&scalars));
+        }
+
+        return Ok((points, comments));
+    }
+
+    Err(anyhow!("no vertex element"))
+}
+
+fn read_header(reader: &mut impl BufRead) -> Result<(Format, Vec<Element>, Vec<String>)> {
+    let mut line = String::new();
+    let mut next_line = |line: &mut String| -> Result<()> {
+        line.clear();
+        if reader.read_line(line)? == 0 {
+            bail!("unexpected end of header");
+        }
+        Ok(())
+    };
+
+    next_line(&mut line)?;
+    if line.trim_end() != "ply" {
+        bail!("not a PLY file");
+    }
+
+    let mut format = None;
+    let mut elements: Vec<Element> = Vec::new();
+    let mut comments = Vec::new();
+
+    loop {
+        next_line(&mut line)?;
+        let tokens: Vec<&str> = line.split_ascii_whitespace().collect();
+
+        match tokens[..] {
+            ["end_header"] => break,
+            ["format", name, _version] => {
+                format = Some(match name {
+                    "ascii" => Format::Ascii,
+                    "binary_little_endian" => Format::BinaryLittleEndian,
+                    "binary_big_endian" => Format::BinaryBigEndian,
+                    _ => bail!("unknown format '{}'", name),
+                })
+            }
+            ["element", name, count] => elements.push(Element {
+                name: name.to_string(),
+                count: count
+                    .parse()
+                    .map_err(|_| anyhow!("invalid element count '{}'", count))?,
+                properties: Vec::new(),
+            }),
+            ["property", "list", count, item, _name] => elements
+                .last_mut()
+                .ok_or_else(|| anyhow!("property before any element"))?
+                .properties
+                .push(Property::List {
+                    count: Scalar::parse(count)?,
+                    item: Scalar::parse(item)?,
+                }),
+            ["property", ty, name] => elements
+                .last_mut()
+                .ok_or_else(|| anyhow!("property before any element"))?
+                .properties
+                .push(Property::Scalar {
+                    name: name.to_string(),
+                    ty: Scalar::parse(ty)?,
+                }),
+            ["comment", ..] | ["obj_info", ..] => {
+                comments.push(tokens[1..].join(" "));
+            }
+            [] => {}
+            _ => bail!("unexpected header line '{}'", line.trim_end()),
+        }
+    }
+
+    let format = format.ok_or_else(|| anyhow!("missing format line"))?;
+    Ok((format, elements, comments))
+}
+
+/// Positions of the properties we understand among a vertex's scalar values.
+struct VertexSchema {
+    x: usize,
+    y: usize,
+    z: usize,
+    intensity: Option<usize>,
+    /// Red, green and blue, and whether they are floats in [0, 1] rather than
+    /// bytes.
+    rgb: Option<([usize; 3], bool)>,
+}
+
+impl VertexSchema {
+    fn new(element: &Element) -> Result<Self> {
+        let scalars: Vec<(&str, Scalar)> = element
+            .properties
+            .iter()
+            .filter_map(|property| match property {
+                Property::Scalar { name, ty } => Some((name.as_str(), *ty)),
+                Property::List { .. } => None,
+            })
+            .collect();
+
+        let find = |names: &[&str]| {
+            scalars
+                .iter()
+                .position(|(name, _)| names.iter().any(|n| n.eq_ignore_ascii_case(name)))
+        };
+        let require =
+            |name: &str| find(&[name]).ok_or_else(|| anyhow!("missing '{}' property", name));
+
+        let rgb = match (
+            find(&["red", "r", "diffuse_red"]),
+            find(&["green", "g", "diffuse_green"]),
+            find(&["blue", "b", "diffuse_blue"]),
+        ) {
+            (Some(r), Some(g), Some(b)) => Some(([r, g, b], scalars[r].1.is_float())),
+            _ => None,
+        };
+
+        Ok(Self {
+            x: require("x")?,
+            y: require("y")?,
+            z: require("z")?,
+            intensity: find(&["intensity", "scalar_intensity"]),
+            rgb,
+        })
+    }
+
+    fn point(&self, values: &[f64]) -> Point {
+        Point {
+            x: values[self.x],
+            y: values[self.y],
+            z: values[self.z],
+            intensity: self.intensity.map_or(0.0, |index| values[index] as f32),
+            rgb: self.rgb.map(|(indices, float)| {
+                let [r, g, b] = indices.map(|index| {
+                    let value = if float {
+                        values[index] * 255.0
+                    } else {
+                        values[index]
+                    };
+                    value.round().clamp(0.0, 255.0) as u32
+                });
+                (r << 16) | (g << 8) | b
+            }),
+            classification: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_fixture(name: &str, contents: &[u8]) -> Result<Vec<Point>> {
+        read(&loader::fixture(name, contents), &Progress::default()).map(|(points, _)| points)
+    }
+
+    fn positions(points: &[Point]) -> Vec<[f64; 3]> {
+        points
+            .iter()
+            .map(|point| [point.x, point.y, point.z])
+            .collect()
+    }
+
+    #[test]
+    fn reads_ascii_vertices_and_skips_faces() {
+        let points = read_fixture(
+            "ascii.ply",
+            b"ply
+format ascii 1.0
+comment made by hand
+element vertex 2
+property float x
+property float y
+property float z
+property uchar red
+property uchar green
+property uchar blue
+element face 1
+property list uchar int vertex_indices
+end_header
+1 2 3 255 128 0
+
+-4.5 0 1e2 0 0 255
+3 0 1 1
+",
+        )
+        .unwrap();
+
+        assert_eq!(positions(&points), [[1.0, 2.0, 3.0], [-4.5, 0.0, 100.0]]);
+        assert_eq!(points[0].rgb, Some(0xFF_80_00));
+        assert_eq!(points[1].rgb, Some(0x00_00_FF));
+    }
+
+    #[test]
+    fn reads_binary_little_endian() {
+        let mut contents = b"ply
+format binary_little_endian 1.0
+element vertex 2
+property float x
+property float y
+property float z
+property float intensity
+property float red
+property float green
+property float blue
+end_header
+"
+        .to_vec();
+        for value in [1.0f32, 2.0, 3.0, 0.5, 1.0, 0.0, 0.5] {
+            contents.extend(value.to_le_bytes());
+        }
+        for value in [-1.0f32, -2.0, -3.0, 0.25, 0.0, 0.0, 0.0] {
+            contents.extend(value.to_le_bytes());
+        }
+
+        let points = read_fixture("little.ply", &contents).unwrap();
+
+        assert_eq!(positions(&points), [[1.0, 2.0, 3.0], [-1.0, -2.0, -3.0]]);
+        assert_eq!(points[0].intensity, 0.5);
+        // Float colors are in [0, 1].
+        assert_eq!(points[0].rgb, Some(0xFF_00_80));
+    }
+
+    #[test]
+    fn reads_binary_big_endian_after_other_elements() {
+        let mut contents = b"ply
+format binary_big_endian 1.0
+element camera 1
+property list uchar short view
+element vertex 1
+property double x
+property double y
+property double z
+property ushort intensity
+end_header
+"
+        .to_vec();
+        contents.push(2);
+        contents.extend(7i16.to_be_bytes());
+        contents.extend((-8i16).to_be_bytes());
+        for value in [1.5f64, -2.25, 1e6] {
+            contents.extend(value.to_be_bytes());
+        }
+        contents.extend(300u16.to_be_bytes());
+
+        let points = read_fixture("big.ply", &contents).unwrap();
+
+        assert_eq!(positions(&points), [[1.5, -2.25, 1e6]]);
+        assert_eq!(points[0].intensity, 300.0);
+        assert_eq!(points[0].rgb, None);
+    }
+
+    #[test]
+    fn a_corrupt_vertex_count_fails_without_reserving_for_it() {
+        let result = read_fixture(
+            "corrupt.ply",
+            b"ply
+format ascii 1.0
+element vertex 4000000000000
+property float x
+property float y
+property float z
+end_header
+1 2 3
+",
+        );
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn rejects_truncated_and_incomplete_files() {
+        let truncated = read_fixture(
+            "truncated.ply",
+            b"ply
+format ascii 1.0
+element vertex 2
+property float x
+property float y
+property float z
+end_header
+1 2 3
+",
+        );
+        assert!(truncated.is_err());
+
+        let no_z = read_fixture(
+            "no_z.ply",
+            b"ply
+format ascii 1.0
+element vertex 1
+property float x
+property float y
+end_header
+1 2
+",
+        );
+        assert!(no_z.is_err_and(|e| e.to_string().contains("'z'")));
+
+        assert!(read_fixture("not.ply", b"solid cube\n").is_err());
+    }
+
+    #[test]
+    fn keeps_header_comments() {
+        let path = loader::fixture(
+            "comments.ply",
+            b"ply
+format ascii 1.0
+comment up_axis Y
+obj_info scanned  indoors
+element vertex 1
+property float x
+property float y
+property float z
+end_header
+1 2 3
+",
+        );
+
+        let (points, comments) = read(&path, &Progress::default()).unwrap();
+        assert_eq!(points.len(), 1);
+        assert_eq!(comments, ["up_axis Y", "scanned indoors"]);
+    }
+}
@@ -1,129 +1,1675 @@
-use std::{mem, path::PathBuf, sync::Arc};
+use std::{
+    borrow::Cow,
+    mem,
+    ops::{Range, RangeInclusive},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::SystemTime,
+};
 
-use super::{camera::Camera, texture::Texture};
+use super::{
+    camera::Camera,
+    cull::{CullChunk, Culler},
+    fs, las,
+    loader::{self, Progress},
+    lod::{self, Node},
+    ply, text,
+    texture::Texture,
+    up_axis::{self, Hints, UpAxis},
+    voxel,
+};
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use bytemuck::{Pod, Zeroable};
-use pcd_rs::{PcdDeserialize, Reader};
+use cgmath::{Deg, InnerSpace, Matrix4, Quaternion, Rotation, SquareMatrix, Vector3, Vector4};
+use pcd_rs::{DynReader, DynRecord, Field, Schema, ViewPoint};
+use serde::{Deserialize, Serialize};
 use wgpu::{
     util::{BufferInitDescriptor, DeviceExt},
     BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
     BindGroupLayoutEntry, BindingType, BlendComponent, BlendState, Buffer, BufferAddress,
-    BufferBindingType, BufferUsages, ColorTargetState, ColorWrites, CommandEncoder,
-    CompareFunction, DepthBiasState, DepthStencilState, Device, FragmentState, FrontFace, LoadOp,
-    MultisampleState, Operations, PipelineCompilationOptions, PipelineLayoutDescriptor,
-    PolygonMode, PrimitiveState, PrimitiveTopology, Queue, RenderPassColorAttachment,
-    RenderPassDepthStencilAttachment, RenderPassDescriptor, RenderPipeline,
-    RenderPipelineDescriptor, ShaderModuleDescriptor, ShaderSource, ShaderStages, StencilState,
-    StoreOp, SurfaceConfiguration, TextureView, VertexAttribute, VertexBufferLayout, VertexFormat,
-    VertexState, VertexStepMode,
+    BufferBindingType, BufferDescriptor, BufferUsages, ColorTargetState, ColorWrites,
+    CommandEncoder, CompareFunction, DepthBiasState, DepthStencilState, Device, FragmentState,
+    FrontFace, Limits, LoadOp, MultisampleState, Operations, PipelineCompilationOptions,
+    PipelineLayoutDescriptor, PolygonMode, PrimitiveState, PrimitiveTopology, Queue,
+    RenderPassColorAttachment, RenderPassDepthStencilAttachment, RenderPassDescriptor,
+    RenderPipeline, RenderPipelineDescriptor, ShaderModuleDescriptor, ShaderSource, ShaderStages,
+    StencilState, StoreOp, SurfaceConfiguration, TextureFormat, TextureView, VertexAttribute,
+    VertexBufferLayout, VertexFormat, VertexState, VertexStepMode,
 };
-use winit::window::Window;
-
-#[allow(dead_code)]
-#[derive(PcdDeserialize)]
-struct Point {
-    x: f32,
-    y: f32,
-    z: f32,
+use winit::{dpi::PhysicalSize, window::Window};
+
+pub(super) struct Point {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+    pub intensity: f32,
+    /// Packed `0xAARRGGBB`, as PCL stores it.
+    pub rgb: Option<u32>,
+    /// ASPRS class from a LAS file, kept for coloring by class.
+    pub classification: Option<u8>,
+}
+
+impl Point {
+    /// Organized clouds mark pixels without a return with NaN coordinates.
+    fn is_finite(&self) -> bool {
+        self.x.is_finite() && self.y.is_finite() && self.z.is_finite()
+    }
+}
+
+/// Column indices of the fields we understand within a PCD record.
+struct PointSchema {
+    x: usize,
+    y: usize,
+    z: usize,
+    intensity: Option<usize>,
+    rgb: Option<usize>,
+}
+
+impl PointSchema {
+    fn new(schema: &Schema) -> Result<Self> {
+        let find = |names: &[&str]| {
+            schema
+                .iter()
+                .position(|def| names.contains(&def.name.as_str()))
+        };
+
+        let require = |name: &str| find(&[name]).ok_or_else(|| anyhow!("missing '{}' field", name));
+
+        Ok(Self {
+            x: require("x")?,
+            y: require("y")?,
+            z: require("z")?,
+            intensity: find(&["intensity"]),
+            rgb: find(&["rgb", "rgba"]),
+        })
+    }
+
+    fn point(&self, record: &DynRecord) -> Point {
+        let fields = &record.0;
+
+        Point {
+            x: field_value(&fields[self.x]),
+            y: field_value(&fields[self.y]),
+            z: field_value(&fields[self.z]),
+            intensity: self
+                .intensity
+                .map_or(0.0, |index| field_value(&fields[index]) as f32),
+            rgb: self.rgb.map(|index| field_bits(&fields[index])),
+            classification: None,
+        }
+    }
+}
+
+/// Splits a packed PCL `rgb`/`rgba` value (`0xAARRGGBB`) into sRGB channels,
+/// marking the point as colored.
+fn unpack_rgb(bits: u32) -> [u8; 4] {
+    let [b, g, r, _] = bits.to_le_bytes();
+    [r, g, b, u8::MAX]
+}
+
+fn field_value(field: &Field) -> f64 {
+    let value = match field {
+        Field::I8(values) => values.first().map(|v| *v as f64),
+        Field::I16(values) => values.first().map(|v| *v as f64),
+        Field::I32(values) => values.first().map(|v| *v as f64),
+        Field::U8(values) => values.first().map(|v| *v as f64),
+        Field::U16(values) => values.first().map(|v| *v as f64),
+        Field::U32(values) => values.first().map(|v| *v as f64),
+        Field::F32(values) => values.first().map(|v| *v as f64),
+        Field::F64(values) => values.first().copied(),
+    };
+
+    value.unwrap_or(0.0)
+}
+
+/// Raw 32 bits of a packed PCL color, which may be stored as a float or an
+/// unsigned integer depending on the writer.
+fn field_bits(field: &Field) -> u32 {
+    let bits = match field {
+        Field::F32(values) => values.first().map(|v| v.to_bits()),
+        Field::U32(values) => values.first().copied(),
+        Field::I32(values) => values.first().map(|v| *v as u32),
+        _ => None,
+    };
+
+    bits.unwrap_or(0)
+}
+
+/// Axis-aligned bounds of a cloud in its original file coordinates.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Bounds {
+    pub min: [f64; 3],
+    pub max: [f64; 3],
+}
+
+impl Bounds {
+    /// Extents below this are treated as flat when scaling or fitting.
+    const MIN_EXTENT: f64 = 1e-6;
+
+    fn from_points(points: &[Point]) -> Option<Self> {
+        let mut points = points.iter().filter(|point| point.is_finite());
+        let first = points.next()?;
+        let mut bounds = Self {
+            min: [first.x, first.y, first.z],
+            max: [first.x, first.y, first.z],
+        };
+
+        for point in points {
+            for (axis, value) in [point.x, point.y, point.z].into_iter().enumerate() {
+                bounds.min[axis] = bounds.min[axis].min(value);
+                bounds.max[axis] = bounds.max[axis].max(value);
+            }
+        }
+
+        Some(bounds)
+    }
+
+    pub fn center(&self) -> [f64; 3] {
+        [0, 1, 2].map(|axis| (self.min[axis] + self.max[axis]) / 2.0)
+    }
+
+    fn half_extents(&self) -> [f64; 3] {
+        [0, 1, 2].map(|axis| (self.max[axis] - self.min[axis]) / 2.0)
+    }
+
+    /// Largest half-extent over the three axes.
+    pub fn half_extent(&self) -> f64 {
+        self.half_extents().into_iter().fold(0.0, f64::max)
+    }
+
+    /// Radius of the sphere through the corners of the box, never smaller
+    /// than [`Bounds::MIN_EXTENT`] so a single point still has a size to fit.
+    pub fn radius(&self) -> f64 {
+        self.half_extents()
+            .into_iter()
+            .map(|half| half * half)
+            .sum::<f64>()
+            .sqrt()
+            .max(Self::MIN_EXTENT)
+    }
+
+    /// Number of axes along which the cloud has no meaningful extent: 1 for
+    /// a plane, 2 for a line, 3 for a single point.
+    pub fn flat_axes(&self) -> usize {
+        self.half_extents()
+            .into_iter()
+            .filter(|half| *half < Self::MIN_EXTENT)
+            .count()
+    }
+
+    /// Factor that maps the largest half-extent to 1.
+    fn scale(&self) -> f64 {
+        1.0 / self.half_extent().max(Self::MIN_EXTENT)
+    }
+
+    /// Smallest bounds containing both.
+    fn union(&self, other: &Bounds) -> Bounds {
+        Bounds {
+            min: [0, 1, 2].map(|axis| self.min[axis].min(other.min[axis])),
+            max: [0, 1, 2].map(|axis| self.max[axis].max(other.max[axis])),
+        }
+    }
+
+    /// Maps file coordinates into the unit cube of these bounds.
+    fn normalize(&self, position: [f64; 3]) -> [f32; 3] {
+        let center = self.center();
+        let scale = self.scale();
+
+        [0, 1, 2].map(|axis| ((position[axis] - center[axis]) * scale) as f32)
+    }
+
+    /// Matrix form of [`Bounds::normalize`].
+    fn normalization(&self) -> Matrix4<f64> {
+        Matrix4::from_scale(self.scale()) * Matrix4::from_translation(-Vector3::from(self.center()))
+    }
+
+    /// Moves file coordinates so the center of these bounds is the origin.
+    /// Georeferenced coordinates in the millions keep only about half a
+    /// metre of f32 precision; offsets from the center keep their detail.
+    fn recenter(&self, position: [f64; 3]) -> [f32; 3] {
+        let center = self.center();
+
+        [0, 1, 2].map(|axis| (position[axis] - center[axis]) as f32)
+    }
+
+    /// Matrix form of [`Bounds::recenter`].
+    fn recentering(&self) -> Matrix4<f64> {
+        Matrix4::from_translation(-Vector3::from(self.center()))
+    }
+
+    /// Axis-aligned bounds of the box's corners after `matrix`, which has to
+    /// be affine.
+    fn transformed(&self, matrix: Matrix4<f64>) -> Bounds {
+        (0..8)
+            .map(|corner| {
+                let [x, y, z] = [0, 1, 2].map(|axis| {
+                    if corner >> axis & 1 == 0 {
+                        self.min[axis]
+                    } else {
+                        self.max[axis]
+                    }
+                });
+                let point = matrix * Vector4::new(x, y, z, 1.0);
+                let point = [point.x, point.y, point.z];
+                Bounds {
+                    min: point,
+                    max: point,
+                }
+            })
+            .reduce(|union, corner| union.union(&corner))
+            .unwrap()
+    }
+
+    /// Inverse of [`Bounds::normalize`].
+    fn denormalize(&self, position: [f32; 3]) -> [f64; 3] {
+        let center = self.center();
+        let scale = self.scale();
+
+        [0, 1, 2].map(|axis| position[axis] as f64 / scale + center[axis])
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Zeroable, Pod)]
+struct Uniform {
+    camera: [[f32; 4]; 4],
+    resolution: [f32; 2],
+    size: f32,
+    size_mode: u32,
+    size_limits: [f32; 2],
+    proj_scale: f32,
+    color_mode: u32,
+    height_range: [f32; 2],
+    _padding: [u32; 2],
+    /// Camera position in the rendered space, for the depth of round points.
+    eye: [f32; 3],
+    _eye_padding: u32,
+}
+
+impl Uniform {
+    fn layout(device: &Device) -> BindGroupLayout {
+        device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("uniform_bind_group_layout"),
+            entries: &[BindGroupLayoutEntry {
+                binding: 0,
+                visibility: ShaderStages::VERTEX | ShaderStages::FRAGMENT,
+                ty: BindingType::Buffer {
+                    ty: BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        })
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Zeroable, Pod)]
+struct Instance {
+    model: [f32; 3],
+    /// sRGB color; an alpha of zero means the point has no color of its own.
+    color: [u8; 4],
+    /// Intensity normalized to [0, 1] over the loaded cloud.
     intensity: f32,
 }
 
-#[repr(C)]
-#[derive(Clone, Copy, Zeroable, Pod)]
-struct Uniform {
-    camera: [[f32; 4]; 4],
-    resolution: [f32; 2],
-    size: f32,
-    _padding: u32,
-}
+impl Instance {
+    const ATTRIBUTES: [VertexAttribute; 3] = [
+        VertexAttribute {
+            format: VertexFormat::Float32x3,
+            offset: 0,
+            shader_location: 0,
+        },
+        VertexAttribute {
+            format: VertexFormat::Unorm8x4,
+            offset: mem::size_of::<[f32; 3]>() as BufferAddress,
+            shader_location: 1,
+        },
+        VertexAttribute {
+            format: VertexFormat::Float32,
+            offset: (mem::size_of::<[f32; 3]>() + mem::size_of::<[u8; 4]>()) as BufferAddress,
+            shader_location: 2,
+        },
+    ];
+
+    fn layout() -> VertexBufferLayout<'static> {
+        VertexBufferLayout {
+            array_stride: mem::size_of::<Instance>() as BufferAddress,
+            step_mode: VertexStepMode::Instance,
+            attributes: &Self::ATTRIBUTES,
+        }
+    }
+}
+
+/// Per-cloud values for the shader, bound at group 1 for each draw.
+#[repr(C)]
+#[derive(Clone, Copy, Zeroable, Pod)]
+struct CloudUniform {
+    /// The cloud's transform in the space it is rendered in.
+    model: [[f32; 4]; 4],
+    /// sRGB, used for points without a color of their own.
+    color: [f32; 4],
+}
+
+impl CloudUniform {
+    fn new(model: Matrix4<f64>, color: [u8; 3]) -> Self {
+        let [r, g, b] = color.map(|channel| channel as f32 / 255.0);
+        Self {
+            model: model.cast::<f32>().unwrap_or(Matrix4::identity()).into(),
+            color: [r, g, b, 1.0],
+        }
+    }
+
+    fn layout(device: &Device) -> BindGroupLayout {
+        device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("cloud_bind_group_layout"),
+            entries: &[BindGroupLayoutEntry {
+                binding: 0,
+                visibility: ShaderStages::VERTEX,
+                ty: BindingType::Buffer {
+                    ty: BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        })
+    }
+}
+
+/// How files are turned into clouds.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LoadOptions {
+    /// Move PCD points by the sensor pose in their header.
+    pub apply_viewpoint: bool,
+    /// Keep one averaged point per voxel of this edge length, in file units.
+    pub voxel_size: Option<f64>,
+}
+
+impl Default for LoadOptions {
+    fn default() -> Self {
+        Self {
+            apply_viewpoint: true,
+            voxel_size: None,
+        }
+    }
+}
+
+/// A parsed cloud, ready to be uploaded.
+#[derive(Clone)]
+pub struct CloudData {
+    path: PathBuf,
+    instances: Vec<Instance>,
+    bounds: Option<Bounds>,
+    /// Modification time of the file before it was read.
+    modified: Option<SystemTime>,
+    /// Points dropped for non-finite coordinates.
+    skipped: usize,
+    /// Valid points in the file, when it was downsampled.
+    downsampled_from: Option<usize>,
+    /// Octree leaves over `instances`, which are stored leaf by leaf.
+    nodes: Vec<Node>,
+    /// Which way the file looks to point up, from its metadata or shape.
+    up_axis: Option<UpAxis>,
+}
+
+impl CloudData {
+    pub fn path(&self) -> &PathBuf {
+        &self.path
+    }
+
+    pub fn up_axis(&self) -> Option<UpAxis> {
+        self.up_axis
+    }
+}
+
+/// Rigid motion applied to a cloud for rough registration, in file
+/// coordinates. Rotation is about the cloud's own bounding-box center.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Transform {
+    pub translation: [f64; 3],
+    /// Roll, pitch and yaw in degrees, about x, y and z, applied in that
+    /// order.
+    pub rotation: [f64; 3],
+}
+
+impl Transform {
+    fn matrix(&self, pivot: [f64; 3]) -> Matrix4<f64> {
+        let [roll, pitch, yaw] = self.rotation;
+        let pivot = Vector3::from(pivot);
+
+        Matrix4::from_translation(pivot + Vector3::from(self.translation))
+            * Matrix4::from_angle_z(Deg(yaw))
+            * Matrix4::from_angle_y(Deg(pitch))
+            * Matrix4::from_angle_x(Deg(roll))
+            * Matrix4::from_translation(-pivot)
+    }
+}
+
+/// Splits `count` instances into consecutive ranges of `max_chunk` each, the
+/// last holding whatever is left over.
+fn chunk_ranges(count: usize, max_chunk: usize) -> impl Iterator<Item = Range<usize>> {
+    let max_chunk = max_chunk.max(1);
+    (0..count)
+        .step_by(max_chunk)
+        .map(move |start| start..count.min(start + max_chunk))
+}
+
+/// The part of `range` inside `chunk`, relative to the chunk's start, or
+/// `None` if they do not overlap.
+fn clip_to_chunk(range: &Range<usize>, chunk: &Range<usize>) -> Option<Range<u32>> {
+    let start = range.start.max(chunk.start);
+    let end = range.end.min(chunk.end);
+    (start < end).then(|| (start - chunk.start) as u32..(end - chunk.start) as u32)
+}
+
+/// Size to allocate for a chunk of `size` bytes: half again as much, so a
+/// slightly larger reload still fits, but never more than a full chunk.
+fn chunk_capacity(size: BufferAddress, max_chunk: usize) -> BufferAddress {
+    let limit = (max_chunk * mem::size_of::<Instance>()) as BufferAddress;
+    (size + size / 2).min(limit)
+}
+
+/// One loaded file, drawn with its own instance buffers.
+pub struct Cloud {
+    path: PathBuf,
+    /// Normalized to the cloud's own bounds, as read.
+    instances: Vec<Instance>,
+    bounds: Bounds,
+    /// Modification time of the version shown, or of the last version that
+    /// failed to load, so a broken file is not retried until it changes.
+    modified: Option<SystemTime>,
+    /// Points dropped for non-finite coordinates.
+    skipped: usize,
+    /// Valid points in the file, when it was downsampled.
+    downsampled_from: Option<usize>,
+    /// Octree leaves, with spheres in the space last uploaded.
+    nodes: Vec<Node>,
+    /// Instances picked for this frame by the point budget.
+    draw_ranges: Vec<Range<usize>>,
+    /// One buffer per chunk of at most [`PointCloud::max_chunk`] instances,
+    /// each allocated with headroom.
+    instance_buffers: Vec<Buffer>,
+    /// Culling buffers, one per instance buffer.
+    cull_chunks: Vec<CullChunk>,
+    color: [u8; 3],
+    visible: bool,
+    transform: Transform,
+    /// Uniform buffer and its bind group, created on the first upload.
+    uniform: Option<(Buffer, BindGroup)>,
+}
+
+impl Cloud {
+    /// Takes over `instance_buffers`, so a cloud replacing another can write
+    /// into the old allocations. Nothing touches the GPU until
+    /// [`Cloud::upload`].
+    fn new(data: CloudData, bounds: Bounds, color: [u8; 3], instance_buffers: Vec<Buffer>) -> Self {
+        Self {
+            path: data.path,
+            instances: data.instances,
+            bounds,
+            modified: data.modified,
+            skipped: data.skipped,
+            downsampled_from: data.downsampled_from,
+            nodes: data.nodes,
+            draw_ranges: Vec::new(),
+            cull_chunks: Vec::new(),
+            instance_buffers,
+            color,
+            visible: true,
+            transform: Transform::default(),
+            uniform: None,
+        }
+    }
+
+    /// Swaps in a fresh read of the cloud's file, keeping its color,
+    /// visibility and transform. Nothing is uploaded until [`Cloud::upload`].
+    fn replace_data(&mut self, data: CloudData, bounds: Bounds) {
+        self.instances = data.instances;
+        self.bounds = bounds;
+        self.modified = data.modified;
+        self.skipped = data.skipped;
+        self.downsampled_from = data.downsampled_from;
+        self.nodes = data.nodes;
+    }
+
+    fn create_uniform(color: [u8; 3], device: &Device) -> (Buffer, BindGroup) {
+        let buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("cloud_uniform_buffer"),
+            contents: bytemuck::cast_slice(&[CloudUniform::new(Matrix4::identity(), color)]),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        });
+
+        let bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("cloud_bind_group"),
+            layout: &CloudUniform::layout(device),
+            entries: &[BindGroupEntry {
+                binding: 0,
+                resource: buffer.as_entire_binding(),
+            }],
+        });
+
+        (buffer, bind_group)
+    }
+
+    fn create_instance_buffer(size: BufferAddress, device: &Device) -> Buffer {
+        device.create_buffer(&BufferDescriptor {
+            label: Some("pointcloud_instance_buffer"),
+            size,
+            // Storage so the culling pass can read it.
+            usage: BufferUsages::VERTEX | BufferUsages::STORAGE | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    /// Uploads the instances normalized to `space`, or in file units
+    /// recentered on it when not `normalized`, in chunks of at most
+    /// `max_chunk` instances. A chunk's buffer is written in place when the
+    /// chunk fits, and otherwise replaced by one with room to grow, so
+    /// reloading a file of about the same size does not reallocate.
+    fn upload(
+        &mut self,
+        space: &Bounds,
+        normalized: bool,
+        max_chunk: usize,
+        device: &Device,
+        queue: &Queue,
+    ) {
+        if self.uniform.is_none() {
+            self.uniform = Some(Self::create_uniform(self.color, device));
+        }
+        // Buffers taken over from another cloud get culling buffers here.
+        while self.cull_chunks.len() < self.instance_buffers.len() {
+            self.cull_chunks.push(CullChunk::new(device));
+        }
+
+        let instances: Cow<[Instance]> = if normalized && *space == self.bounds {
+            Cow::Borrowed(&self.instances)
+        } else {
+            Cow::Owned(
+                self.instances
+                    .iter()
+                    .map(|instance| {
+                        let position = self.bounds.denormalize(instance.model);
+                        Instance {
+                            model: if normalized {
+                                space.normalize(position)
+                            } else {
+                                space.recenter(position)
+                            },
+                            ..*instance
+                        }
+                    })
+                    .collect(),
+            )
+        };
+
+        for node in &mut self.nodes {
+            node.fit(&instances, |instance| instance.model);
+        }
+
+        let ranges: Vec<_> = chunk_ranges(instances.len(), max_chunk).collect();
+        self.instance_buffers.truncate(ranges.len());
+        self.cull_chunks.truncate(ranges.len());
+
+        for (index, range) in ranges.into_iter().enumerate() {
+            let bytes: &[u8] = bytemuck::cast_slice(&instances[range]);
+            let size = bytes.len() as BufferAddress;
+
+            let fits = self
+                .instance_buffers
+                .get(index)
+                .is_some_and(|buffer| buffer.size() >= size);
+            if !fits {
+                let buffer = Self::create_instance_buffer(chunk_capacity(size, max_chunk), device);
+
+                if index < self.instance_buffers.len() {
+                    self.instance_buffers[index] = buffer;
+                    self.cull_chunks[index].invalidate();
+                } else {
+                    self.instance_buffers.push(buffer);
+                    self.cull_chunks.push(CullChunk::new(device));
+                }
+            }
+
+            queue.write_buffer(&self.instance_buffers[index], 0, bytes);
+        }
+    }
+
+    /// Each chunk's buffer with the instances it holds.
+    fn chunks(&self, max_chunk: usize) -> impl Iterator<Item = (&Buffer, Range<usize>)> {
+        self.instance_buffers
+            .iter()
+            .zip(chunk_ranges(self.instances.len(), max_chunk))
+    }
+
+    /// Bytes of the instance buffers holding current instances.
+    fn used_bytes(&self) -> BufferAddress {
+        (self.instances.len() * mem::size_of::<Instance>()) as BufferAddress
+    }
+
+    /// The part of `draw_ranges` within `chunk`, counted from its start.
+    fn chunk_draw_ranges<'a>(
+        &'a self,
+        chunk: &'a Range<usize>,
+    ) -> impl Iterator<Item = Range<u32>> + 'a {
+        self.draw_ranges
+            .iter()
+            .filter_map(|range| clip_to_chunk(range, chunk))
+    }
+
+    /// Bytes allocated for instances, including room to grow and the
+    /// buffers culling packs them into.
+    fn capacity(&self) -> BufferAddress {
+        let instances: BufferAddress = self.instance_buffers.iter().map(Buffer::size).sum();
+        let culled: BufferAddress = self.cull_chunks.iter().map(CullChunk::capacity).sum();
+        instances + culled
+    }
+
+    pub fn path(&self) -> &PathBuf {
+        &self.path
+    }
+
+    pub fn point_count(&self) -> usize {
+        self.instances.len()
+    }
+
+    /// sRGB color of points that have none of their own.
+    pub fn color(&self) -> [u8; 3] {
+        self.color
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
+
+    pub fn modified(&self) -> Option<SystemTime> {
+        self.modified
+    }
+
+    /// Points dropped while loading for non-finite coordinates.
+    pub fn skipped(&self) -> usize {
+        self.skipped
+    }
+
+    pub fn downsampled_from(&self) -> Option<usize> {
+        self.downsampled_from
+    }
+
+    /// Bounds in the file's original coordinates, before the transform.
+    pub fn bounds(&self) -> Bounds {
+        self.bounds
+    }
+
+    pub fn transform(&self) -> Transform {
+        self.transform
+    }
+
+    /// The transform as a matrix acting on file coordinates.
+    pub fn matrix(&self) -> Matrix4<f64> {
+        self.transform.matrix(self.bounds.center())
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum SizeMode {
+    Pixel,
+    World,
+}
+
+impl SizeMode {
+    const MIN_PIXEL_SIZE: f32 = 1.0;
+    const MAX_PIXEL_SIZE: f32 = 64.0;
+
+    pub const DEFAULT_POINT_SIZE: f32 = 1.5;
+    const POINT_SIZE_RANGE: RangeInclusive<f32> = 0.5..=20.0;
+
+    fn as_uniform(self) -> u32 {
+        match self {
+            SizeMode::Pixel => 0,
+            SizeMode::World => 1,
+        }
+    }
+}
+
+/// How each point's sprite is cut out.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PointShape {
+    Square,
+    /// A disc, given the depth of a sphere so that overlapping points
+    /// intersect.
+    Circle,
+}
+
+impl PointShape {
+    /// Only circles write their own depth; squares leave early depth testing
+    /// on.
+    fn fragment_entry_point(self) -> &'static str {
+        match self {
+            PointShape::Square => "fs_square",
+            PointShape::Circle => "fs_circle",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorMode {
+    Flat,
+    Intensity,
+    Height,
+}
+
+impl ColorMode {
+    pub const ALL: [ColorMode; 3] = [ColorMode::Flat, ColorMode::Intensity, ColorMode::Height];
+
+    pub fn next(self) -> Self {
+        match self {
+            ColorMode::Flat => ColorMode::Intensity,
+            ColorMode::Intensity => ColorMode::Height,
+            ColorMode::Height => ColorMode::Flat,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ColorMode::Flat => "flat",
+            ColorMode::Intensity => "intensity",
+            ColorMode::Height => "height",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|mode| mode.name() == name)
+    }
+
+    fn as_uniform(self) -> u32 {
+        match self {
+            ColorMode::Flat => 0,
+            ColorMode::Intensity => 1,
+            ColorMode::Height => 2,
+        }
+    }
+}
+
+/// The loaded clouds and the settings they are all drawn with.
+pub struct PointCloud {
+    uniform_buffer: Buffer,
+    uniform_bind_group: BindGroup,
+    clouds: Vec<Cloud>,
+    square_pipeline: RenderPipeline,
+    circle_pipeline: RenderPipeline,
+    size_mode: SizeMode,
+    point_size: f32,
+    world_point_size: f32,
+    shape: PointShape,
+    color_mode: ColorMode,
+    normalized: bool,
+    color_format: TextureFormat,
+    reversed_z: bool,
+    /// MSAA samples of the targets drawn to.
+    sample_count: u32,
+    /// Most instances one buffer may hold on this device.
+    max_chunk: usize,
+    culler: Culler,
+    /// Draw only the instances a compute pass finds inside the frustum.
+    culling: bool,
+    /// Instances left after culling, as last read back from the GPU.
+    visible_points: Option<usize>,
+    /// Points drawn per frame while the camera moves.
+    point_budget: usize,
+    /// Budget for this frame; grows towards the whole cloud while the camera
+    /// is still.
+    frame_budget: usize,
+    last_view_proj: [[f32; 4]; 4],
+    drawn_points: usize,
+}
+
+impl PointCloud {
+    /// Colors given to clouds in the order they are added, starting with the
+    /// flat yellow a single cloud has always had.
+    const CLOUD_COLORS: [[u8; 3]; 6] = [
+        [255, 255, 0],
+        [0, 255, 255],
+        [255, 0, 255],
+        [255, 128, 0],
+        [0, 255, 0],
+        [255, 64, 64],
+    ];
+
+    pub const DEFAULT_POINT_BUDGET: usize = 5_000_000;
+
+    pub fn new(
+        device: &Device,
+        camera: &Camera,
+        window: Arc<Window>,
+        config: &SurfaceConfiguration,
+    ) -> Self {
+        let size_mode = SizeMode::Pixel;
+        let point_size = SizeMode::DEFAULT_POINT_SIZE;
+        let world_point_size = 0.002;
+        let shape = PointShape::Circle;
+        let color_mode = ColorMode::Flat;
+
+        let uniform = Uniform {
+            camera: camera.get_view_proj(),
+            resolution: window.inner_size().into(),
+            size: point_size,
+            size_mode: size_mode.as_uniform(),
+            size_limits: [SizeMode::MIN_PIXEL_SIZE, SizeMode::MAX_PIXEL_SIZE],
+            proj_scale: camera.get_proj_scale(),
+            color_mode: color_mode.as_uniform(),
+            height_range: [0.0, 0.0],
+            _padding: [0; 2],
+            eye: camera.eye().into(),
+            _eye_padding: 0,
+        };
+
+        let uniform_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("pointcloud_uniform_buffer_layout"),
+            contents: bytemuck::cast_slice(&[uniform]),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        });
+
+        let uniform_bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("pointcloud_uniform_bind_group"),
+            layout: &Uniform::layout(device),
+            entries: &[BindGroupEntry {
+                binding: 0,
+                resource: uniform_buffer.as_entire_binding(),
+            }],
+        });
+
+        let reversed_z = camera.reversed_z();
+        let [square_pipeline, circle_pipeline] = [PointShape::Square, PointShape::Circle]
+            .map(|shape| Self::create_pipeline(device, config.format, reversed_z, 1, shape));
+
+        Self {
+            uniform_buffer,
+            uniform_bind_group,
+            clouds: Vec::new(),
+            square_pipeline,
+            circle_pipeline,
+            size_mode,
+            point_size,
+            world_point_size,
+            shape,
+            color_mode,
+            normalized: true,
+            color_format: config.format,
+            reversed_z,
+            sample_count: 1,
+            max_chunk: Self::max_chunk(&device.limits()),
+            culler: Culler::new(device),
+            culling: true,
+            visible_points: None,
+            point_budget: Self::DEFAULT_POINT_BUDGET,
+            frame_budget: Self::DEFAULT_POINT_BUDGET,
+            last_view_proj: [[0.0; 4]; 4],
+            drawn_points: 0,
+        }
+    }
+
+    /// Huge scans exceed the largest buffer some adapters allow, or can bind
+    /// for culling, so clouds are split into buffers of at most this many
+    /// instances.
+    fn max_chunk(limits: &Limits) -> usize {
+        let max_bytes = limits
+            .max_buffer_size
+            .min(limits.max_storage_buffer_binding_size as u64);
+        usize::try_from(max_bytes / mem::size_of::<Instance>() as u64).unwrap_or(usize::MAX)
+    }
+
+    /// CPU half of a load: parses and normalizes the file without touching the
+    /// GPU, so it can run on another thread. How far the file has been read
+    /// is reported to `progress`, which can also cancel the read.
+    pub fn read(path: &PathBuf, options: LoadOptions, progress: &Progress) -> Result<CloudData> {
+        // Taken first, so a write that lands while parsing still counts as a
+        // change.
+        let modified = Self::modified(path);
+
+        let mut comments = Vec::new();
+        let extension = Self::extension(path);
+        let mut points = match extension.as_deref() {
+            Some("pcd") => Self::read_pcd(path, options.apply_viewpoint, progress)?,
+            Some("ply") => {
+                let (points, ply_comments) = ply::read(path, progress)
+                    .map_err(|e| anyhow!("{}: {:#}", path.display(), e))?;
+                comments = ply_comments;
+                points
+            }
+            Some("las") => {
+                las::read(path, progress).map_err(|e| anyhow!("{}: {:#}", path.display(), e))?
+            }
+            Some("xyz" | "txt" | "csv") => {
+                text::read(path, progress).map_err(|e| anyhow!("{}: {:#}", path.display(), e))?
+            }
+            _ => return Err(anyhow!("{}: unsupported file type", path.display())),
+        };
+
+        // A single NaN would otherwise spread through bounds, normalization
+        // and the intensity range.
+        let total = points.len();
+        points.retain(Point::is_finite);
+        let skipped = total - points.len();
+        if skipped > 0 {
+            log::warn!(
+                "{}: skipped {} of {} points with non-finite coordinates",
+                path.display(),
+                skipped,
+                total
+            );
+        }
+
+        let valid = points.len();
+        let mut downsampled_from = None;
+        if let Some(leaf_size) = options
+            .voxel_size
+            .filter(|size| size.is_finite() && *size > 0.0)
+        {
+            points = voxel::downsample(&points, leaf_size);
+            downsampled_from = Some(valid);
+            log::info!(
+                "{}: downsampled {} points to {} with a voxel size of {}",
+                path.display(),
+                valid,
+                points.len(),
+                leaf_size
+            );
+        }
+
+        let bounds = Bounds::from_points(&points);
+        let mut instances = match &bounds {
+            Some(bounds) => Self::to_instance(&points, bounds),
+            None => Vec::new(),
+        };
+        let nodes = lod::build(&mut instances, |instance| instance.model);
+
+        let up_axis = up_axis::guess(&Hints {
+            georeferenced: extension.as_deref() == Some("las"),
+            comments: &comments,
+            extent: bounds.map_or([0.0; 3], |bounds| {
+                bounds.half_extents().map(|half| 2.0 * half)
+            }),
+        });
+
+        if let Some(bounds) = &bounds {
+            log::info!(
+                "loaded {} points from {}, bounds {:?} to {:?}",
+                instances.len(),
+                path.display(),
+                bounds.min,
+                bounds.max
+            );
+
+            match bounds.flat_axes() {
+                0 => {}
+                1 => log::warn!("{} is planar", path.display()),
+                2 => log::warn!("{} is a line", path.display()),
+                _ => log::warn!("{} collapses to a single point", path.display()),
+            }
+        }
+
+        Ok(CloudData {
+            path: path.clone(),
+            instances,
+            bounds,
+            modified,
+            skipped,
+            downsampled_from,
+            nodes,
+            up_axis,
+        })
+    }
+
+    /// The extension of `path` in lower case, which decides how it is read.
+    pub fn extension(path: &Path) -> Option<String> {
+        path.extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase())
+    }
+
+    /// Modification time of the file at `path`, if the platform has one.
+    pub fn modified(path: &Path) -> Option<SystemTime> {
+        std::fs::metadata(fs::os_path(path))
+            .and_then(|metadata| metadata.modified())
+            .ok()
+    }
+
+    /// GPU half of a load: replaces all displayed clouds with `data`, in
+    /// order. See [`PointCloud::add_data_many`] for what is returned.
+    pub fn set_data_many(
+        &mut self,
+        data: Vec<CloudData>,
+        device: &Device,
+        queue: &Queue,
+    ) -> Vec<Option<Bounds>> {
+        // The largest buffer is kept for the first new cloud, which usually
+        // comes from the same or a similar file.
+        let instance_buffers = self
+            .clouds
+            .drain(..)
+            .max_by_key(Cloud::capacity)
+            .map(|cloud| cloud.instance_buffers)
+            .unwrap_or_default();
+
+        self.push_clouds(data, instance_buffers, device, queue)
+    }
+
+    /// GPU half of a load: shows `data` next to the clouds already loaded,
+    /// uploading once for all of them. Returns the bounds of each in order;
+    /// a cloud without points is dropped, and its entry `None`.
+    pub fn add_data_many(
+        &mut self,
+        data: Vec<CloudData>,
+        device: &Device,
+        queue: &Queue,
+    ) -> Vec<Option<Bounds>> {
+        self.push_clouds(data, Vec::new(), device, queue)
+    }
+
+    /// Pushes the clouds of `data`, the first one taking over
+    /// `instance_buffers`, then uploads.
+    fn push_clouds(
+        &mut self,
+        data: Vec<CloudData>,
+        mut instance_buffers: Vec<Buffer>,
+        device: &Device,
+        queue: &Queue,
+    ) -> Vec<Option<Bounds>> {
+        let shown: Vec<_> = data
+            .into_iter()
+            .map(|data| {
+                let bounds = data.bounds?;
+                let color = self.next_color();
+                let buffers = mem::take(&mut instance_buffers);
+
+                self.clouds.push(Cloud::new(data, bounds, color, buffers));
+                Some(bounds)
+            })
+            .collect();
+        self.upload(device, queue);
+
+        shown
+    }
+
+    /// Swaps in a fresh read of a cloud's file, keeping its color,
+    /// visibility and transform. Data without points is ignored and `None`
+    /// returned, leaving the previous points on screen.
+    pub fn reload_data(
+        &mut self,
+        index: usize,
+        data: CloudData,
+        device: &Device,
+        queue: &Queue,
+    ) -> Option<Bounds> {
+        let bounds = data.bounds?;
+        self.clouds.get_mut(index)?.replace_data(data, bounds);
+        self.upload(device, queue);
+
+        Some(bounds)
+    }
+
+    pub fn set_cloud_modified(&mut self, index: usize, modified: Option<SystemTime>) {
+        if let Some(cloud) = self.clouds.get_mut(index) {
+            cloud.modified = modified;
+        }
+    }
+
+    /// Unloads every cloud, freeing their buffers.
+    pub fn clear(&mut self) {
+        self.clouds.clear();
+    }
+
+    pub fn remove_cloud(&mut self, index: usize, device: &Device, queue: &Queue) {
+        if index < self.clouds.len() {
+            self.clouds.remove(index);
+            self.upload(device, queue);
+        }
+    }
+
+    pub fn clouds(&self) -> &[Cloud] {
+        &self.clouds
+    }
+
+    pub fn set_cloud_visible(&mut self, index: usize, visible: bool) {
+        if let Some(cloud) = self.clouds.get_mut(index) {
+            cloud.visible = visible;
+        }
+    }
+
+    pub fn set_cloud_color(&mut self, index: usize, color: [u8; 3]) {
+        if let Some(cloud) = self.clouds.get_mut(index) {
+            cloud.color = color;
+        }
+    }
+
+    pub fn set_cloud_transform(&mut self, index: usize, transform: Transform) {
+        if let Some(cloud) = self.clouds.get_mut(index) {
+            cloud.transform = transform;
+        }
+    }
+
+    /// The first palette color no cloud uses yet.
+    fn next_color(&self) -> [u8; 3] {
+        Self::CLOUD_COLORS
+            .into_iter()
+            .find(|color| self.clouds.iter().all(|cloud| cloud.color != *color))
+            .unwrap_or(Self::CLOUD_COLORS[self.clouds.len() % Self::CLOUD_COLORS.len()])
+    }
+
+    pub fn is_normalized(&self) -> bool {
+        self.normalized
+    }
+
+    /// Switches between the normalized unit-cube view and the original file
+    /// coordinates, re-uploading the instances in the new space. World point
+    /// sizes are converted so points keep their size relative to the cloud.
+    pub fn set_normalized(&mut self, normalized: bool, device: &Device, queue: &Queue) {
+        if self.normalized == normalized {
+            return;
+        }
+
+        // A world size is in the units of the space it was picked in.
+        if let Some(bounds) = self.bounds() {
+            let scale = bounds.scale() as f32;
+            if normalized {
+                self.world_point_size *= scale;
+            } else {
+                self.world_point_size /= scale;
+            }
+        }
+
+        self.normalized = normalized;
+        self.upload(device, queue);
+    }
+
+    /// Bounding sphere of all clouds, moved by their transforms, in the space
+    /// they are currently rendered in.
+    pub fn bounding_sphere(&self) -> Option<([f32; 3], f32)> {
+        let bounds = self.rendered_bounds(|_| true)?;
+        Some((bounds.center().map(|v| v as f32), bounds.radius() as f32))
+    }
+
+    /// Union of the bounds of the clouds `include` picks, moved by their
+    /// transforms into the space the shader sees them in.
+    fn rendered_bounds(&self, include: impl Fn(&Cloud) -> bool) -> Option<Bounds> {
+        let space = self.space()?;
+
+        self.clouds
+            .iter()
+            .filter(|cloud| include(cloud))
+            .map(|cloud| cloud.bounds.transformed(cloud.matrix()).transformed(space))
+            .reduce(|union, bounds| union.union(&bounds))
+    }
+
+    /// Maps file coordinates into the space the shader sees them in: the
+    /// unit cube of the union of all bounds when normalized, otherwise file
+    /// units recentered on the union.
+    fn space(&self) -> Option<Matrix4<f64>> {
+        let bounds = self.bounds()?;
+        Some(if self.normalized {
+            bounds.normalization()
+        } else {
+            bounds.recentering()
+        })
+    }
+
+    /// Where the origin of the rendered space is in file coordinates, so
+    /// positions can be shown to the user in the file's own units. Zero when
+    /// normalized, where there are no file units to show.
+    pub fn origin(&self) -> [f64; 3] {
+        self.bounds()
+            .filter(|_| !self.normalized)
+            .map_or([0.0; 3], |bounds| bounds.center())
+    }
+
+    /// The space is over the union of all bounds, so clouds stay registered
+    /// relative to each other.
+    fn upload(&mut self, device: &Device, queue: &Queue) {
+        let Some(space) = self.bounds() else {
+            return;
+        };
+
+        for cloud in &mut self.clouds {
+            cloud.upload(&space, self.normalized, self.max_chunk, device, queue);
+        }
+    }
+
+    /// Z range over the visible clouds after their transforms, for height
+    /// coloring, which reads the transformed z.
+    fn height_range(&self) -> [f32; 2] {
+        self.rendered_bounds(|cloud| cloud.visible)
+            .map_or([0.0, 0.0], |bounds| {
+                [bounds.min[2] as f32, bounds.max[2] as f32]
+            })
+    }
+
+    pub fn update(
+        &mut self,
+        camera: &Camera,
+        device: &Device,
+        queue: &Queue,
+        resolution: PhysicalSize<u32>,
+    ) {
+        let uniform = Uniform {
+            camera: camera.get_view_proj(),
+            resolution: resolution.into(),
+            size: self.point_size(),
+            size_mode: self.size_mode.as_uniform(),
+            size_limits: [SizeMode::MIN_PIXEL_SIZE, SizeMode::MAX_PIXEL_SIZE],
+            proj_scale: camera.get_proj_scale(),
+            color_mode: self.color_mode.as_uniform(),
+            height_range: self.height_range(),
+            _padding: [0; 2],
+            eye: camera.eye().into(),
+            _eye_padding: 0,
+        };
+
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+
+        // The depth test and clear value have to agree with the projection
+        // on which end of the depth range is near.
+        debug_assert_eq!(
+            camera.depth_at(1.0) > camera.depth_at(2.0),
+            Texture::depth_compare(self.reversed_z) == CompareFunction::Greater,
+            "depth test does not match the camera's depth convention"
+        );
+
+        // Transforms are edited in file coordinates; conjugate them into the
+        // rendered space.
+        let space = self.space().unwrap_or(Matrix4::identity());
+        let mut models = Vec::with_capacity(self.clouds.len());
+
+        for cloud in &self.clouds {
+            let model = space * cloud.matrix() * space.invert().unwrap_or(Matrix4::identity());
+
+            if let Some((buffer, _)) = &cloud.uniform {
+                queue.write_buffer(
+                    buffer,
+                    0,
+                    bytemuck::cast_slice(&[CloudUniform::new(model, cloud.color)]),
+                );
+            }
+            models.push(model.cast().unwrap_or(Matrix4::identity()));
+        }
+
+        self.select_points(camera, &models, uniform.camera);
+
+        if self.culling {
+            // Point sprites reach past their center by up to half the largest
+            // size, so centers just off screen still count.
+            let margin = [resolution.width, resolution.height]
+                .map(|pixels| SizeMode::MAX_PIXEL_SIZE / pixels.max(1) as f32);
+            let view_proj = Matrix4::from(uniform.camera);
+
+            for (cloud, model) in self.clouds.iter_mut().zip(&models) {
+                if !cloud.visible {
+                    continue;
+                }
+
+                let model_view_proj = (view_proj * model).into();
+                for (index, chunk) in
+                    chunk_ranges(cloud.instances.len(), self.max_chunk).enumerate()
+                {
+                    let ranges: Vec<_> = cloud.chunk_draw_ranges(&chunk).collect();
+                    cloud.cull_chunks[index].prepare(
+                        &self.culler,
+                        &cloud.instance_buffers[index],
+                        &ranges,
+                        model_view_proj,
+                        margin,
+                        device,
+                        queue,
+                    );
+                }
+            }
+
+            self.poll_readback();
+        } else {
+            self.visible_points = None;
+        }
+    }
+
+    /// Picks how much of each octree leaf to draw this frame. While the
+    /// camera moves the point budget holds; once it stops, the budget doubles
+    /// every frame until the whole cloud is drawn.
+    fn select_points(
+        &mut self,
+        camera: &Camera,
+        models: &[Matrix4<f32>],
+        view_proj: [[f32; 4]; 4],
+    ) {
+        self.frame_budget = if view_proj == self.last_view_proj {
+            self.frame_budget.saturating_mul(2).max(self.point_budget)
+        } else {
+            self.point_budget
+        };
+        self.last_view_proj = view_proj;
+
+        let views: Vec<lod::View> = self
+            .clouds
+            .iter()
+            .zip(models)
+            .filter(|(cloud, _)| cloud.visible)
+            .flat_map(|(cloud, model)| {
+                cloud
+                    .nodes
+                    .iter()
+                    .map(|node| lod::View::new(node, model, camera.eye()))
+            })
+            .collect();
+        let mut counts = lod::select(&views, self.frame_budget).into_iter();
+
+        self.drawn_points = 0;
+        for cloud in self.clouds.iter_mut() {
+            cloud.draw_ranges.clear();
+            if !cloud.visible {
+                continue;
+            }
+
+            for node in &cloud.nodes {
+                let count = counts.next().unwrap_or(0);
+                let range = node.range.start..node.range.start + count;
+                self.drawn_points += count;
+
+                // Leaves drawn in full run into the next one.
+                match cloud.draw_ranges.last_mut() {
+                    Some(last) if last.end == range.start => last.end = range.end,
+                    _ if !range.is_empty() => cloud.draw_ranges.push(range),
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    pub fn point_budget(&self) -> usize {
+        self.point_budget
+    }
+
+    pub fn set_point_budget(&mut self, budget: usize) {
+        self.point_budget = budget.max(1);
+    }
+
+    /// Points drawn in the last frame after level-of-detail selection.
+    pub fn drawn_point_count(&self) -> usize {
+        self.drawn_points
+    }
+
+    /// Whether the next frames will draw more of the visible clouds, as the
+    /// budget grows while the view stays still.
+    pub fn is_refining(&self) -> bool {
+        let visible: usize = self
+            .clouds
+            .iter()
+            .filter(|cloud| cloud.visible)
+            .map(|cloud| cloud.instances.len())
+            .sum();
+        self.drawn_points < visible
+    }
+
+    /// Moves the culled point count along; true when a different one arrived.
+    pub fn poll_readback(&mut self) -> bool {
+        match self.culler.poll_count() {
+            Some(count) if self.culling => {
+                let changed = self.visible_points != Some(count);
+                self.visible_points = Some(count);
+                changed
+            }
+            _ => false,
+        }
+    }
+
+    pub fn is_reading_back(&self) -> bool {
+        self.culler.is_pending()
+    }
+
+    pub fn is_culling(&self) -> bool {
+        self.culling
+    }
+
+    pub fn set_culling(&mut self, culling: bool) {
+        self.culling = culling;
+    }
+
+    /// Points inside the view frustum a frame or two ago, while culling.
+    pub fn visible_point_count(&self) -> Option<usize> {
+        self.visible_points
+    }
+
+    pub fn draw(
+        &mut self,
+        encoder: &mut CommandEncoder,
+        view: &TextureView,
+        depth_texture: &Texture,
+        device: &Device,
+    ) {
+        if !self.clouds.iter().any(|cloud| cloud.visible) {
+            return;
+        }
+
+        if self.culling {
+            self.cull(encoder, device);
+        }
+
+        let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            label: Some("pointcloud_render_pass"),
+            color_attachments: &[Some(RenderPassColorAttachment {
+                view: &view,
+                resolve_target: None,
+                ops: Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: Some(RenderPassDepthStencilAttachment {
+                view: &depth_texture.view(),
+                depth_ops: Some(Operations {
+                    load: LoadOp::Clear(Texture::depth_clear_value(self.reversed_z)),
+                    store: StoreOp::Store,
+                }),
+                stencil_ops: None,
+            }),
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+
+        render_pass.set_pipeline(match self.shape {
+            PointShape::Square => &self.square_pipeline,
+            PointShape::Circle => &self.circle_pipeline,
+        });
+        render_pass.set_bind_group(0, &self.uniform_bind_group, &[]);
+
+        for cloud in self.clouds.iter().filter(|cloud| cloud.visible) {
+            let Some((_, bind_group)) = &cloud.uniform else {
+                continue;
+            };
+            render_pass.set_bind_group(1, bind_group, &[]);
+
+            if self.culling {
+                for cull in &cloud.cull_chunks {
+                    render_pass.set_vertex_buffer(0, cull.culled().slice(..));
+                    render_pass.draw_indirect(cull.args(), 0);
+                }
+                continue;
+            }
+
+            for (buffer, chunk) in cloud.chunks(self.max_chunk) {
+                let bytes = (chunk.len() * mem::size_of::<Instance>()) as BufferAddress;
+                render_pass.set_vertex_buffer(0, buffer.slice(..bytes));
+
+                for instances in cloud.chunk_draw_ranges(&chunk) {
+                    render_pass.draw(0..6, instances);
+                }
+            }
+        }
+    }
+
+    /// Packs the instances inside the frustum of each visible chunk, and
+    /// queues the count of them for reading back.
+    fn cull(&mut self, encoder: &mut CommandEncoder, device: &Device) {
+        {
+            let mut pass = self.culler.begin_pass(encoder);
+            for cloud in self.clouds.iter().filter(|cloud| cloud.visible) {
+                for cull in &cloud.cull_chunks {
+                    cull.dispatch(&mut pass);
+                }
+            }
+        }
+
+        let chunks = self
+            .clouds
+            .iter()
+            .filter(|cloud| cloud.visible)
+            .flat_map(|cloud| &cloud.cull_chunks);
+        self.culler.copy_counts(chunks, encoder, device);
+    }
+
+    /// Rebuilds the pipelines for the other depth convention; the camera has
+    /// to be switched along with it.
+    pub fn set_reversed_z(&mut self, reversed_z: bool, device: &Device) {
+        if reversed_z != self.reversed_z {
+            self.reversed_z = reversed_z;
+            self.rebuild_pipelines(device);
+        }
+    }
+
+    /// Rebuilds the pipelines for targets with `sample_count` samples; the
+    /// color and depth textures have to be recreated along with them.
+    pub fn set_sample_count(&mut self, sample_count: u32, device: &Device) {
+        if sample_count != self.sample_count {
+            self.sample_count = sample_count;
+            self.rebuild_pipelines(device);
+        }
+    }
+
+    fn rebuild_pipelines(&mut self, device: &Device) {
+        [self.square_pipeline, self.circle_pipeline] = [PointShape::Square, PointShape::Circle]
+            .map(|shape| {
+                Self::create_pipeline(
+                    device,
+                    self.color_format,
+                    self.reversed_z,
+                    self.sample_count,
+                    shape,
+                )
+            });
+    }
+
+    pub fn size_mode(&self) -> SizeMode {
+        self.size_mode
+    }
+
+    pub fn set_size_mode(&mut self, mode: SizeMode) {
+        self.size_mode = mode;
+    }
+
+    pub fn shape(&self) -> PointShape {
+        self.shape
+    }
+
+    pub fn set_shape(&mut self, shape: PointShape) {
+        self.shape = shape;
+    }
+
+    pub fn point_count(&self) -> usize {
+        self.clouds.iter().map(Cloud::point_count).sum()
+    }
+
+    /// Union of the clouds' bounds in their files' original coordinates.
+    pub fn bounds(&self) -> Option<Bounds> {
+        self.clouds
+            .iter()
+            .map(|cloud| cloud.bounds)
+            .reduce(|union, bounds| union.union(&bounds))
+    }
+
+    /// Bytes of the instance buffers holding points.
+    pub fn gpu_bytes(&self) -> u64 {
+        self.clouds.iter().map(Cloud::used_bytes).sum()
+    }
+
+    /// Bytes allocated for instance buffers, including room to grow.
+    pub fn gpu_capacity(&self) -> u64 {
+        self.clouds.iter().map(Cloud::capacity).sum()
+    }
+
+    /// File the most recently added cloud was loaded from.
+    pub fn path(&self) -> Option<&PathBuf> {
+        self.clouds.last().map(Cloud::path)
+    }
 
-impl Uniform {
-    fn layout(device: &Device) -> BindGroupLayout {
-        device.create_bind_group_layout(&BindGroupLayoutDescriptor {
-            label: Some("uniform_bind_group_layout"),
-            entries: &[BindGroupLayoutEntry {
-                binding: 0,
-                visibility: ShaderStages::VERTEX,
-                ty: BindingType::Buffer {
-                    ty: BufferBindingType::Uniform,
-                    has_dynamic_offset: false,
-                    min_binding_size: None,
-                },
-                count: None,
-            }],
-        })
+    pub fn color_mode(&self) -> ColorMode {
+        self.color_mode
     }
-}
 
-#[repr(C)]
-#[derive(Clone, Copy, Zeroable, Pod)]
-struct Instance {
-    model: [f32; 3],
-}
+    pub fn set_color_mode(&mut self, mode: ColorMode) {
+        self.color_mode = mode;
+    }
 
-impl Instance {
-    fn layout() -> VertexBufferLayout<'static> {
-        VertexBufferLayout {
-            array_stride: mem::size_of::<Instance>() as BufferAddress,
-            step_mode: VertexStepMode::Instance,
-            attributes: &[VertexAttribute {
-                format: VertexFormat::Float32x3,
-                offset: 0,
-                shader_location: 0,
-            }],
-        }
+    /// The size used in pixel mode, whichever mode is active.
+    pub fn pixel_point_size(&self) -> f32 {
+        self.point_size
     }
-}
 
-pub struct PointCloud {
-    uniform_buffer: Buffer,
-    uniform_bind_group: BindGroup,
-    instances: Vec<Instance>,
-    instance_buffer: Buffer,
-    pipeline: RenderPipeline,
-    point_size: f32,
-}
+    pub fn point_size(&self) -> f32 {
+        match self.size_mode {
+            SizeMode::Pixel => self.point_size,
+            SizeMode::World => self.world_point_size,
+        }
+    }
 
-impl PointCloud {
-    pub fn new(
-        device: &Device,
-        camera: &Camera,
-        window: Arc<Window>,
-        config: &SurfaceConfiguration,
-    ) -> Self {
-        let point_size = 1.5;
+    /// Clamped to [`PointCloud::point_size_range`], so repeated decrements
+    /// cannot make points vanish.
+    pub fn set_point_size(&mut self, size: f32) {
+        let range = self.point_size_range();
+        let size = size.clamp(*range.start(), *range.end());
 
-        let uniform = Uniform {
-            camera: camera.get_view_proj(),
-            resolution: window.inner_size().into(),
-            size: point_size,
-            _padding: 0,
-        };
+        match self.size_mode {
+            SizeMode::Pixel => self.point_size = size,
+            SizeMode::World => self.world_point_size = size,
+        }
+    }
 
-        let uniform_buffer = device.create_buffer_init(&BufferInitDescriptor {
-            label: Some("pointcloud_uniform_buffer_layout"),
-            contents: bytemuck::cast_slice(&[uniform]),
-            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
-        });
+    /// Sizes accepted in the active mode. World sizes are relative to the
+    /// cloud's radius so the range suits both normalized and original units.
+    pub fn point_size_range(&self) -> RangeInclusive<f32> {
+        match self.size_mode {
+            SizeMode::Pixel => SizeMode::POINT_SIZE_RANGE,
+            SizeMode::World => {
+                let radius = self.world_size_reference();
+                radius * 1e-5..=radius * 0.1
+            }
+        }
+    }
 
-        let uniform_bind_group = device.create_bind_group(&BindGroupDescriptor {
-            label: Some("pointcloud_uniform_bind_group"),
-            layout: &Uniform::layout(device),
-            entries: &[BindGroupEntry {
-                binding: 0,
-                resource: uniform_buffer.as_entire_binding(),
-            }],
-        });
+    pub fn default_point_size(&self) -> f32 {
+        match self.size_mode {
+            SizeMode::Pixel => SizeMode::DEFAULT_POINT_SIZE,
+            SizeMode::World => self.world_size_reference() * 1e-3,
+        }
+    }
 
-        let instances = Vec::new();
+    fn world_size_reference(&self) -> f32 {
+        self.bounding_sphere().map_or(1.0, |(_, radius)| radius)
+    }
 
-        let instance_buffer = device.create_buffer_init(&BufferInitDescriptor {
-            label: Some("pointcloud_instance_buffer"),
-            contents: &[],
-            usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
-        });
+    pub fn point_size_step(&self) -> f32 {
+        match self.size_mode {
+            SizeMode::Pixel => 0.1,
+            SizeMode::World => self.world_point_size * 0.1,
+        }
+    }
 
+    fn create_pipeline(
+        device: &Device,
+        color_format: TextureFormat,
+        reversed_z: bool,
+        sample_count: u32,
+        shape: PointShape,
+    ) -> RenderPipeline {
         let shader = device.create_shader_module(ShaderModuleDescriptor {
             label: Some("pointcloud_shader"),
             source: ShaderSource::Wgsl(include_str!("shaders/pointcloud.wgsl").into()),
@@ -131,11 +1677,11 @@ impl PointCloud {
 
         let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
             label: Some("pointcloud_pipeline_layout"),
-            bind_group_layouts: &[&Uniform::layout(&device)],
+            bind_group_layouts: &[&Uniform::layout(&device), &CloudUniform::layout(device)],
             push_constant_ranges: &[],
         });
 
-        let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+        device.create_render_pipeline(&RenderPipelineDescriptor {
             label: Some("pointcloud_pipeline"),
             layout: Some(&pipeline_layout),
             vertex: VertexState {
@@ -156,21 +1702,21 @@ impl PointCloud {
             depth_stencil: Some(DepthStencilState {
                 format: Texture::DEPTH_FORMAT,
                 depth_write_enabled: true,
-                depth_compare: CompareFunction::Less,
+                depth_compare: Texture::depth_compare(reversed_z),
                 stencil: StencilState::default(),
                 bias: DepthBiasState::default(),
             }),
             multisample: MultisampleState {
-                count: 1,
+                count: sample_count,
                 mask: !0,
                 alpha_to_coverage_enabled: false,
             },
             fragment: Some(FragmentState {
                 module: &shader,
-                entry_point: "fs_main",
+                entry_point: shape.fragment_entry_point(),
                 compilation_options: PipelineCompilationOptions::default(),
                 targets: &[Some(ColorTargetState {
-                    format: config.format,
+                    format: color_format,
                     blend: Some(BlendState {
                         color: BlendComponent::REPLACE,
                         alpha: BlendComponent::REPLACE,
@@ -180,125 +1726,589 @@ impl PointCloud {
             }),
             multiview: None,
             cache: None,
-        });
+        })
+    }
 
-        Self {
-            uniform_buffer,
-            uniform_bind_group,
-            instances,
-            instance_buffer,
-            pipeline,
-            point_size,
+    fn read_pcd(path: &PathBuf, apply_viewpoint: bool, progress: &Progress) -> Result<Vec<Point>> {
+        let file =
+            loader::open(path, progress).map_err(|e| anyhow!("{}: {}", path.display(), e))?;
+        let reader = match DynReader::from_reader(file) {
+            Ok(reader) => reader,
+            Err(e) => return Err(anyhow!("{}: {}", path.display(), e)),
+        };
+
+        let schema = PointSchema::new(&reader.meta().field_defs)
+            .map_err(|e| anyhow!("{}: {}", path.display(), e))?;
+        let (width, height) = (reader.meta().width, reader.meta().height);
+        let viewpoint = reader.meta().viewpoint.clone();
+
+        let mut points: Vec<Point> = match reader
+            .map(|record| record.map(|record| schema.point(&record)))
+            .collect()
+        {
+            Ok(points) => points,
+            Err(e) => return Err(anyhow!("{}: {}", path.display(), e)),
+        };
+
+        if height > 1 {
+            let invalid = points.iter().filter(|point| !point.is_finite()).count();
+            log::info!(
+                "{}: organized {}×{}, {:.0}% invalid",
+                path.display(),
+                width,
+                height,
+                100.0 * invalid as f64 / points.len().max(1) as f64
+            );
+        }
+
+        if apply_viewpoint {
+            Self::apply_viewpoint(path, &viewpoint, &mut points);
+        }
+
+        Ok(points)
+    }
+
+    /// Moves points from the sensor frame into the frame the header's
+    /// VIEWPOINT places the sensor in: rotation first, then translation.
+    fn apply_viewpoint(path: &PathBuf, viewpoint: &ViewPoint, points: &mut [Point]) {
+        let translation = Vector3::new(viewpoint.tx, viewpoint.ty, viewpoint.tz);
+        let rotation = Quaternion::new(viewpoint.qw, viewpoint.qx, viewpoint.qy, viewpoint.qz);
+
+        if rotation.magnitude2() == 0.0 || !rotation.magnitude2().is_finite() {
+            log::warn!("{}: ignoring invalid viewpoint rotation", path.display());
+            return;
+        }
+
+        let rotation = rotation.normalize();
+        if rotation == Quaternion::new(1.0, 0.0, 0.0, 0.0)
+            && translation == Vector3::new(0.0, 0.0, 0.0)
+        {
+            return;
+        }
+
+        log::info!(
+            "{}: applying viewpoint {:?} {:?}",
+            path.display(),
+            translation,
+            rotation
+        );
+
+        for point in points {
+            let moved =
+                rotation.rotate_vector(Vector3::new(point.x, point.y, point.z)) + translation;
+            (point.x, point.y, point.z) = (moved.x, moved.y, moved.z);
+        }
+    }
+
+    /// Centers the cloud on its bounding-box center and scales it uniformly so
+    /// the largest half-extent becomes 1, keeping the aspect ratio intact.
+    fn to_instance(points: &[Point], bounds: &Bounds) -> Vec<Instance> {
+        let [cx, cy, cz] = bounds.center();
+        let scale = bounds.scale();
+
+        let (min_intensity, max_intensity) = points
+            .iter()
+            .fold((f32::MAX, f32::MIN), |(min, max), point| {
+                (min.min(point.intensity), max.max(point.intensity))
+            });
+        let intensity_range = max_intensity - min_intensity;
+
+        let mut instances = Vec::new();
+
+        for point in points {
+            let instance = Instance {
+                model: [
+                    ((point.x - cx) * scale) as f32,
+                    ((point.y - cy) * scale) as f32,
+                    ((point.z - cz) * scale) as f32,
+                ],
+                color: point.rgb.map_or([0; 4], unpack_rgb),
+                intensity: if intensity_range > 0.0 {
+                    (point.intensity - min_intensity) / intensity_range
+                } else {
+                    0.0
+                },
+            };
+
+            instances.push(instance);
+        }
+
+        instances
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::Point3;
+
+    use super::*;
+
+    fn assert_bounds(bounds: Bounds, min: [f64; 3], max: [f64; 3]) {
+        for axis in 0..3 {
+            assert!((bounds.min[axis] - min[axis]).abs() < 1e-9, "{:?}", bounds);
+            assert!((bounds.max[axis] - max[axis]).abs() < 1e-9, "{:?}", bounds);
         }
     }
 
-    pub fn load_pcd(&mut self, path: &PathBuf, device: &Device) -> Result<()> {
-        let points = match Self::read_pcd(path) {
-            Ok(data) => data,
-            Err(e) => return Err(e),
+    #[test]
+    fn drops_points_with_non_finite_coordinates() {
+        let path = loader::fixture("holes.xyz", b"1 2 3\nnan 0 0\n4 5 inf\n7 8 9\n0 -inf 0\n");
+
+        let data = PointCloud::read(&path, LoadOptions::default(), &Progress::default()).unwrap();
+
+        assert_eq!(data.instances.len(), 2);
+        assert_eq!(data.skipped, 3);
+        assert_eq!(
+            data.bounds,
+            Some(Bounds {
+                min: [1.0, 2.0, 3.0],
+                max: [7.0, 8.0, 9.0],
+            })
+        );
+        assert!(data
+            .instances
+            .iter()
+            .all(|instance| instance.model.iter().all(|v| v.is_finite())));
+    }
+
+    #[test]
+    fn a_cloud_without_finite_points_has_no_bounds() {
+        let path = loader::fixture("empty.xyz", b"nan nan nan\n");
+
+        let data = PointCloud::read(&path, LoadOptions::default(), &Progress::default()).unwrap();
+
+        assert!(data.instances.is_empty());
+        assert_eq!(data.skipped, 1);
+        assert_eq!(data.bounds, None);
+    }
+
+    /// A PCD file of float fields called `fields`, in ASCII or binary. A
+    /// `rgb` value is the packed color, stored in a float's bits the way PCL
+    /// writes it.
+    fn pcd(fields: &[&str], rows: &[&[f64]], binary: bool) -> Vec<u8> {
+        let float = |name: &str, value: f64| {
+            if name == "rgb" {
+                f32::from_bits(value as u32)
+            } else {
+                value as f32
+            }
         };
 
-        self.instances = Self::to_instance(&points);
+        let mut contents = format!(
+            "VERSION .7\nFIELDS {}\nSIZE {}\nTYPE {}\nCOUNT {}\nWIDTH {}\nHEIGHT 1\n\
+             VIEWPOINT 0 0 0 1 0 0 0\nPOINTS {}\nDATA {}\n",
+            fields.join(" "),
+            vec!["4"; fields.len()].join(" "),
+            vec!["F"; fields.len()].join(" "),
+            vec!["1"; fields.len()].join(" "),
+            rows.len(),
+            rows.len(),
+            if binary { "binary" } else { "ascii" }
+        )
+        .into_bytes();
 
-        self.instance_buffer = device.create_buffer_init(&BufferInitDescriptor {
-            label: Some("pointcloud_instance_buffer"),
-            contents: bytemuck::cast_slice(&self.instances),
-            usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
-        });
+        for row in rows {
+            let values = fields
+                .iter()
+                .zip(row.iter())
+                .map(|(name, value)| float(name, *value));
+            if binary {
+                for value in values {
+                    contents.extend(value.to_le_bytes());
+                }
+            } else {
+                let line: Vec<_> = values.map(|value| value.to_string()).collect();
+                contents.extend(format!("{}\n", line.join(" ")).into_bytes());
+            }
+        }
 
-        Ok(())
+        contents
     }
 
-    pub fn update(&self, camera: &Camera, queue: &Queue, window: &Window) {
-        let uniform = Uniform {
-            camera: camera.get_view_proj(),
-            resolution: window.inner_size().into(),
-            size: self.point_size,
-            _padding: 0,
+    #[test]
+    fn reads_pcd_with_any_subset_of_fields() {
+        for binary in [false, true] {
+            let read = |fields: &[&str], rows: &[&[f64]]| {
+                let path = loader::fixture("subset.pcd", &pcd(fields, rows, binary));
+                PointCloud::read_pcd(&path, true, &Progress::default()).unwrap()
+            };
+
+            let xyz = read(&["x", "y", "z"], &[&[1.0, 2.0, 3.0]]);
+            assert_position(&xyz[0], [1.0, 2.0, 3.0]);
+            assert_eq!(xyz[0].intensity, 0.0);
+            assert_eq!(xyz[0].rgb, None);
+
+            let intensity = read(&["x", "y", "z", "intensity"], &[&[1.0, 2.0, 3.0, 0.75]]);
+            assert_position(&intensity[0], [1.0, 2.0, 3.0]);
+            assert_eq!(intensity[0].intensity, 0.75);
+            assert_eq!(intensity[0].rgb, None);
+
+            // Field order follows the header, not x y z first.
+            let rgb = read(
+                &["rgb", "z", "x", "y"],
+                &[&[0x00FF_8000 as f64, 3.0, 1.0, 2.0]],
+            );
+            assert_position(&rgb[0], [1.0, 2.0, 3.0]);
+            assert_eq!(rgb[0].intensity, 0.0);
+            assert_eq!(
+                rgb[0].rgb.map(unpack_rgb),
+                Some([0xFF, 0x80, 0x00, u8::MAX])
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_pcd_without_coordinates() {
+        let path = loader::fixture(
+            "flat.pcd",
+            &pcd(&["x", "y", "intensity"], &[&[1.0, 2.0, 0.5]], false),
+        );
+
+        let error = PointCloud::read_pcd(&path, true, &Progress::default())
+            .err()
+            .unwrap();
+        assert!(error.to_string().contains("'z'"), "{}", error);
+    }
+
+    /// A quarter turn about z, then a shift by (1, 2, 3).
+    const QUARTER_TURN_PCD: &[u8] = b"\
+VERSION .7
+FIELDS x y z
+SIZE 4 4 4
+TYPE F F F
+COUNT 1 1 1
+WIDTH 2
+HEIGHT 1
+VIEWPOINT 1 2 3 0.70710678 0 0 0.70710678
+POINTS 2
+DATA ascii
+1 0 0
+0 2 5
+";
+
+    fn assert_position(point: &Point, expected: [f64; 3]) {
+        let actual = [point.x, point.y, point.z];
+        for axis in 0..3 {
+            assert!((actual[axis] - expected[axis]).abs() < 1e-6, "{:?}", actual);
+        }
+    }
+
+    #[test]
+    fn viewpoint_rotates_then_translates() {
+        let path = loader::fixture("quarter.pcd", QUARTER_TURN_PCD);
+
+        let points = PointCloud::read_pcd(&path, true, &Progress::default()).unwrap();
+
+        assert_position(&points[0], [1.0, 3.0, 3.0]);
+        assert_position(&points[1], [-1.0, 2.0, 8.0]);
+    }
+
+    #[test]
+    fn viewpoint_can_be_ignored() {
+        let path = loader::fixture("quarter.pcd", QUARTER_TURN_PCD);
+
+        let points = PointCloud::read_pcd(&path, false, &Progress::default()).unwrap();
+
+        assert_position(&points[0], [1.0, 0.0, 0.0]);
+        assert_position(&points[1], [0.0, 2.0, 5.0]);
+    }
+
+    #[test]
+    fn a_zero_viewpoint_rotation_is_ignored() {
+        let mut points = [Point {
+            x: 1.0,
+            y: 2.0,
+            z: 3.0,
+            intensity: 0.0,
+            rgb: None,
+            classification: None,
+        }];
+        let viewpoint = ViewPoint {
+            tx: 10.0,
+            ty: 0.0,
+            tz: 0.0,
+            qw: 0.0,
+            qx: 0.0,
+            qy: 0.0,
+            qz: 0.0,
         };
 
-        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+        PointCloud::apply_viewpoint(&PathBuf::from("zero.pcd"), &viewpoint, &mut points);
+
+        assert_position(&points[0], [1.0, 2.0, 3.0]);
     }
 
-    pub fn draw(&self, encoder: &mut CommandEncoder, view: &TextureView, depth_texture: &Texture) {
-        if self.instances.is_empty() {
-            return;
+    fn points(positions: &[[f64; 3]]) -> Vec<Point> {
+        positions
+            .iter()
+            .map(|&[x, y, z]| Point {
+                x,
+                y,
+                z,
+                intensity: 0.0,
+                rgb: None,
+                classification: None,
+            })
+            .collect()
+    }
+
+    /// Normalizes `positions` and checks the result against `expected`,
+    /// then that it maps back to where it came from.
+    fn assert_normalized(positions: &[[f64; 3]], expected: &[[f32; 3]]) {
+        let points = points(positions);
+        let bounds = Bounds::from_points(&points).unwrap();
+        let instances = PointCloud::to_instance(&points, &bounds);
+
+        // f32 keeps about seven digits of the unit cube.
+        let tolerance = 1e-6 / bounds.scale();
+
+        for ((instance, expected), position) in instances.iter().zip(expected).zip(positions) {
+            let restored = bounds.denormalize(instance.model);
+
+            for axis in 0..3 {
+                assert!(
+                    (instance.model[axis] - expected[axis]).abs() < 1e-6,
+                    "{:?}",
+                    instance.model
+                );
+                assert!((restored[axis] - position[axis]).abs() < tolerance);
+            }
         }
+    }
 
-        let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
-            label: Some("pointcloud_render_pass"),
-            color_attachments: &[Some(RenderPassColorAttachment {
-                view: &view,
-                resolve_target: None,
-                ops: Operations {
-                    load: wgpu::LoadOp::Load,
-                    store: wgpu::StoreOp::Store,
-                },
-            })],
-            depth_stencil_attachment: Some(RenderPassDepthStencilAttachment {
-                view: &depth_texture.view(),
-                depth_ops: Some(Operations {
-                    load: LoadOp::Clear(1.0),
-                    store: StoreOp::Store,
+    #[test]
+    fn normalizes_an_off_origin_cloud_around_its_center() {
+        // UTM coordinates, far from the origin.
+        assert_normalized(
+            &[
+                [400_000.0, 5_000_000.0, 300.0],
+                [400_100.0, 5_000_050.0, 310.0],
+                [400_050.0, 5_000_025.0, 305.0],
+            ],
+            &[[-1.0, -0.5, -0.1], [1.0, 0.5, 0.1], [0.0, 0.0, 0.0]],
+        );
+    }
+
+    #[test]
+    fn normalizes_an_all_negative_cloud() {
+        assert_normalized(
+            &[[-10.0, -4.0, -3.0], [-6.0, -2.0, -3.0], [-8.0, -3.0, -1.0]],
+            &[[-1.0, -0.5, -0.5], [1.0, 0.5, -0.5], [0.0, 0.0, 0.5]],
+        );
+    }
+
+    #[test]
+    fn degenerate_clouds_load_and_fit_without_nans() {
+        let fixtures: [(&str, &[u8], usize); 5] = [
+            ("plane.xyz", b"0 0 2\n4 0 2\n0 3 2\n4 3 2\n2 1 2\n", 1),
+            ("line.xyz", b"1 5 5\n2 5 5\n7 5 5\n", 2),
+            ("two.xyz", b"-1 -1 -1\n1 1 1\n", 0),
+            ("twice.xyz", b"3 3 3\n3 3 3\n", 3),
+            ("point.xyz", b"1e6 -2e6 30\n", 3),
+        ];
+
+        for (name, contents, flat_axes) in fixtures {
+            let path = loader::fixture(name, contents);
+            let data =
+                PointCloud::read(&path, LoadOptions::default(), &Progress::default()).unwrap();
+            let bounds = data.bounds.unwrap();
+
+            assert_eq!(bounds.flat_axes(), flat_axes, "{}", name);
+            assert!(
+                data.instances
+                    .iter()
+                    .all(|instance| instance.model.iter().all(|v| v.is_finite())),
+                "{}",
+                name
+            );
+            assert!(!data.nodes.is_empty(), "{}", name);
+            assert!(
+                data.nodes.iter().all(|node| {
+                    node.center.iter().all(|v| v.is_finite()) && node.radius.is_finite()
                 }),
-                stencil_ops: None,
-            }),
-            occlusion_query_set: None,
-            timestamp_writes: None,
-        });
+                "{}",
+                name
+            );
 
-        render_pass.set_pipeline(&self.pipeline);
-        render_pass.set_bind_group(0, &self.uniform_bind_group, &[]);
-        render_pass.set_vertex_buffer(0, self.instance_buffer.slice(..));
-        render_pass.draw(0..6, 0..self.instances.len() as _);
+            // Framed the way the engine frames a normalized cloud.
+            let radius = (bounds.radius() * bounds.scale()) as f32;
+            let mut camera = Camera::new(
+                Point3::new(0.0, -10.0, 0.0),
+                Point3::new(0.0, 0.0, 0.0),
+                Vector3::unit_z(),
+                1.5,
+                45.0,
+            );
+            camera.fit_to_bounds(Point3::new(0.0, 0.0, 0.0), radius, 1.5);
+            camera.fit_clip_planes(Point3::new(0.0, 0.0, 0.0), radius);
+
+            assert!(
+                camera
+                    .get_view_proj()
+                    .iter()
+                    .flatten()
+                    .all(|v| v.is_finite()),
+                "{}",
+                name
+            );
+        }
     }
 
-    pub fn point_size(&self) -> f32 {
-        self.point_size
+    #[test]
+    fn chunks_cover_every_instance_once() {
+        let ranges: Vec<_> = chunk_ranges(10, 4).collect();
+        assert_eq!(ranges, [0..4, 4..8, 8..10]);
+
+        assert_eq!(chunk_ranges(8, 4).collect::<Vec<_>>(), [0..4, 4..8]);
+        assert_eq!(chunk_ranges(3, 4).collect::<Vec<_>>(), vec![0..3]);
+        assert_eq!(chunk_ranges(0, 4).count(), 0);
+        // A zero limit would never advance.
+        assert_eq!(chunk_ranges(2, 0).collect::<Vec<_>>(), [0..1, 1..2]);
     }
 
-    pub fn set_point_size(&mut self, size: f32) {
-        self.point_size = size;
+    #[test]
+    fn draw_ranges_split_across_chunks() {
+        let chunks: Vec<_> = chunk_ranges(10, 4).collect();
+        let clipped: Vec<_> = chunks
+            .iter()
+            .map(|chunk| clip_to_chunk(&(2..9), chunk))
+            .collect();
+
+        assert_eq!(clipped, [Some(2..4), Some(0..4), Some(0..1)]);
+        assert_eq!(clip_to_chunk(&(0..4), &(4..8)), None);
     }
 
-    fn read_pcd(path: &PathBuf) -> Result<Vec<Point>> {
-        let reader = match Reader::open(path) {
-            Ok(reader) => reader,
-            Err(e) => return Err(e),
+    #[test]
+    fn chunk_buffers_grow_with_headroom_up_to_a_full_chunk() {
+        let instance = mem::size_of::<Instance>() as BufferAddress;
+
+        assert_eq!(chunk_capacity(100 * instance, 1000), 150 * instance);
+        assert_eq!(chunk_capacity(900 * instance, 1000), 1000 * instance);
+    }
+
+    #[test]
+    fn chunks_fit_the_smaller_buffer_limit() {
+        let instance = mem::size_of::<Instance>() as u64;
+        let limits = Limits {
+            max_buffer_size: 1000 * instance,
+            max_storage_buffer_binding_size: (400 * instance) as u32,
+            ..Limits::default()
         };
 
-        let points: Vec<Point> = match reader.collect() {
-            Ok(points) => points,
-            Err(e) => return Err(e),
+        assert_eq!(PointCloud::max_chunk(&limits), 400);
+    }
+
+    #[test]
+    fn transformed_bounds_follow_a_rotation_about_the_center() {
+        let bounds = Bounds {
+            min: [0.0, 0.0, 0.0],
+            max: [2.0, 4.0, 1.0],
+        };
+        let transform = Transform {
+            translation: [0.0, 0.0, 0.0],
+            rotation: [0.0, 0.0, 90.0],
         };
 
-        Ok(points)
+        assert_bounds(
+            bounds.transformed(transform.matrix(bounds.center())),
+            [-1.0, 1.0, 0.0],
+            [3.0, 3.0, 1.0],
+        );
     }
 
-    fn to_instance(points: &Vec<Point>) -> Vec<Instance> {
-        let mut max_value = f32::MIN;
+    #[test]
+    fn transformed_bounds_move_the_height_range() {
+        let bounds = Bounds {
+            min: [0.0, 0.0, 0.0],
+            max: [1.0, 1.0, 2.0],
+        };
+        let lift = Transform {
+            translation: [0.0, 0.0, 5.0],
+            rotation: [0.0, 0.0, 0.0],
+        };
+        // Tipping the cloud over about x lays its height along y.
+        let tip = Transform {
+            translation: [0.0, 0.0, 0.0],
+            rotation: [90.0, 0.0, 0.0],
+        };
 
-        for point in points {
-            let tmp = point.x.max(point.y).max(point.z);
-            if max_value < tmp {
-                max_value = tmp;
-            }
-        }
+        assert_bounds(
+            bounds.transformed(lift.matrix(bounds.center())),
+            [0.0, 0.0, 5.0],
+            [1.0, 1.0, 7.0],
+        );
+        assert_bounds(
+            bounds.transformed(tip.matrix(bounds.center())),
+            [0.0, -0.5, 0.5],
+            [1.0, 1.5, 1.5],
+        );
+    }
 
-        let mut instances = Vec::new();
+    #[test]
+    fn normalization_maps_transformed_bounds_into_the_unit_cube() {
+        let space = Bounds {
+            min: [10.0, 20.0, 30.0],
+            max: [14.0, 22.0, 31.0],
+        };
 
-        for point in points {
-            let instance = Instance {
-                model: [
-                    point.x / max_value,
-                    point.y / max_value,
-                    point.z / max_value,
-                ],
-            };
+        assert_bounds(
+            space.transformed(space.normalization()),
+            [-1.0, -0.5, -0.25],
+            [1.0, 0.5, 0.25],
+        );
+    }
 
-            instances.push(instance);
+    #[test]
+    fn true_scale_keeps_centimetres_at_utm_magnitudes() {
+        // A 100 m tile at a typical UTM easting and northing.
+        let space = Bounds {
+            min: [500_000.0, 4_500_000.0, 10.0],
+            max: [500_100.0, 4_500_100.0, 60.0],
+        };
+        let position = [500_012.34, 4_500_087.65, 21.09];
+
+        let recentered = space.recenter(position);
+        let restored = [0, 1, 2].map(|axis| recentered[axis] as f64 + space.center()[axis]);
+        for axis in 0..3 {
+            assert!(
+                (restored[axis] - position[axis]).abs() < 1e-4,
+                "{:?}",
+                restored
+            );
         }
+        // Plain f32 file coordinates are half a metre apart there.
+        assert!((position[1] as f32 as f64 - position[1]).abs() > 0.01);
 
-        instances
+        assert_bounds(
+            space.transformed(space.recentering()),
+            [-50.0, -50.0, -25.0],
+            [50.0, 50.0, 25.0],
+        );
+    }
+
+    #[test]
+    fn reloading_keeps_color_visibility_and_transform() {
+        let path = loader::fixture("reload.xyz", b"0 0 0\n1 1 1\n");
+        let read =
+            || PointCloud::read(&path, LoadOptions::default(), &Progress::default()).unwrap();
+
+        let data = read();
+        let mut cloud = Cloud::new(data.clone(), data.bounds.unwrap(), [1, 2, 3], Vec::new());
+        let transform = Transform {
+            translation: [1.0, 0.0, 0.0],
+            rotation: [0.0, 0.0, 90.0],
+        };
+        cloud.visible = false;
+        cloud.transform = transform;
+
+        std::fs::write(&*path, b"0 0 0\n2 2 2\n4 4 4\n").unwrap();
+        let data = read();
+        cloud.replace_data(data.clone(), data.bounds.unwrap());
+
+        assert_eq!(cloud.instances.len(), 3);
+        assert_bounds(cloud.bounds, [0.0; 3], [4.0; 3]);
+        assert_eq!(cloud.color, [1, 2, 3]);
+        assert!(!cloud.visible);
+        assert_eq!(cloud.transform, transform);
     }
 }
@@ -0,0 +1,271 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    camera::Sensitivity,
+    number::NumberFormat,
+    pointcloud::{ColorMode, PointCloud, PointShape, SizeMode},
+};
+
+/// Viewer preferences restored on the next launch, stored as TOML in the
+/// platform config directory.
+///
+/// Every field has a default, so a file written by an older version (or
+/// edited by hand) still loads with whatever it does contain.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// Size in pixels; world sizes depend on the cloud and are not kept.
+    pub point_size: f32,
+    /// Points drawn per frame while the camera moves.
+    pub point_budget: usize,
+    pub point_shape: PointShape,
+    pub color_mode: ColorMode,
+    /// Draw frames back to back even when nothing changes, for benchmarking.
+    pub continuous_redraw: bool,
+    /// sRGB.
+    pub background_color: [u8; 3],
+    pub number_format: NumberFormat,
+    /// Extensions of files turned from Y-up to Z-up without asking.
+    pub y_up_extensions: Vec<String>,
+    pub sensitivity: Sensitivity,
+    pub window: WindowSettings,
+    pub recent_files: RecentFiles,
+}
+
+/// Settings given for one run, e.g. on the command line. They are shown but
+/// not saved for as long as they are left as given.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Overrides {
+    /// In pixels.
+    pub point_size: Option<f32>,
+    pub color_mode: Option<ColorMode>,
+    /// sRGB.
+    pub background_color: Option<[u8; 3]>,
+}
+
+impl Overrides {
+    /// Puts back the `saved` value of each setting still at its override, so
+    /// that only settings changed since are written.
+    pub fn restore(&self, settings: &mut Settings, saved: &Settings) {
+        if self.point_size == Some(settings.point_size) {
+            settings.point_size = saved.point_size;
+        }
+        if self.color_mode == Some(settings.color_mode) {
+            settings.color_mode = saved.color_mode;
+        }
+        if self.background_color == Some(settings.background_color) {
+            settings.background_color = saved.background_color;
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WindowSettings {
+    /// Logical size of the restored (not maximized) window.
+    pub width: u32,
+    pub height: u32,
+    pub maximized: bool,
+}
+
+/// Successfully opened files, most recent first and without duplicates.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct RecentFiles(Vec<PathBuf>);
+
+impl RecentFiles {
+    const CAPACITY: usize = 10;
+
+    /// Moves `path` to the front, adding it if it is new. Relative paths are
+    /// made absolute so the entry still works from another directory.
+    pub fn push(&mut self, path: &Path) {
+        let path = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+
+        self.0.retain(|entry| *entry != path);
+        self.0.insert(0, path);
+        self.0.truncate(Self::CAPACITY);
+    }
+
+    pub fn remove(&mut self, path: &Path) {
+        self.0.retain(|entry| entry != path);
+    }
+
+    /// Drops entries whose file has been moved or deleted.
+    pub fn prune_missing(&mut self) {
+        self.0.retain(|entry| entry.exists());
+    }
+
+    pub fn paths(&self) -> &[PathBuf] {
+        &self.0
+    }
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            point_size: SizeMode::DEFAULT_POINT_SIZE,
+            point_budget: PointCloud::DEFAULT_POINT_BUDGET,
+            point_shape: PointShape::Circle,
+            color_mode: ColorMode::Flat,
+            continuous_redraw: false,
+            background_color: [0, 0, 0],
+            number_format: NumberFormat::default(),
+            y_up_extensions: Vec::new(),
+            sensitivity: Sensitivity::default(),
+            window: WindowSettings::default(),
+            recent_files: RecentFiles::default(),
+        }
+    }
+}
+
+impl Default for WindowSettings {
+    fn default() -> Self {
+        Self {
+            width: 1280,
+            height: 720,
+            maximized: false,
+        }
+    }
+}
+
+impl Settings {
+    /// Reads the settings file, falling back to defaults when it is missing
+    /// or cannot be parsed.
+    pub fn load() -> Self {
+        let Some(path) = Self::path() else {
+            return Self::default();
+        };
+
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Self::default(),
+            Err(e) => {
+                log::warn!("cannot read settings {}: {}", path.display(), e);
+                return Self::default();
+            }
+        };
+
+        toml::from_str(&text).unwrap_or_else(|e| {
+            log::warn!("ignoring invalid settings {}: {}", path.display(), e);
+            Self::default()
+        })
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let path = Self::path().ok_or_else(|| anyhow!("no config directory on this platform"))?;
+
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).with_context(|| format!("cannot create {}", dir.display()))?;
+        }
+
+        let text = toml::to_string_pretty(self)?;
+
+        // Write next to the target and rename over it, so a crash mid-write
+        // cannot leave a truncated file behind.
+        let temp = path.with_extension("toml.tmp");
+        fs::write(&temp, text).with_context(|| format!("cannot write {}", temp.display()))?;
+        fs::rename(&temp, &path).with_context(|| format!("cannot write {}", path.display()))?;
+
+        Ok(())
+    }
+
+    fn path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("pcvisualizer").join("settings.toml"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recent(paths: &[&str]) -> RecentFiles {
+        let mut recent = RecentFiles::default();
+        for path in paths.iter().rev() {
+            recent.push(Path::new(path));
+        }
+        recent
+    }
+
+    fn absolute(path: &str) -> PathBuf {
+        std::path::absolute(path).unwrap()
+    }
+
+    #[test]
+    fn recent_files_put_the_latest_first_without_duplicates() {
+        let mut recent = recent(&["b.pcd", "a.pcd", "c.pcd"]);
+        recent.push(Path::new("a.pcd"));
+
+        assert_eq!(recent.paths(), ["a.pcd", "b.pcd", "c.pcd"].map(absolute));
+    }
+
+    #[test]
+    fn recent_files_keep_only_the_latest_few() {
+        let mut recent = RecentFiles::default();
+        for index in 0..RecentFiles::CAPACITY + 2 {
+            recent.push(Path::new(&format!("{}.pcd", index)));
+        }
+
+        assert_eq!(recent.paths().len(), RecentFiles::CAPACITY);
+        assert_eq!(
+            recent.paths()[0],
+            absolute(&format!("{}.pcd", RecentFiles::CAPACITY + 1))
+        );
+        assert!(!recent.paths().contains(&absolute("0.pcd")));
+    }
+
+    #[test]
+    fn recent_files_match_relative_and_absolute_paths() {
+        let mut recent = recent(&["scan.pcd"]);
+        recent.push(&absolute("scan.pcd"));
+        assert_eq!(recent.paths(), [absolute("scan.pcd")]);
+
+        recent.remove(&absolute("scan.pcd"));
+        assert!(recent.paths().is_empty());
+    }
+
+    #[test]
+    fn overrides_left_as_given_are_not_saved() {
+        let saved = Settings::default();
+        let overrides = Overrides {
+            point_size: Some(4.0),
+            color_mode: Some(ColorMode::Height),
+            background_color: Some([30, 30, 30]),
+        };
+        let mut settings = Settings {
+            point_size: 4.0,
+            color_mode: ColorMode::Height,
+            background_color: [30, 30, 30],
+            ..saved.clone()
+        };
+
+        overrides.restore(&mut settings, &saved);
+        assert_eq!(settings, saved);
+    }
+
+    #[test]
+    fn overridden_settings_changed_since_are_saved() {
+        let saved = Settings::default();
+        let overrides = Overrides {
+            point_size: Some(4.0),
+            color_mode: Some(ColorMode::Height),
+            background_color: None,
+        };
+        let mut settings = Settings {
+            point_size: 6.0,
+            color_mode: ColorMode::Height,
+            background_color: [255, 255, 255],
+            ..saved.clone()
+        };
+
+        overrides.restore(&mut settings, &saved);
+        assert_eq!(settings.point_size, 6.0);
+        assert_eq!(settings.color_mode, saved.color_mode);
+        assert_eq!(settings.background_color, [255, 255, 255]);
+    }
+}
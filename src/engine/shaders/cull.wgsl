@@ -0,0 +1,72 @@
+// Frustum culling: copies the instances of the selected ranges that land
+// inside the view into a packed buffer and counts them into the arguments of
+// an indirect draw.
+
+// Matches `Instance` in pointcloud.rs, 20 bytes with the color packed.
+struct Instance {
+    x: f32,
+    y: f32,
+    z: f32,
+    color: u32,
+    intensity: f32,
+}
+
+struct Params {
+    model_view_proj: mat4x4<f32>,
+    // How far past the screen edge, in normalized device coordinates, a point
+    // center may lie and still have part of its sprite on screen.
+    margin: vec2<f32>,
+    range_count: u32,
+    total: u32,
+    groups_x: u32,
+}
+
+struct DrawArgs {
+    vertex_count: u32,
+    instance_count: atomic<u32>,
+    first_vertex: u32,
+    first_instance: u32,
+}
+
+@group(0) @binding(0) var<uniform> params: Params;
+@group(0) @binding(1) var<storage, read> instances: array<Instance>;
+// Start, count and the number of instances in the ranges before it.
+@group(0) @binding(2) var<storage, read> ranges: array<vec4<u32>>;
+@group(0) @binding(3) var<storage, read_write> culled: array<Instance>;
+@group(0) @binding(4) var<storage, read_write> args: DrawArgs;
+
+const WORKGROUP_SIZE: u32 = 256u;
+
+@compute @workgroup_size(256)
+fn cull(
+    @builtin(workgroup_id) group: vec3<u32>,
+    @builtin(local_invocation_index) local: u32,
+) {
+    let index = (group.y * params.groups_x + group.x) * WORKGROUP_SIZE + local;
+    if index >= params.total {
+        return;
+    }
+
+    // The last range starting at or before `index`.
+    var low = 0u;
+    var high = params.range_count - 1u;
+    while low < high {
+        let mid = (low + high + 1u) / 2u;
+        if ranges[mid].z <= index {
+            low = mid;
+        } else {
+            high = mid - 1u;
+        }
+    }
+    let range = ranges[low];
+    let instance = instances[range.x + index - range.z];
+
+    let clip = params.model_view_proj * vec4<f32>(instance.x, instance.y, instance.z, 1.0);
+    let reach = clip.w * (vec2<f32>(1.0) + params.margin);
+    if clip.w <= 0.0 || any(abs(clip.xy) > reach) || clip.z < 0.0 || clip.z > clip.w {
+        return;
+    }
+
+    let slot = atomicAdd(&args.instance_count, 1u);
+    culled[slot] = instance;
+}
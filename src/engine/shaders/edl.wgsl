@@ -0,0 +1,68 @@
+// Eye-dome lighting: darkens each point by how far it lies behind its
+// neighbors on screen, which outlines edges and brings out the shape of
+// clouds drawn in a single color.
+
+struct Params {
+    // `1 / distance = a * depth + b`, from the camera.
+    depth_to_inverse_distance: vec2<f32>,
+    strength: f32,
+    // Distance to the sampled neighbors, in pixels.
+    radius: f32,
+    // Depth of pixels no point was drawn to.
+    background: f32,
+}
+
+@group(0) @binding(0) var<uniform> params: Params;
+@group(0) @binding(1) var color: texture_2d<f32>;
+@group(0) @binding(2) var depth: texture_depth_2d;
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    // One triangle covering the screen.
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+// Log of the view distance at `coords`, or a huge value for the background.
+fn log_distance(coords: vec2<i32>) -> f32 {
+    let size = vec2<i32>(textureDimensions(depth));
+    let z = textureLoad(depth, clamp(coords, vec2<i32>(0), size - 1), 0);
+    if z == params.background {
+        return 1e30;
+    }
+    let inverse = dot(vec2<f32>(z, 1.0), params.depth_to_inverse_distance);
+    return -log2(max(inverse, 1e-30));
+}
+
+@fragment
+fn fs_main(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let coords = vec2<i32>(position.xy);
+    let base = textureLoad(color, coords, 0);
+
+    let center = log_distance(coords);
+    if center >= 1e30 {
+        return base;
+    }
+
+    var directions = array(
+        vec2<f32>(1.0, 0.0),
+        vec2<f32>(0.70710678, 0.70710678),
+        vec2<f32>(0.0, 1.0),
+        vec2<f32>(-0.70710678, 0.70710678),
+        vec2<f32>(-1.0, 0.0),
+        vec2<f32>(-0.70710678, -0.70710678),
+        vec2<f32>(0.0, -1.0),
+        vec2<f32>(0.70710678, -0.70710678),
+    );
+
+    var response = 0.0;
+    for (var i = 0; i < 8; i++) {
+        let offset = vec2<i32>(round(directions[i] * params.radius));
+        // Background neighbors are infinitely far and never shade.
+        response += max(0.0, center - log_distance(coords + offset));
+    }
+    response /= 8.0;
+
+    let shade = exp(-response * 300.0 * params.strength);
+    return vec4<f32>(base.rgb * shade, base.a);
+}
@@ -1,18 +1,108 @@
 struct InstanceInput {
     @location(0) position: vec3<f32>,
+    @location(1) color: vec4<f32>,
+    @location(2) intensity: f32,
 }
 
 struct Uniform {
     camera: mat4x4<f32>,
     resolution: vec2<f32>,
     size: f32,
+    size_mode: u32,
+    size_limits: vec2<f32>,
+    proj_scale: f32,
+    color_mode: u32,
+    height_range: vec2<f32>,
+    eye: vec3<f32>,
+}
+
+struct Cloud {
+    model: mat4x4<f32>,
+    color: vec4<f32>,
 }
 
 struct VertexOutput {
     @builtin(position) position: vec4<f32>,
+    @location(0) color: vec3<f32>,
+    // Position within the sprite, from -1 to 1 on each axis.
+    @location(1) uv: vec2<f32>,
+    // Depth of the point's center and of its front, were it a sphere.
+    @location(2) @interpolate(flat) depth: vec2<f32>,
+}
+
+struct FragmentOutput {
+    @location(0) color: vec4<f32>,
+    @builtin(frag_depth) depth: f32,
 }
 
 @group(0) @binding(0) var<uniform> uni: Uniform;
+@group(1) @binding(0) var<uniform> cloud: Cloud;
+
+const SIZE_MODE_WORLD: u32 = 1u;
+
+const COLOR_MODE_INTENSITY: u32 = 1u;
+const COLOR_MODE_HEIGHT: u32 = 2u;
+
+// Point colors come from the file in sRGB, but the surface expects linear
+// values and applies the encoding itself.
+fn srgb_to_linear(color: vec3<f32>) -> vec3<f32> {
+    let low = color / 12.92;
+    let high = pow((color + 0.055) / 1.055, vec3<f32>(2.4));
+    return select(high, low, color <= vec3<f32>(0.04045));
+}
+
+// Polynomial fit of the Turbo colormap, returned in sRGB.
+fn turbo(t: f32) -> vec3<f32> {
+    let r4 = vec4<f32>(0.13572138, 4.61539260, -42.66032258, 132.13108234);
+    let g4 = vec4<f32>(0.09140261, 2.19418839, 4.84296658, -14.18503333);
+    let b4 = vec4<f32>(0.10667330, 12.64194608, -60.58204836, 110.36276771);
+    let r2 = vec2<f32>(-152.94239396, 59.28637943);
+    let g2 = vec2<f32>(4.27729857, 2.82956604);
+    let b2 = vec2<f32>(-89.90310912, 27.34824973);
+
+    let x = clamp(t, 0.0, 1.0);
+    let v4 = vec4<f32>(1.0, x, x * x, x * x * x);
+    let v2 = v4.zw * v4.z;
+    let color = vec3<f32>(
+        dot(v4, r4) + dot(v2, r2),
+        dot(v4, g4) + dot(v2, g2),
+        dot(v4, b4) + dot(v2, b2),
+    );
+    return clamp(color, vec3<f32>(0.0), vec3<f32>(1.0));
+}
+
+fn point_color(instance: InstanceInput, height: f32) -> vec3<f32> {
+    if uni.color_mode == COLOR_MODE_INTENSITY {
+        return srgb_to_linear(turbo(instance.intensity));
+    }
+    if uni.color_mode == COLOR_MODE_HEIGHT {
+        let extent = uni.height_range.y - uni.height_range.x;
+        var t = 0.0;
+        if extent > 0.0 {
+            t = (height - uni.height_range.x) / extent;
+        }
+        return srgb_to_linear(turbo(t));
+    }
+    if instance.color.a > 0.0 {
+        return srgb_to_linear(instance.color.rgb);
+    }
+    // Points without a color of their own take their cloud's.
+    return srgb_to_linear(cloud.color.rgb);
+}
+
+fn pixel_size(clip_w: f32) -> f32 {
+    if uni.size_mode == SIZE_MODE_WORLD {
+        // Behind the eye or on its plane the projection is meaningless; such
+        // points are clipped anyway, so keep their quads small rather than
+        // dividing by zero.
+        if clip_w <= 1e-6 {
+            return uni.size_limits.x;
+        }
+        let projected = uni.size * uni.proj_scale * uni.resolution.y * 0.5 / clip_w;
+        return clamp(projected, uni.size_limits.x, uni.size_limits.y);
+    }
+    return uni.size;
+}
 
 @vertex
 fn vs_main(
@@ -29,14 +119,42 @@ fn vs_main(
     );
     var out: VertexOutput;
     let pos = points[vNdx];
-    let instance_pos = vec4<f32>(instance.position, 1.0);
-    let clip_pos = uni.camera * instance_pos;
-    let point_pos = vec4<f32>(pos * uni.size / uni.resolution * clip_pos.w, 0.0, 0.0);
+    let world_pos = cloud.model * vec4<f32>(instance.position, 1.0);
+    let clip_pos = uni.camera * world_pos;
+    let size = pixel_size(clip_pos.w);
+    let point_pos = vec4<f32>(pos * size / uni.resolution * clip_pos.w, 0.0, 0.0);
     out.position = clip_pos + point_pos;
+    out.color = point_color(instance, world_pos.z);
+    out.uv = pos;
+
+    // The sprite's radius in world units at its distance, and the point that
+    // far towards the eye.
+    let radius = size * clip_pos.w / (uni.proj_scale * uni.resolution.y);
+    let to_eye = uni.eye - world_pos.xyz;
+    let toward = min(radius, length(to_eye) * 0.5);
+    let front = uni.camera * vec4<f32>(world_pos.xyz + normalize(to_eye) * toward, 1.0);
+    out.depth = vec2<f32>(clip_pos.z / clip_pos.w, front.z / front.w);
     return out;
 }
 
+// Squares keep the rasterized depth, so they leave early depth testing on.
 @fragment
-fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    return vec4<f32>(1.0, 1.0, 0.0, 1.0);
+fn fs_square(in: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(in.color, 1.0);
+}
+
+@fragment
+fn fs_circle(in: VertexOutput) -> FragmentOutput {
+    let distance = dot(in.uv, in.uv);
+    if distance > 1.0 {
+        discard;
+    }
+
+    var out: FragmentOutput;
+    out.color = vec4<f32>(in.color, 1.0);
+    // Bulge towards the eye like a sphere, so overlapping discs cut into
+    // each other instead of stacking as flat cards.
+    let bulge = sqrt(1.0 - distance);
+    out.depth = clamp(mix(in.depth.x, in.depth.y, bulge), 0.0, 1.0);
+    return out;
 }
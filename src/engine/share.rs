@@ -0,0 +1,457 @@
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Result};
+
+use super::pointcloud::ColorMode;
+
+/// A view of a file that fits in a single string, for pasting into chat:
+///
+/// `pcviz://open?file=<path>&cam=<pose>&space=<normalized|world>&mode=<color mode>&clip=<fitted|infinite>`
+///
+/// Values are percent-encoded UTF-8. The pose is the eye and target as six
+/// little-endian `f32`s in unpadded URL-safe base64. Only `file` is required;
+/// a malformed optional component is dropped and listed in `ignored` so the
+/// file still opens.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ShareLink {
+    pub file: PathBuf,
+    /// Eye and target, in the space given by `normalized`.
+    pub camera: Option<([f32; 3], [f32; 3])>,
+    pub normalized: Option<bool>,
+    pub color_mode: Option<ColorMode>,
+    /// Whether the far clip plane is at infinity rather than fitted to the
+    /// clouds.
+    pub infinite_far: Option<bool>,
+    /// What was wrong with each component dropped while parsing, for telling
+    /// the user. Never written out.
+    pub ignored: Vec<String>,
+}
+
+impl ShareLink {
+    const PREFIX: &'static str = "pcviz://open?";
+
+    /// A link that only opens `file`, leaving the view to be filled in.
+    pub fn new(file: PathBuf) -> Self {
+        Self {
+            file,
+            camera: None,
+            normalized: None,
+            color_mode: None,
+            infinite_far: None,
+            ignored: Vec::new(),
+        }
+    }
+
+    pub fn is_link(text: &str) -> bool {
+        text.trim_start().starts_with(Self::PREFIX)
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        let query = text
+            .trim()
+            .strip_prefix(Self::PREFIX)
+            .ok_or_else(|| anyhow!("not a {} link", Self::PREFIX))?;
+
+        let mut file = None;
+        let mut camera = None;
+        let mut normalized = None;
+        let mut color_mode = None;
+        let mut infinite_far = None;
+        let mut ignored = Vec::new();
+
+        for component in query.split('&').filter(|component| !component.is_empty()) {
+            let Some((key, value)) = component
+                .split_once('=')
+                .and_then(|(key, value)| Some((key, percent_decode(value)?)))
+            else {
+                ignored.push(format!("malformed share link component '{}'", component));
+                continue;
+            };
+
+            let parsed = match key {
+                "file" => {
+                    file = Some(PathBuf::from(&value));
+                    true
+                }
+                "cam" => {
+                    camera = decode_pose(&value);
+                    camera.is_some()
+                }
+                "space" => {
+                    normalized = match value.as_str() {
+                        "normalized" => Some(true),
+                        "world" => Some(false),
+                        _ => None,
+                    };
+                    normalized.is_some()
+                }
+                "mode" => {
+                    color_mode = ColorMode::from_name(&value);
+                    color_mode.is_some()
+                }
+                "clip" => {
+                    infinite_far = match value.as_str() {
+                        "infinite" => Some(true),
+                        "fitted" => Some(false),
+                        _ => None,
+                    };
+                    infinite_far.is_some()
+                }
+                _ => {
+                    ignored.push(format!("unknown share link component '{}'", key));
+                    continue;
+                }
+            };
+
+            if !parsed {
+                ignored.push(format!("invalid share link {} '{}'", key, value));
+            }
+        }
+
+        for problem in &ignored {
+            log::warn!("ignoring {}", problem);
+        }
+
+        let file = file
+            .filter(|file| !file.as_os_str().is_empty())
+            .ok_or_else(|| anyhow!("share link does not name a file"))?;
+
+        Ok(Self {
+            file,
+            camera,
+            normalized,
+            color_mode,
+            infinite_far,
+            ignored,
+        })
+    }
+}
+
+impl fmt::Display for ShareLink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}file={}",
+            Self::PREFIX,
+            percent_encode(&self.file.to_string_lossy())
+        )?;
+
+        if let Some((eye, target)) = &self.camera {
+            write!(f, "&cam={}", encode_pose(eye, target))?;
+        }
+
+        if let Some(normalized) = self.normalized {
+            let space = if normalized { "normalized" } else { "world" };
+            write!(f, "&space={}", space)?;
+        }
+
+        if let Some(color_mode) = self.color_mode {
+            write!(f, "&mode={}", color_mode.name())?;
+        }
+
+        if let Some(infinite_far) = self.infinite_far {
+            let clip = if infinite_far { "infinite" } else { "fitted" };
+            write!(f, "&clip={}", clip)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// URL-safe base64 digits, so a pose needs no percent-encoding.
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+fn encode_pose(eye: &[f32; 3], target: &[f32; 3]) -> String {
+    let bytes: Vec<u8> = eye
+        .iter()
+        .chain(target)
+        .flat_map(|v| v.to_le_bytes())
+        .collect();
+
+    // 24 bytes make 32 digits without padding.
+    let mut encoded = String::with_capacity(32);
+    for group in bytes.chunks(3) {
+        let bits = (group[0] as u32) << 16 | (group[1] as u32) << 8 | group[2] as u32;
+        for shift in [18, 12, 6, 0] {
+            encoded.push(BASE64[(bits >> shift & 0x3F) as usize] as char);
+        }
+    }
+    encoded
+}
+
+fn decode_pose(text: &str) -> Option<([f32; 3], [f32; 3])> {
+    if text.len() != 32 {
+        return None;
+    }
+
+    let mut bytes = Vec::with_capacity(24);
+    for group in text.as_bytes().chunks(4) {
+        let mut bits = 0;
+        for &digit in group {
+            bits = bits << 6 | BASE64.iter().position(|&d| d == digit)? as u32;
+        }
+        bytes.extend_from_slice(&bits.to_be_bytes()[1..]);
+    }
+
+    let values: Vec<f32> = bytes
+        .chunks(4)
+        .map(|chunk| f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+        .collect();
+    if !values.iter().all(|v| v.is_finite()) {
+        return None;
+    }
+
+    Some((
+        [values[0], values[1], values[2]],
+        [values[3], values[4], values[5]],
+    ))
+}
+
+/// Registry values that make Windows hand `pcviz://` links to `exe`, as
+/// key, value name (empty for the default value) and data. Per user, so no
+/// elevation is needed.
+fn scheme_registry_values(exe: &Path) -> Vec<(String, &'static str, String)> {
+    const KEY: &str = r"HKCU\Software\Classes\pcviz";
+
+    vec![
+        (
+            KEY.to_string(),
+            "",
+            "URL:pcvisualizer share link".to_string(),
+        ),
+        (KEY.to_string(), "URL Protocol", String::new()),
+        (
+            format!(r"{}\shell\open\command", KEY),
+            "",
+            format!("\"{}\" \"%1\"", exe.display()),
+        ),
+    ]
+}
+
+/// Registers the running executable as the handler of `pcviz://` links for
+/// the current user, so opening one starts the viewer on it. Only Windows
+/// has a registry to write to.
+pub fn register_scheme() -> Result<()> {
+    let exe = std::env::current_exe()?;
+    let values = scheme_registry_values(&exe);
+
+    #[cfg(windows)]
+    {
+        for (key, name, data) in values {
+            let mut command = std::process::Command::new("reg");
+            command.args(["add", &key]);
+            match name {
+                "" => command.arg("/ve"),
+                name => command.args(["/v", name]),
+            };
+            let status = command.args(["/d", &data, "/f"]).status()?;
+            if !status.success() {
+                return Err(anyhow!("reg add {} failed with {}", key, status));
+            }
+        }
+        Ok(())
+    }
+
+    #[cfg(not(windows))]
+    {
+        let _ = values;
+        Err(anyhow!(
+            "registering the pcviz:// scheme is only supported on Windows"
+        ))
+    }
+}
+
+/// Eye and target from six comma-separated numbers, as taken on the command
+/// line.
+pub fn parse_camera(value: &str) -> Option<([f32; 3], [f32; 3])> {
+    let values = value
+        .split(',')
+        .map(|value| value.trim().parse::<f32>().ok().filter(|v| v.is_finite()))
+        .collect::<Option<Vec<f32>>>()?;
+
+    match values[..] {
+        [ex, ey, ez, tx, ty, tz] => Some(([ex, ey, ez], [tx, ty, tz])),
+        _ => None,
+    }
+}
+
+fn percent_encode(text: &str) -> String {
+    let mut encoded = String::with_capacity(text.len());
+
+    for byte in text.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+
+    encoded
+}
+
+fn percent_decode(text: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(text.len());
+    let mut input = text.bytes();
+
+    while let Some(byte) = input.next() {
+        if byte == b'%' {
+            let high = (input.next()? as char).to_digit(16)?;
+            let low = (input.next()? as char).to_digit(16)?;
+            bytes.push((high * 16 + low) as u8);
+        } else {
+            bytes.push(byte);
+        }
+    }
+
+    String::from_utf8(bytes).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use super::{decode_pose, encode_pose, scheme_registry_values, ShareLink};
+    use crate::engine::pointcloud::ColorMode;
+
+    fn link(file: &str) -> ShareLink {
+        ShareLink {
+            file: PathBuf::from(file),
+            camera: Some(([1.5, -2.25, 1e6], [0.0, -0.0, 3.0e-7])),
+            normalized: Some(false),
+            color_mode: Some(ColorMode::Height),
+            infinite_far: Some(true),
+            ignored: Vec::new(),
+        }
+    }
+
+    fn round_trip(link: &ShareLink) -> ShareLink {
+        ShareLink::parse(&link.to_string()).unwrap()
+    }
+
+    #[test]
+    fn round_trips_every_component() {
+        let link = link("/data/scans/site a/cloud.pcd");
+        assert_eq!(round_trip(&link), link);
+
+        let bare = ShareLink {
+            camera: None,
+            normalized: None,
+            color_mode: None,
+            infinite_far: None,
+            ..link.clone()
+        };
+        assert_eq!(round_trip(&bare), bare);
+    }
+
+    #[test]
+    fn round_trips_unicode_and_reserved_characters() {
+        for file in [
+            "/home/jürgen/点云/スキャン 1.pcd",
+            r"C:\Users\Zoë\scans\a&b=c%d?.pcd",
+            "relative/😀 #1.ply",
+        ] {
+            let link = link(file);
+            let text = link.to_string();
+            assert!(text.is_ascii(), "{}", text);
+            assert_eq!(text.matches('&').count(), 4, "{}", text);
+            assert_eq!(round_trip(&link), link);
+        }
+    }
+
+    #[test]
+    fn pose_encodes_to_url_safe_base64() {
+        let encoded = encode_pose(&[1.0, 2.0, 3.0], &[-1.0, f32::MAX, f32::MIN_POSITIVE]);
+        assert_eq!(encoded.len(), 32);
+        assert!(encoded
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_'));
+        assert_eq!(
+            decode_pose(&encoded),
+            Some(([1.0, 2.0, 3.0], [-1.0, f32::MAX, f32::MIN_POSITIVE]))
+        );
+    }
+
+    #[test]
+    fn rejects_links_without_a_file() {
+        assert!(ShareLink::parse("pcviz://open?cam=AAAA&mode=flat").is_err());
+        assert!(ShareLink::parse("pcviz://open?file=").is_err());
+        assert!(ShareLink::parse("pcviz://open?file=%ZZ").is_err());
+        assert!(ShareLink::parse("https://example.com/?file=a.pcd").is_err());
+    }
+
+    #[test]
+    fn drops_each_malformed_component_and_keeps_the_rest() {
+        let valid = link("a.pcd").to_string();
+        let nan_pose = encode_pose(&[f32::NAN, 0.0, 0.0], &[0.0; 3]);
+
+        for (key, bad) in [
+            ("cam", "1,2,3,4,5,6"),
+            ("cam", "AAAA"),
+            ("cam", "!AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA"),
+            ("cam", nan_pose.as_str()),
+            ("space", "sideways"),
+            ("mode", "rainbow"),
+            ("clip", "0.1,100"),
+            ("clip", "%E2%82"),
+        ] {
+            let start = valid.find(&format!("&{}=", key)).unwrap() + 1;
+            let end = valid[start..]
+                .find('&')
+                .map_or(valid.len(), |end| start + end);
+            let text = format!("{}{}={}{}", &valid[..start], key, bad, &valid[end..]);
+
+            let parsed = ShareLink::parse(&text).unwrap();
+            assert_eq!(parsed.file, PathBuf::from("a.pcd"), "{}", text);
+            assert_eq!(parsed.ignored.len(), 1, "{}", text);
+
+            let mut expected = link("a.pcd");
+            match key {
+                "cam" => expected.camera = None,
+                "space" => expected.normalized = None,
+                "mode" => expected.color_mode = None,
+                _ => expected.infinite_far = None,
+            }
+            expected.ignored = parsed.ignored.clone();
+            assert_eq!(parsed, expected, "{}", text);
+        }
+    }
+
+    #[test]
+    fn notes_unknown_and_keyless_components() {
+        let parsed = ShareLink::parse("pcviz://open?file=a.pcd&zoom=2&junk&&").unwrap();
+        assert_eq!(parsed.file, PathBuf::from("a.pcd"));
+        assert_eq!(parsed.ignored.len(), 2);
+    }
+
+    #[test]
+    fn scheme_registration_opens_links_with_the_executable() {
+        let values =
+            scheme_registry_values(Path::new(r"C:\Program Files\pcvisualizer\pcvisualizer.exe"));
+
+        assert_eq!(
+            values,
+            [
+                (
+                    r"HKCU\Software\Classes\pcviz".to_string(),
+                    "",
+                    "URL:pcvisualizer share link".to_string()
+                ),
+                (
+                    r"HKCU\Software\Classes\pcviz".to_string(),
+                    "URL Protocol",
+                    String::new()
+                ),
+                (
+                    r"HKCU\Software\Classes\pcviz\shell\open\command".to_string(),
+                    "",
+                    r#""C:\Program Files\pcvisualizer\pcvisualizer.exe" "%1""#.to_string()
+                ),
+            ]
+        );
+        assert!(ShareLink::PREFIX.starts_with("pcviz:"));
+    }
+}
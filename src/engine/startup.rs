@@ -0,0 +1,190 @@
+use std::{
+    fmt,
+    time::{Duration, Instant},
+};
+
+/// The steps between process start and the first presented frame, in the
+/// order they run. Files given at startup load in the background and show
+/// their own progress, so they are not a stage.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Stage {
+    EventLoop,
+    Window,
+    Surface,
+    Adapter,
+    Device,
+    SurfaceConfiguration,
+    Egui,
+    PointPipeline,
+    FirstFrame,
+}
+
+impl Stage {
+    pub fn name(self) -> &'static str {
+        match self {
+            Stage::EventLoop => "event loop",
+            Stage::Window => "window",
+            Stage::Surface => "instance and surface",
+            Stage::Adapter => "adapter",
+            Stage::Device => "device",
+            Stage::SurfaceConfiguration => "surface configuration",
+            Stage::Egui => "egui",
+            Stage::PointPipeline => "point pipeline",
+            Stage::FirstFrame => "first frame",
+        }
+    }
+}
+
+/// Wall time spent in each stage between process start and the first
+/// presented frame.
+pub struct StartupReport {
+    start: Instant,
+    last: Instant,
+    stages: Vec<(Stage, Duration)>,
+}
+
+impl StartupReport {
+    pub fn new() -> Self {
+        let now = Instant::now();
+
+        Self {
+            start: now,
+            last: now,
+            stages: Vec::new(),
+        }
+    }
+
+    /// Closes the stage that has been running since the previous call.
+    pub fn stage(&mut self, stage: Stage) {
+        debug_assert!(
+            self.stages.last().is_none_or(|(last, _)| *last < stage),
+            "startup stage {:?} recorded out of order",
+            stage
+        );
+
+        let now = Instant::now();
+        self.stages.push((stage, now - self.last));
+        self.last = now;
+    }
+
+    pub fn total(&self) -> Duration {
+        self.last - self.start
+    }
+
+    /// The stages recorded so far, in order.
+    pub fn stages(&self) -> &[(Stage, Duration)] {
+        &self.stages
+    }
+}
+
+impl fmt::Display for StartupReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "startup report")?;
+
+        for (stage, duration) in &self.stages {
+            writeln!(
+                f,
+                "  {:<24} {:>9.1} ms",
+                stage.name(),
+                duration.as_secs_f64() * 1e3
+            )?;
+        }
+
+        write!(
+            f,
+            "  {:<24} {:>9.1} ms",
+            "total",
+            self.total().as_secs_f64() * 1e3
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Every stage, in order.
+    const STAGES: [Stage; 9] = [
+        Stage::EventLoop,
+        Stage::Window,
+        Stage::Surface,
+        Stage::Adapter,
+        Stage::Device,
+        Stage::SurfaceConfiguration,
+        Stage::Egui,
+        Stage::PointPipeline,
+        Stage::FirstFrame,
+    ];
+
+    /// Scripts and dashboards read the report by these names.
+    #[test]
+    fn stages_keep_their_names_and_order() {
+        assert_eq!(
+            STAGES.map(Stage::name),
+            [
+                "event loop",
+                "window",
+                "instance and surface",
+                "adapter",
+                "device",
+                "surface configuration",
+                "egui",
+                "point pipeline",
+                "first frame",
+            ]
+        );
+        assert!(STAGES.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn report_lists_stages_as_recorded_then_the_total() {
+        let mut report = StartupReport::new();
+        for stage in STAGES {
+            report.stage(stage);
+        }
+
+        let text = report.to_string();
+        let names: Vec<_> = text
+            .lines()
+            .skip(1)
+            .map(|line| line[2..26].trim_end())
+            .collect();
+
+        assert_eq!(text.lines().next(), Some("startup report"));
+        assert_eq!(
+            names,
+            [
+                "event loop",
+                "window",
+                "instance and surface",
+                "adapter",
+                "device",
+                "surface configuration",
+                "egui",
+                "point pipeline",
+                "first frame",
+                "total",
+            ]
+        );
+
+        // The overlay lists the same stages, and they add up to the total.
+        assert_eq!(report.stages().len(), names.len() - 1);
+        assert_eq!(
+            report
+                .stages()
+                .iter()
+                .map(|(_, duration)| *duration)
+                .sum::<Duration>(),
+            report.total()
+        );
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "out of order")]
+    fn stages_recorded_out_of_order_are_caught() {
+        let mut report = StartupReport::new();
+        report.stage(Stage::Device);
+        report.stage(Stage::Adapter);
+    }
+}
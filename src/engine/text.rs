@@ -0,0 +1,165 @@
+use std::{io::BufRead, path::Path};
+
+use anyhow::{anyhow, bail, Result};
+
+use super::{
+    loader::{self, Progress},
+    pointcloud::Point,
+};
+
+#[derive(Clone, Copy)]
+enum Delimiter {
+    Comma,
+    Semicolon,
+    Whitespace,
+}
+
+impl Delimiter {
+    fn detect(line: &str) -> Self {
+        if line.contains(',') {
+            Delimiter::Comma
+        } else if line.contains(';') {
+            Delimiter::Semicolon
+        } else {
+            Delimiter::Whitespace
+        }
+    }
+
+    fn fields<'a>(self, line: &'a str) -> Box<dyn Iterator<Item = &'a str> + 'a> {
+        match self {
+            Delimiter::Comma => Box::new(line.split(',').map(str::trim)),
+            Delimiter::Semicolon => Box::new(line.split(';').map(str::trim)),
+            Delimiter::Whitespace => Box::new(line.split_whitespace()),
+        }
+    }
+}
+
+/// Reads text with one point per line: x, y, z and an optional intensity,
+/// separated by commas, semicolons or whitespace. Lines before the first row
+/// of points that do not start with a number are taken for a header and
+/// skipped, as are blank lines and `#` comments.
+pub fn read(path: &Path, progress: &Progress) -> Result<Vec<Point>> {
+    let mut reader = loader::open(path, progress)?;
+    let mut buffer = Vec::new();
+    let mut delimiter = None;
+    let mut points = Vec::new();
+
+    for number in 1.. {
+        buffer.clear();
+        if reader.read_until(b'\n', &mut buffer)? == 0 {
+            break;
+        }
+
+        let line = std::str::from_utf8(&buffer)
+            .map_err(|_| anyhow!("line {}: not valid UTF-8", number))?
+            .trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let row_delimiter = delimiter.unwrap_or_else(|| Delimiter::detect(line));
+        if delimiter.is_none() && !starts_with_number(row_delimiter, line) {
+            continue;
+        }
+        match parse_row(row_delimiter, line) {
+            Ok(point) => {
+                delimiter = Some(row_delimiter);
+                points.push(point);
+            }
+            Err(e) => bail!("line {}: {}", number, e),
+        }
+    }
+
+    Ok(points)
+}
+
+fn starts_with_number(delimiter: Delimiter, line: &str) -> bool {
+    delimiter
+        .fields(line)
+        .next()
+        .is_some_and(|field| field.parse::<f64>().is_ok())
+}
+
+fn parse_row(delimiter: Delimiter, line: &str) -> Result<Point> {
+    let mut fields = delimiter.fields(line);
+    let mut next = |name: &str| -> Result<Option<f64>> {
+        fields
+            .next()
+            .map(|field| {
+                field
+                    .parse::<f64>()
+                    .map_err(|_| anyhow!("invalid {} '{}'", name, field))
+            })
+            .transpose()
+    };
+
+    let mut coordinate = |name: &str| next(name)?.ok_or_else(|| anyhow!("missing {}", name));
+    let x = coordinate("x")?;
+    let y = coordinate("y")?;
+    let z = coordinate("z")?;
+    let intensity = next("intensity")?;
+
+    Ok(Point {
+        x,
+        y,
+        z,
+        intensity: intensity.unwrap_or(0.0) as f32,
+        rgb: None,
+        classification: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_fixture(contents: &[u8]) -> Result<Vec<Point>> {
+        read(
+            &loader::fixture("points.txt", contents),
+            &Progress::default(),
+        )
+    }
+
+    fn positions(points: &[Point]) -> Vec<[f64; 3]> {
+        points
+            .iter()
+            .map(|point| [point.x, point.y, point.z])
+            .collect()
+    }
+
+    #[test]
+    fn detects_the_delimiter() {
+        for contents in [
+            &b"1,2,3\n4, 5, 6, 7\n"[..],
+            b"1;2;3\n4; 5; 6; 7\n",
+            b"1 2 3\n4\t5   6 7\n",
+        ] {
+            let points = read_fixture(contents).unwrap();
+
+            assert_eq!(positions(&points), [[1.0, 2.0, 3.0], [4.0, 5.0, 6.0]]);
+            assert_eq!(points[1].intensity, 7.0);
+        }
+    }
+
+    #[test]
+    fn skips_headers_comments_and_blank_lines() {
+        let points =
+            read_fixture(b"# scan\nx,y,z,intensity\n\n1,2,3,0.5\n# more\n-4,5e1,6\n").unwrap();
+
+        assert_eq!(positions(&points), [[1.0, 2.0, 3.0], [-4.0, 50.0, 6.0]]);
+        assert_eq!(points[0].intensity, 0.5);
+    }
+
+    #[test]
+    fn malformed_rows_fail_with_their_line_number() {
+        let error = read_fixture(b"x y z\n1 2\n").err().unwrap();
+        assert_eq!(error.to_string(), "line 2: missing z");
+
+        let error = read_fixture(b"1 2 3\n4 five 6\n").err().unwrap();
+        assert_eq!(error.to_string(), "line 2: invalid y 'five'");
+
+        // Not a header just because no row has parsed yet.
+        let error = read_fixture(b"1,2,z\n3,4,w\n").err().unwrap();
+        assert_eq!(error.to_string(), "line 1: invalid z 'z'");
+    }
+}
@@ -1,7 +1,8 @@
 use wgpu::{
-    AddressMode, CompareFunction, Device, Extent3d, FilterMode, SamplerDescriptor,
+    AddressMode, CommandEncoder, CompareFunction, Device, Extent3d, FilterMode, LoadOp, Operations,
+    RenderPassColorAttachment, RenderPassDescriptor, SamplerDescriptor, StoreOp,
     SurfaceConfiguration, TextureDescriptor, TextureDimension, TextureFormat, TextureUsages,
-    TextureViewDescriptor,
+    TextureView, TextureViewDescriptor,
 };
 
 pub struct Texture {
@@ -13,9 +14,31 @@ pub struct Texture {
 impl Texture {
     pub const DEPTH_FORMAT: TextureFormat = TextureFormat::Depth32Float;
 
+    /// Depth test for the chosen convention. Reversed-Z maps the near plane
+    /// to 1 and the far plane to 0, which spreads float precision evenly
+    /// over distance instead of piling it up at the near plane.
+    pub fn depth_compare(reversed_z: bool) -> CompareFunction {
+        if reversed_z {
+            CompareFunction::Greater
+        } else {
+            CompareFunction::Less
+        }
+    }
+
+    /// Depth of an empty pixel, i.e. the far plane, for the chosen convention.
+    pub fn depth_clear_value(reversed_z: bool) -> f32 {
+        if reversed_z {
+            0.0
+        } else {
+            1.0
+        }
+    }
+
+    /// `sample_count` must match that of the color target it is drawn with.
     pub fn create_depth_texture(
         device: &Device,
         config: &SurfaceConfiguration,
+        sample_count: u32,
         label: &str,
     ) -> Self {
         let size = Extent3d {
@@ -28,7 +51,7 @@ impl Texture {
             label: Some(label),
             size,
             mip_level_count: 1,
-            sample_count: 1,
+            sample_count,
             dimension: TextureDimension::D2,
             format: Self::DEPTH_FORMAT,
             usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
@@ -59,6 +82,65 @@ impl Texture {
         }
     }
 
+    /// A surface-sized color target the shaders can also read back.
+    pub fn create_color_target(
+        device: &Device,
+        config: &SurfaceConfiguration,
+        sample_count: u32,
+        label: &str,
+    ) -> Self {
+        let texture = device.create_texture(&TextureDescriptor {
+            label: Some(label),
+            size: Extent3d {
+                width: config.width,
+                height: config.height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count,
+            dimension: TextureDimension::D2,
+            format: config.format,
+            usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+
+        let view = texture.create_view(&TextureViewDescriptor::default());
+
+        let sampler = device.create_sampler(&SamplerDescriptor {
+            address_mode_u: AddressMode::ClampToEdge,
+            address_mode_v: AddressMode::ClampToEdge,
+            address_mode_w: AddressMode::ClampToEdge,
+            mag_filter: FilterMode::Nearest,
+            min_filter: FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        Self {
+            texture,
+            view,
+            sampler,
+        }
+    }
+
+    /// Resolves the multisampled `source` into `target` with an otherwise
+    /// empty pass.
+    pub fn resolve(encoder: &mut CommandEncoder, source: &TextureView, target: &TextureView) {
+        let _ = encoder.begin_render_pass(&RenderPassDescriptor {
+            label: Some("resolve_render_pass"),
+            color_attachments: &[Some(RenderPassColorAttachment {
+                view: source,
+                resolve_target: Some(target),
+                ops: Operations {
+                    load: LoadOp::Load,
+                    store: StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+    }
+
     pub fn texture(&self) -> &wgpu::Texture {
         &self.texture
     }
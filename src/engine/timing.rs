@@ -0,0 +1,68 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+/// Rolling window of recent frames: how long each took from the start of
+/// rendering through present, and how far apart frames started.
+pub struct FrameTimes {
+    durations: VecDeque<Duration>,
+    intervals: VecDeque<Duration>,
+    frame_start: Option<Instant>,
+}
+
+impl FrameTimes {
+    const CAPACITY: usize = 120;
+
+    pub fn new() -> Self {
+        Self {
+            durations: VecDeque::with_capacity(Self::CAPACITY),
+            intervals: VecDeque::with_capacity(Self::CAPACITY),
+            frame_start: None,
+        }
+    }
+
+    pub fn begin_frame(&mut self) {
+        let now = Instant::now();
+
+        if let Some(previous) = self.frame_start.replace(now) {
+            push_bounded(&mut self.intervals, now - previous);
+        }
+    }
+
+    pub fn end_frame(&mut self) {
+        if let Some(start) = self.frame_start {
+            push_bounded(&mut self.durations, start.elapsed());
+        }
+    }
+
+    /// Frames per second over the window, from the spacing of frame starts.
+    pub fn fps(&self) -> f32 {
+        let total: Duration = self.intervals.iter().sum();
+
+        if total.is_zero() {
+            0.0
+        } else {
+            self.intervals.len() as f32 / total.as_secs_f32()
+        }
+    }
+
+    pub fn average(&self) -> Duration {
+        if self.durations.is_empty() {
+            return Duration::ZERO;
+        }
+
+        self.durations.iter().sum::<Duration>() / self.durations.len() as u32
+    }
+
+    pub fn durations(&self) -> impl Iterator<Item = Duration> + '_ {
+        self.durations.iter().copied()
+    }
+}
+
+fn push_bounded(window: &mut VecDeque<Duration>, value: Duration) {
+    if window.len() == FrameTimes::CAPACITY {
+        window.pop_front();
+    }
+    window.push_back(value);
+}
@@ -0,0 +1,140 @@
+/// Which file axis points up. The viewer is Z-up.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UpAxis {
+    Y,
+    Z,
+}
+
+/// What is known about a file's orientation once it has been read.
+#[derive(Debug, Default)]
+pub struct Hints<'a> {
+    /// The format puts elevation in z by definition, as LAS does.
+    pub georeferenced: bool,
+    /// Header comments, from a PLY file.
+    pub comments: &'a [String],
+    /// Size of the bounding box along x, y and z.
+    pub extent: [f64; 3],
+}
+
+/// Guesses the up axis of a file, or `None` when nothing points either way.
+///
+/// The format and comments naming an up axis are trusted first. Otherwise a
+/// scan is taken to be much wider than it is tall, so the axis along which
+/// the bounding box is clearly thinnest is up; photogrammetry exports lying
+/// flat in x and z are the case this catches.
+pub fn guess(hints: &Hints) -> Option<UpAxis> {
+    // How much thinner than both other axes the up axis has to be.
+    const FLATNESS: f64 = 0.5;
+
+    if hints.georeferenced {
+        return Some(UpAxis::Z);
+    }
+    if let Some(axis) = hints
+        .comments
+        .iter()
+        .find_map(|comment| from_comment(comment))
+    {
+        return Some(axis);
+    }
+
+    let [x, y, z] = hints.extent;
+    if ![x, y, z]
+        .iter()
+        .all(|extent| extent.is_finite() && *extent > 0.0)
+    {
+        return None;
+    }
+    if y < FLATNESS * x.min(z) {
+        Some(UpAxis::Y)
+    } else if z < FLATNESS * x.min(y) {
+        Some(UpAxis::Z)
+    } else {
+        None
+    }
+}
+
+/// The axis a comment such as `up_axis Y`, `Y-up` or `up: z` names.
+fn from_comment(comment: &str) -> Option<UpAxis> {
+    let words: Vec<String> = comment
+        .split(|c: char| c.is_whitespace() || matches!(c, ':' | '=' | ','))
+        .filter(|word| !word.is_empty())
+        .map(|word| word.to_ascii_lowercase().replace('-', "_"))
+        .collect();
+
+    let axis = |name: &str| match name {
+        "y" => Some(UpAxis::Y),
+        "z" => Some(UpAxis::Z),
+        _ => None,
+    };
+
+    words
+        .iter()
+        .enumerate()
+        .find_map(|(index, word)| match word.as_str() {
+            "y_up" | "yup" => Some(UpAxis::Y),
+            "z_up" | "zup" => Some(UpAxis::Z),
+            "up" | "up_axis" | "upaxis" => words.get(index + 1).and_then(|next| axis(next)),
+            _ => None,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn extent(extent: [f64; 3]) -> Hints<'static> {
+        Hints {
+            extent,
+            ..Hints::default()
+        }
+    }
+
+    #[test]
+    fn flat_boxes_are_up_along_their_thin_axis() {
+        assert_eq!(guess(&extent([40.0, 3.0, 25.0])), Some(UpAxis::Y));
+        assert_eq!(guess(&extent([40.0, 25.0, 3.0])), Some(UpAxis::Z));
+        // A room or an object is no wider than it is tall.
+        assert_eq!(guess(&extent([4.0, 3.0, 2.5])), None);
+        // Thin along x says nothing about y or z.
+        assert_eq!(guess(&extent([1.0, 30.0, 25.0])), None);
+        assert_eq!(guess(&extent([40.0, 0.0, 25.0])), None);
+        assert_eq!(guess(&extent([f64::NAN, 3.0, 25.0])), None);
+    }
+
+    #[test]
+    fn metadata_wins_over_the_bounding_box() {
+        let comments = [
+            "generated by a scanner".to_string(),
+            "up_axis: Y".to_string(),
+        ];
+        let hints = Hints {
+            comments: &comments,
+            extent: [40.0, 25.0, 3.0],
+            ..Hints::default()
+        };
+        assert_eq!(guess(&hints), Some(UpAxis::Y));
+
+        let hints = Hints {
+            georeferenced: true,
+            extent: [40.0, 3.0, 25.0],
+            ..Hints::default()
+        };
+        assert_eq!(guess(&hints), Some(UpAxis::Z));
+    }
+
+    #[test]
+    fn comments_name_the_up_axis_in_several_spellings() {
+        for (comment, axis) in [
+            ("up_axis Y", Some(UpAxis::Y)),
+            ("UpAxis=z", Some(UpAxis::Z)),
+            ("exported Y-up", Some(UpAxis::Y)),
+            ("coordinate system: Z_UP", Some(UpAxis::Z)),
+            ("up: y", Some(UpAxis::Y)),
+            ("made by hand", None),
+            ("up to date", None),
+            ("y", None),
+        ] {
+            assert_eq!(from_comment(comment), axis, "{}", comment);
+        }
+    }
+}
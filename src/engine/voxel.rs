@@ -0,0 +1,170 @@
+use std::collections::HashMap;
+
+use super::pointcloud::Point;
+
+/// Running sums of the points that fell into one voxel.
+#[derive(Default)]
+struct Voxel {
+    count: usize,
+    position: [f64; 3],
+    intensity: f64,
+    /// Red, green and blue sums over the points that have a color.
+    rgb: [u64; 3],
+    colored: u64,
+    /// Of the first point; classes cannot be averaged.
+    classification: Option<u8>,
+}
+
+impl Voxel {
+    fn add(&mut self, point: &Point) {
+        if self.count == 0 {
+            self.classification = point.classification;
+        }
+        self.count += 1;
+        self.position[0] += point.x;
+        self.position[1] += point.y;
+        self.position[2] += point.z;
+        self.intensity += point.intensity as f64;
+
+        if let Some(rgb) = point.rgb {
+            for (channel, shift) in [16, 8, 0].into_iter().enumerate() {
+                self.rgb[channel] += ((rgb >> shift) & 0xFF) as u64;
+            }
+            self.colored += 1;
+        }
+    }
+
+    /// The centroid, with intensity and color averaged.
+    fn point(&self) -> Point {
+        let count = self.count as f64;
+        let [x, y, z] = self.position.map(|sum| sum / count);
+
+        Point {
+            x,
+            y,
+            z,
+            intensity: (self.intensity / count) as f32,
+            rgb: (self.colored > 0).then(|| {
+                let [r, g, b] = self.rgb.map(|sum| (sum / self.colored) as u32);
+                (r << 16) | (g << 8) | b
+            }),
+            classification: self.classification,
+        }
+    }
+}
+
+/// Replaces the points in each cube of `leaf_size` by their centroid, in one
+/// pass over a hash map keyed on the cube. Voxels come out in the order their
+/// first point was read.
+pub fn downsample(points: &[Point], leaf_size: f64) -> Vec<Point> {
+    let mut indices: HashMap<[i64; 3], usize> = HashMap::with_capacity(points.len() / 8);
+    let mut voxels: Vec<Voxel> = Vec::new();
+
+    for point in points {
+        let key = [point.x, point.y, point.z].map(|v| (v / leaf_size).floor() as i64);
+        let index = *indices.entry(key).or_insert_with(|| {
+            voxels.push(Voxel::default());
+            voxels.len() - 1
+        });
+        voxels[index].add(point);
+    }
+
+    voxels.iter().map(Voxel::point).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(x: f64, y: f64, z: f64, intensity: f32, rgb: Option<u32>) -> Point {
+        Point {
+            x,
+            y,
+            z,
+            intensity,
+            rgb,
+            classification: None,
+        }
+    }
+
+    #[test]
+    fn merges_points_in_the_same_voxel_into_their_centroid() {
+        let points = [
+            point(0.1, 0.1, 0.1, 1.0, Some(0x00_10_20_30)),
+            point(0.3, 0.5, 0.9, 3.0, Some(0x00_30_40_50)),
+            point(0.2, 0.9, 0.2, 2.0, None),
+        ];
+
+        let voxels = downsample(&points, 1.0);
+
+        assert_eq!(voxels.len(), 1);
+        let voxel = &voxels[0];
+        assert!((voxel.x - 0.2).abs() < 1e-12);
+        assert!((voxel.y - 0.5).abs() < 1e-12);
+        assert!((voxel.z - 0.4).abs() < 1e-12);
+        assert_eq!(voxel.intensity, 2.0);
+        // Color is averaged over the colored points only.
+        assert_eq!(voxel.rgb, Some(0x00_20_30_40));
+    }
+
+    #[test]
+    fn splits_at_voxel_boundaries_including_below_zero() {
+        let points = [
+            point(0.9, 0.0, 0.0, 0.0, None),
+            point(1.1, 0.0, 0.0, 0.0, None),
+            // Floors to -1, not 0, so it does not share the first voxel.
+            point(-0.1, 0.0, 0.0, 0.0, None),
+            point(1.9, 0.5, 0.5, 0.0, None),
+        ];
+
+        let voxels = downsample(&points, 1.0);
+        let xs: Vec<_> = voxels.iter().map(|voxel| voxel.x).collect();
+
+        // In the order each voxel's first point was read.
+        assert_eq!(xs.len(), 3);
+        assert!((xs[0] - 0.9).abs() < 1e-12);
+        assert!((xs[1] - 1.5).abs() < 1e-12);
+        assert!((xs[2] + 0.1).abs() < 1e-12);
+    }
+
+    #[test]
+    fn voxels_without_colored_points_have_no_color() {
+        let points = [
+            point(0.0, 0.0, 0.0, 0.0, None),
+            point(5.0, 0.0, 0.0, 0.0, Some(0x00_FF_00_00)),
+        ];
+
+        let voxels = downsample(&points, 0.5);
+
+        assert_eq!(voxels[0].rgb, None);
+        assert_eq!(voxels[1].rgb, Some(0x00_FF_00_00));
+    }
+
+    #[test]
+    fn a_uniform_grid_downsamples_to_one_point_per_occupied_voxel() {
+        const N: usize = 8;
+        // Cell centres, so no point sits on a voxel boundary.
+        let points: Vec<Point> = (0..N * N * N)
+            .map(|index| {
+                let [x, y, z] = [index % N, index / N % N, index / (N * N)];
+                point(x as f64 + 0.5, y as f64 + 0.5, z as f64 + 0.5, 0.0, None)
+            })
+            .collect();
+
+        for (leaf_size, per_axis) in [(1.0, 8), (2.0, 4), (3.0, 3), (4.0, 2), (8.0, 1)] {
+            let voxels = downsample(&points, leaf_size);
+            assert_eq!(
+                voxels.len(),
+                per_axis * per_axis * per_axis,
+                "{}",
+                leaf_size
+            );
+        }
+
+        // A voxel per point leaves every point where it was.
+        let voxels = downsample(&points, 1.0);
+        for (voxel, point) in voxels.iter().zip(&points) {
+            assert_eq!([voxel.x, voxel.y, voxel.z], [point.x, point.y, point.z]);
+        }
+    }
+}
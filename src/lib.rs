@@ -2,38 +2,90 @@ mod engine;
 
 use std::path::PathBuf;
 
-use engine::Engine;
+pub use engine::{parse_camera, register_scheme, AdapterOptions, Backend, ColorMode, PresentMode};
+use engine::{
+    Engine, LoadJob, LoadKind, LoadOptions, Overrides, Settings, ShareLink, Stage, StartupReport,
+};
 use wgpu::SurfaceError;
 use winit::{
     application::ApplicationHandler,
     dpi::LogicalSize,
     event::WindowEvent,
-    event_loop::{ActiveEventLoop, EventLoop},
+    event_loop::{ActiveEventLoop, ControlFlow, EventLoop},
     window::{Window, WindowId},
 };
 
 #[derive(Default)]
 struct App {
     engine: Option<Engine>,
-    pcd_path: Option<PathBuf>,
+    share_link: Option<ShareLink>,
+    /// Reported once the engine exists to show it.
+    startup_error: Option<String>,
+    /// The files given on the command line, read while the window and
+    /// device are created.
+    initial_load: Option<LoadJob>,
+    startup: Option<StartupReport>,
+    print_startup_report: bool,
+    load_options: LoadOptions,
+    msaa_samples: Option<u32>,
+    present_mode: Option<PresentMode>,
+    adapter_options: AdapterOptions,
+    overrides: Overrides,
+    camera: Option<([f32; 3], [f32; 3])>,
 }
 
 impl ApplicationHandler for App {
     fn resumed(&mut self, event_loop: &ActiveEventLoop) {
+        let settings = Settings::load();
+
         let window_attrs = Window::default_attributes()
             .with_title("pcvisualizer")
-            .with_inner_size(LogicalSize::new(1280, 720));
+            .with_inner_size(LogicalSize::new(
+                settings.window.width.max(1),
+                settings.window.height.max(1),
+            ))
+            .with_maximized(settings.window.maximized);
+
+        let mut fallback_report = StartupReport::new();
+        let startup = self.startup.as_mut().unwrap_or(&mut fallback_report);
 
         let window = event_loop.create_window(window_attrs).unwrap();
-        self.engine = Some(Engine::new(window));
+        startup.stage(Stage::Window);
+
+        self.engine = Some(Engine::new(
+            window,
+            settings,
+            &self.adapter_options,
+            startup,
+        ));
 
         let Some(ref mut engine) = self.engine else {
             return;
         };
+        engine.set_load_options(self.load_options);
+        engine.override_settings(self.overrides);
+        if let Some(samples) = self.msaa_samples {
+            engine.set_msaa_samples(samples);
+        }
+        if let Some(mode) = self.present_mode {
+            engine.set_present_mode(mode);
+        }
 
-        match &self.pcd_path {
-            Some(path) => engine.set_pcd(&path),
-            None => (),
+        // The engine uploads the files as they finish, with progress shown,
+        // and only then restores the view, which needs their bounds.
+        if let Some(job) = self.initial_load.take() {
+            let mut link = self.share_link.clone();
+            if let Some(camera) = self.camera {
+                link.get_or_insert_with(|| ShareLink::new(job.path().to_path_buf()))
+                    .camera = Some(camera);
+            }
+            engine.adopt_load(job, link);
+        } else if let Some((eye, target)) = self.camera {
+            engine.look_at(eye, target);
+        }
+
+        if let Some(message) = self.startup_error.take() {
+            engine.notify_error(message);
         }
     }
 
@@ -56,7 +108,10 @@ impl ApplicationHandler for App {
         }
 
         match event {
-            WindowEvent::CloseRequested => event_loop.exit(),
+            WindowEvent::CloseRequested => {
+                engine.save_settings();
+                event_loop.exit();
+            }
 
             WindowEvent::Resized(physical_size) => {
                 engine.resize(physical_size);
@@ -65,30 +120,146 @@ impl ApplicationHandler for App {
             WindowEvent::RedrawRequested => {
                 engine.update();
                 match engine.render() {
-                    Ok(_) => {}
+                    Ok(_) => {
+                        if let Some(mut startup) = self.startup.take() {
+                            startup.stage(Stage::FirstFrame);
+                            log::info!("{}", startup);
+                            if self.print_startup_report {
+                                println!("{}", startup);
+                            }
+                            engine.set_startup_report(startup);
+                        }
+                    }
                     Err(SurfaceError::Lost | SurfaceError::Outdated) => {
                         engine.resize(engine.size())
                     }
-                    Err(SurfaceError::OutOfMemory | SurfaceError::Timeout) => event_loop.exit(),
+                    // Already reported by the engine; try again next frame.
+                    Err(SurfaceError::Timeout) => {}
+                    Err(SurfaceError::OutOfMemory) => event_loop.exit(),
                 }
             }
             _ => {}
         }
     }
 
-    fn about_to_wait(&mut self, _event_loop: &ActiveEventLoop) {
+    fn about_to_wait(&mut self, event_loop: &ActiveEventLoop) {
         let Some(ref mut engine) = self.engine else {
             return;
         };
 
-        engine.window().request_redraw();
+        // Frames are drawn when the engine asks for one; in between, sleep
+        // until the next event or the next timer it has running.
+        event_loop.set_control_flow(match engine.poll() {
+            Some(deadline) => ControlFlow::WaitUntil(deadline),
+            None => ControlFlow::Wait,
+        });
     }
 }
 
+/// What to open and how, as given on the command line. Settings left unset
+/// keep their saved values; those given are not saved unless changed in the
+/// viewer.
+#[derive(Clone, Debug, Default)]
+pub struct Config {
+    /// Point cloud files to open side by side, or a single share link.
+    pub files: Vec<PathBuf>,
+    /// Print the startup timing report to stdout once the first frame has
+    /// been presented.
+    pub print_startup_report: bool,
+    /// Downsample loaded files to one point per voxel of this size.
+    pub voxel_size: Option<f64>,
+    /// MSAA sample count to start with; the most the adapter supports up to
+    /// it is used.
+    pub msaa_samples: Option<u32>,
+    /// Present mode to start with; vsync if unset or unsupported.
+    pub present_mode: Option<PresentMode>,
+    /// Backend and GPU to render with.
+    pub adapter: AdapterOptions,
+    /// In pixels.
+    pub point_size: Option<f32>,
+    pub color_mode: Option<ColorMode>,
+    /// sRGB.
+    pub background: Option<[u8; 3]>,
+    /// Eye and target to look from once the file is shown, in place of
+    /// framing the cloud.
+    pub camera: Option<([f32; 3], [f32; 3])>,
+}
+
 pub fn run(pcd_path: Option<PathBuf>) {
+    run_with_config(Config {
+        files: pcd_path.into_iter().collect(),
+        ..Config::default()
+    });
+}
+
+/// The GPUs `options` could pick from, described one per line in the order
+/// [`AdapterOptions::adapter`] indexes them.
+pub fn list_adapters(options: &AdapterOptions) -> Vec<String> {
+    engine::list_adapters(options)
+        .iter()
+        .map(engine::describe_adapter)
+        .collect()
+}
+
+pub fn run_with_config(config: Config) {
+    let load_options = LoadOptions {
+        voxel_size: config.voxel_size,
+        ..LoadOptions::default()
+    };
+
+    let mut startup = StartupReport::new();
+
     env_logger::init();
+
+    // A share link in place of a path opens its file and then restores the
+    // view it describes.
+    let mut share_link = None;
+    let mut startup_error = None;
+    let mut files = config.files;
+    let link = match &files[..] {
+        [arg] => arg
+            .to_str()
+            .filter(|text| ShareLink::is_link(text))
+            .map(ShareLink::parse),
+        _ => None,
+    };
+    match link {
+        Some(Ok(link)) => {
+            files = vec![link.file.clone()];
+            share_link = Some(link);
+        }
+        Some(Err(e)) => {
+            startup_error = Some(format!("{:#}", e));
+            files.clear();
+        }
+        None => {}
+    }
+
+    // Parse the initial files while the window, device and pipelines are
+    // being created; only the GPU upload has to wait for the device.
+    let initial_load =
+        (!files.is_empty()).then(|| Engine::spawn_load(files, LoadKind::Open, load_options));
+
     let event_loop = EventLoop::new().unwrap();
-    let mut app = App::default();
-    app.pcd_path = pcd_path;
+    startup.stage(Stage::EventLoop);
+
+    let mut app = App {
+        share_link,
+        startup_error,
+        initial_load,
+        startup: Some(startup),
+        print_startup_report: config.print_startup_report,
+        load_options,
+        msaa_samples: config.msaa_samples,
+        present_mode: config.present_mode,
+        adapter_options: config.adapter,
+        overrides: Overrides {
+            point_size: config.point_size,
+            color_mode: config.color_mode,
+            background_color: config.background,
+        },
+        camera: config.camera,
+        ..Default::default()
+    };
     let _ = event_loop.run_app(&mut app);
 }
@@ -1,17 +1,178 @@
 #![windows_subsystem = "windows"]
 
-use std::{env, path::PathBuf};
+use std::path::PathBuf;
 
-use pcvisualizer::run;
+use clap::{builder::PossibleValuesParser, builder::TypedValueParser, Parser};
+use pcvisualizer::{
+    list_adapters, parse_camera, register_scheme, run_with_config, AdapterOptions, Backend,
+    ColorMode, Config, PresentMode,
+};
+
+/// Point cloud viewer.
+#[derive(Parser)]
+#[command(version)]
+struct Cli {
+    /// Point cloud files to open together, or a share link.
+    files: Vec<PathBuf>,
+
+    /// Point size in pixels.
+    #[arg(long, value_name = "PIXELS", value_parser = positive::<f32>)]
+    point_size: Option<f32>,
+
+    /// How points are colored.
+    #[arg(
+        long,
+        value_name = "MODE",
+        value_parser = named(ColorMode::ALL.map(ColorMode::name), ColorMode::from_name)
+    )]
+    color_mode: Option<ColorMode>,
+
+    /// Background color as sRGB hex, e.g. 1e1e1e.
+    #[arg(long, value_name = "RRGGBB", value_parser = parse_color)]
+    background: Option<[u8; 3]>,
+
+    /// Look from the eye at the target once the file is shown.
+    #[arg(
+        long,
+        value_name = "EX,EY,EZ,TX,TY,TZ",
+        allow_hyphen_values = true,
+        value_parser = |value: &str| parse_camera(value).ok_or("expected six comma-separated numbers")
+    )]
+    camera: Option<([f32; 3], [f32; 3])>,
+
+    /// Downsample to one point per voxel of this size when loading.
+    #[arg(long, value_name = "SIZE", value_parser = positive::<f64>)]
+    voxel_size: Option<f64>,
+
+    /// MSAA sample count; the most the adapter supports up to it is used.
+    #[arg(
+        long,
+        value_name = "SAMPLES",
+        value_parser = PossibleValuesParser::new(["1", "2", "4"])
+            .map(|samples| samples.parse::<u32>().unwrap())
+    )]
+    msaa: Option<u32>,
+
+    /// How frames are handed to the display.
+    #[arg(
+        long,
+        value_name = "MODE",
+        value_parser = named(PresentMode::ALL.map(PresentMode::name), PresentMode::from_name)
+    )]
+    present_mode: Option<PresentMode>,
+
+    /// Only try this graphics API.
+    #[arg(
+        long,
+        value_parser = named(Backend::ALL.map(Backend::name), Backend::from_name)
+    )]
+    backend: Option<Backend>,
+
+    /// GPU to use, by index in --list-adapters or part of its name.
+    #[arg(long, value_name = "INDEX OR NAME")]
+    adapter: Option<String>,
+
+    /// Prefer a discrete GPU to an integrated one.
+    #[arg(long)]
+    high_performance: bool,
+
+    /// Print the available GPUs and exit.
+    #[arg(long)]
+    list_adapters: bool,
+
+    /// Print startup timings once the first frame is shown.
+    #[arg(long)]
+    startup_report: bool,
+
+    /// Make this executable open pcviz:// share links for the current user,
+    /// then exit. Windows only.
+    #[arg(long)]
+    register_scheme: bool,
+}
+
+/// Parses one of `names` into the value `from_name` gives for it, listing the
+/// names in the help and in errors.
+fn named<T: Clone + Send + Sync + 'static>(
+    names: impl IntoIterator<Item = &'static str>,
+    from_name: fn(&str) -> Option<T>,
+) -> impl TypedValueParser<Value = T> {
+    PossibleValuesParser::new(names).map(move |name| from_name(&name).unwrap())
+}
+
+fn positive<T: std::str::FromStr + PartialOrd + Default>(value: &str) -> Result<T, String> {
+    match value.parse::<T>() {
+        Ok(number) if number > T::default() => Ok(number),
+        _ => Err("expected a positive number".to_string()),
+    }
+}
+
+fn parse_color(value: &str) -> Result<[u8; 3], String> {
+    let hex = value.strip_prefix('#').unwrap_or(value);
+    match u32::from_str_radix(hex, 16) {
+        Ok(rgb) if hex.len() == 6 => Ok([(rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8]),
+        _ => Err("expected six hex digits".to_string()),
+    }
+}
+
+/// The window subsystem gives the process no console, so `--help`, argument
+/// errors and the adapter list would go nowhere. Writes to the console of
+/// the shell it was started from instead; from Explorer there is none and
+/// nothing changes.
+fn attach_parent_console() {
+    #[cfg(windows)]
+    {
+        const ATTACH_PARENT_PROCESS: u32 = u32::MAX;
+
+        #[link(name = "kernel32")]
+        extern "system" {
+            fn AttachConsole(process_id: u32) -> i32;
+        }
+
+        // Failing only means there is no console to attach to.
+        unsafe {
+            AttachConsole(ATTACH_PARENT_PROCESS);
+        }
+    }
+}
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
+    attach_parent_console();
+    let cli = Cli::parse();
 
-    let pcd_path = if args.len() > 1 {
-        Some(PathBuf::from(args[1].clone()))
-    } else {
-        None
+    let adapter = AdapterOptions {
+        backend: cli.backend,
+        adapter: cli.adapter,
+        high_performance: cli.high_performance,
     };
 
-    run(pcd_path);
+    if cli.register_scheme {
+        match register_scheme() {
+            Ok(()) => println!("pcviz:// links now open in this viewer"),
+            Err(e) => {
+                eprintln!("error: {:#}", e);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    if cli.list_adapters {
+        for (index, adapter) in list_adapters(&adapter).iter().enumerate() {
+            println!("{}: {}", index, adapter);
+        }
+        return;
+    }
+
+    run_with_config(Config {
+        files: cli.files,
+        print_startup_report: cli.startup_report,
+        voxel_size: cli.voxel_size,
+        msaa_samples: cli.msaa,
+        present_mode: cli.present_mode,
+        adapter,
+        point_size: cli.point_size,
+        color_mode: cli.color_mode,
+        background: cli.background,
+        camera: cli.camera,
+    });
 }